@@ -1,7 +1,7 @@
 #version 130
-in vec4 dbg_color;
+in vec4 cell_color;
 out vec4 color;
 
 void main() {
-    color = vec4(0.85, 0.85, 0.85, 1);
+    color = cell_color;
 }
@@ -1,23 +1,39 @@
 #version 130
 in vec2 pos;
-out vec4 dbg_color;
+in float state;
+out vec4 cell_color;
 // 1: Default zoom level, 10: 10x larger cells, 0.1: 1/10th as large
 uniform float zoom;
 uniform ivec2 viewport;
+// Number of cell states (2 for normal rules, more for Generations rules)
+uniform int states;
+// Colours of the states of automata with a fixed palette (unused if empty)
+#define MAX_PALETTE 16
+uniform vec3 palette[MAX_PALETTE];
+uniform int palette_size;
 
 #define DEFAULT_WIDTH_IN_PIXELS 600.0
 #define DEFAULT_HEIGHT_IN_PIXELS 600.0
 #define DEFAULT_WIDTH_IN_CELLS 20.0
 #define DEFAULT_HEIGHT_IN_CELLS 20.0
 
+#define ALIVE_COLOR vec3(0.85, 0.85, 0.85)
+#define DYING_COLOR vec3(0.85, 0.45, 0.15)
+#define BACKGROUND_COLOR vec3(0.2, 0.2, 0.2)
+
 void main() {
     float scale_x = DEFAULT_WIDTH_IN_PIXELS / viewport.x;
     float scale_y = DEFAULT_HEIGHT_IN_PIXELS / viewport.y;
     gl_Position = vec4(pos.x / DEFAULT_WIDTH_IN_CELLS * scale_x,
                        pos.y / DEFAULT_HEIGHT_IN_CELLS * scale_y,
                        0, zoom);
-    dbg_color = vec4(pos.x,
-                     pos.y,
-                     0, 1);
-    // dbg_color = vec4(scale_x, scale_y, 0, 1);
+    if (palette_size > 0) {
+        cell_color = vec4(palette[min(int(state), palette_size - 1)], 1);
+    } else if (state <= 1.0) {
+        cell_color = vec4(ALIVE_COLOR, 1);
+    } else {
+        // Dying cells fade towards the background
+        float age = (state - 2.0) / max(float(states - 2), 1.0);
+        cell_color = vec4(mix(DYING_COLOR, BACKGROUND_COLOR, age * 0.75), 1);
+    }
 }
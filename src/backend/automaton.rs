@@ -0,0 +1,451 @@
+//! Cellular automata with fixed rules that aren't Life-like.
+
+use std::collections::BTreeSet;
+
+use super::Point;
+use super::data::{CellMap, QTree, State};
+use super::topology::Topology;
+
+/// Offsets of the eight cells in the Moore neighbourhood of a cell.
+pub const NEIGHBOURS: [Point; 8] = [
+    (-1,  1), (0,  1), (1,  1),
+    (-1,  0),          (1,  0),
+    (-1, -1), (0, -1), (1, -1),
+];
+
+/// An RGB colour with components between 0 and 1.
+pub type Colour = [f32; 3];
+
+/// A cellular automaton with a fixed rule.
+///
+/// State 0 is the background, which is not stored in the pattern.
+pub trait Automaton: Send {
+    /// Get the name of the automaton.
+    fn name(&self) -> &'static str;
+
+    /// Get the number of states (including the background).
+    fn states(&self) -> State;
+
+    /// Get the colour of each state.
+    fn palette(&self) -> Vec<Colour>;
+
+    /// Compute the next generation of the `current` pattern.
+    fn next(&mut self, topology: &Topology, current: &QTree<State>)
+            -> QTree<State>;
+
+    /// Compute the previous generation of the `current` pattern, if the
+    /// automaton is reversible.
+    fn previous(&mut self, _topology: &Topology, _current: &QTree<State>)
+                -> Option<QTree<State>> {
+        None
+    }
+}
+
+/// A cellular automaton whose next states only depend on the Moore
+/// neighbourhood of each cell.
+///
+/// A cell in state 0 whose neighbours are all in state 0 must stay in
+/// state 0.
+pub trait Local {
+    /// Compute the next state of a cell in `state`, whose neighbours (in the
+    /// order of `NEIGHBOURS`) are in the states `neighbours`.
+    fn next_state(&self, state: State, neighbours: &[State; 8]) -> State;
+}
+
+/// Compute the next generation of a `Local` automaton.
+///
+/// Only cells that are not in the background state and their neighbours
+/// can change, so we only compute the next states of those.
+pub fn next_local<L: Local + ?Sized>(local: &L, topology: &Topology,
+                                     current: &QTree<State>) -> QTree<State> {
+    let neighbour = |point, offset| {
+        topology.neighbour(point, offset).map(|(neighbour, _)| neighbour)
+    };
+    let mut candidates = BTreeSet::new();
+    for point in current.points() {
+        let _ = candidates.insert(point);
+        candidates.extend(NEIGHBOURS.iter()
+                          .filter_map(|&offset| neighbour(point, offset)));
+    }
+
+    let mut next = QTree::<State>::empty(current.boundary());
+    for point in candidates {
+        let mut neighbours = [0; 8];
+        for (state, &offset) in neighbours.iter_mut().zip(NEIGHBOURS.iter()) {
+            *state = neighbour(point, offset)
+                .and_then(|neighbour| current.get(neighbour).cloned())
+                .unwrap_or(0);
+        }
+        let state = current.get(point).cloned().unwrap_or(0);
+        match local.next_state(state, &neighbours) {
+            0 => {}
+            state => next.set(point, state),
+        }
+    }
+    next
+}
+
+/// Wireworld, in which electrons travel along conductors.
+///
+/// Electron heads turn into tails, which turn back into conductors.
+/// Conductors become heads if one or two of their neighbours are heads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Wireworld;
+
+impl Wireworld {
+    /// The state of empty cells.
+    pub const EMPTY: State = 0;
+    /// The state of electron heads.
+    pub const HEAD: State = 1;
+    /// The state of electron tails.
+    pub const TAIL: State = 2;
+    /// The state of conductors.
+    pub const CONDUCTOR: State = 3;
+}
+
+impl Automaton for Wireworld {
+    fn name(&self) -> &'static str {
+        "Wireworld"
+    }
+
+    fn states(&self) -> State {
+        4
+    }
+
+    fn palette(&self) -> Vec<Colour> {
+        vec![
+            [0.0, 0.0, 0.0],
+            [0.2, 0.4, 1.0],
+            [1.0, 0.3, 0.1],
+            [1.0, 0.8, 0.0],
+        ]
+    }
+
+    fn next(&mut self, topology: &Topology, current: &QTree<State>)
+            -> QTree<State> {
+        next_local(self, topology, current)
+    }
+}
+
+impl Local for Wireworld {
+    fn next_state(&self, state: State, neighbours: &[State; 8]) -> State {
+        match state {
+            Wireworld::HEAD => Wireworld::TAIL,
+            Wireworld::TAIL => Wireworld::CONDUCTOR,
+            Wireworld::CONDUCTOR => {
+                let heads = neighbours.iter()
+                    .filter(|&&n| n == Wireworld::HEAD)
+                    .count();
+                if heads == 1 || heads == 2 {
+                    Wireworld::HEAD
+                } else {
+                    Wireworld::CONDUCTOR
+                }
+            }
+            _ => Wireworld::EMPTY,
+        }
+    }
+}
+
+/// Brian's Brain, in which cells fire, rest and then become ready again.
+///
+/// Ready cells fire if exactly two of their neighbours are firing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BriansBrain;
+
+impl BriansBrain {
+    /// The state of ready cells.
+    pub const READY: State = 0;
+    /// The state of firing cells.
+    pub const FIRING: State = 1;
+    /// The state of resting cells.
+    pub const RESTING: State = 2;
+}
+
+impl Automaton for BriansBrain {
+    fn name(&self) -> &'static str {
+        "Brian's Brain"
+    }
+
+    fn states(&self) -> State {
+        3
+    }
+
+    fn palette(&self) -> Vec<Colour> {
+        vec![
+            [0.0, 0.0, 0.0],
+            [0.95, 0.95, 0.95],
+            [0.2, 0.3, 0.9],
+        ]
+    }
+
+    fn next(&mut self, topology: &Topology, current: &QTree<State>)
+            -> QTree<State> {
+        next_local(self, topology, current)
+    }
+}
+
+impl Local for BriansBrain {
+    fn next_state(&self, state: State, neighbours: &[State; 8]) -> State {
+        match state {
+            BriansBrain::READY => {
+                let firing = neighbours.iter()
+                    .filter(|&&n| n == BriansBrain::FIRING)
+                    .count();
+                if firing == 2 {
+                    BriansBrain::FIRING
+                } else {
+                    BriansBrain::READY
+                }
+            }
+            BriansBrain::FIRING => BriansBrain::RESTING,
+            _ => BriansBrain::READY,
+        }
+    }
+}
+
+/// Langton's Ant, which walks across a grid of cells that are on or off.
+///
+/// On a cell that is off, the ant turns right, otherwise it turns left. It
+/// then toggles the cell and moves forward one cell. The ants are part of
+/// the state of the cell they are on: `ANT + 4 * colour + direction`, where
+/// the directions are north, east, south and west. If several ants enter
+/// the same cell, only one of them survives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LangtonsAnt;
+
+impl LangtonsAnt {
+    /// The state of cells that are off.
+    pub const OFF: State = 0;
+    /// The state of cells that are on.
+    pub const ON: State = 1;
+    /// The state of an ant facing north on a cell that is off.
+    pub const ANT: State = 2;
+
+    /// The indices of the neighbours that ants moving north, east, south and
+    /// west come from.
+    const ARRIVALS: [usize; 4] = [6, 3, 1, 4];
+
+    /// Split a `state` into the colour of the cell and the direction of the
+    /// ant on it (if any).
+    fn split(state: State) -> (State, Option<State>) {
+        if state < LangtonsAnt::ANT {
+            (state, None)
+        } else {
+            let ant = state - LangtonsAnt::ANT;
+            (ant / 4, Some(ant % 4))
+        }
+    }
+}
+
+impl Automaton for LangtonsAnt {
+    fn name(&self) -> &'static str {
+        "Langton's Ant"
+    }
+
+    fn states(&self) -> State {
+        LangtonsAnt::ANT + 8
+    }
+
+    fn palette(&self) -> Vec<Colour> {
+        let mut palette = vec![[0.0, 0.0, 0.0], [0.85, 0.85, 0.85]];
+        palette.extend(vec![[0.9, 0.1, 0.1]; 4]);
+        palette.extend(vec![[1.0, 0.5, 0.5]; 4]);
+        palette
+    }
+
+    fn next(&mut self, topology: &Topology, current: &QTree<State>)
+            -> QTree<State> {
+        next_local(self, topology, current)
+    }
+}
+
+impl Local for LangtonsAnt {
+    fn next_state(&self, state: State, neighbours: &[State; 8]) -> State {
+        let (colour, ant) = Self::split(state);
+        // Ants toggle the cell they leave
+        let colour = if ant.is_some() { 1 - colour } else { colour };
+        for (direction, &from) in Self::ARRIVALS.iter().enumerate() {
+            let (their_colour, their_ant) = Self::split(neighbours[from]);
+            let turned = match their_ant {
+                Some(ant) if their_colour == LangtonsAnt::OFF => (ant + 1) % 4,
+                Some(ant) => (ant + 3) % 4,
+                None => continue,
+            };
+            if turned == direction as State {
+                return LangtonsAnt::ANT + 4 * colour + turned
+            }
+        }
+        colour
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use backend::Coord;
+    use backend::data::AABB;
+
+    /// Create a pattern with the given `cells`.
+    fn grid(cells: &[(Point, State)]) -> QTree<State> {
+        let mut grid = QTree::<State>::empty(AABB::new((0, 0), 8));
+        for &(point, state) in cells {
+            grid.set(point, state);
+        }
+        grid
+    }
+
+    /// Compute `generations` generations of the cells in `grid`.
+    fn run<A: Automaton>(automaton: &mut A, topology: &Topology,
+                         grid: &QTree<State>, generations: usize)
+                         -> QTree<State> {
+        let mut grid = grid.map(|_, &state| state);
+        for _ in 0..generations {
+            grid = automaton.next(topology, &grid);
+        }
+        grid
+    }
+
+    /// Parse a Wireworld circuit (`#` are conductors, `H` heads and `t`
+    /// tails) with the top left cell at `(0, 0)`.
+    fn circuit(rows: &[&str]) -> QTree<State> {
+        let mut cells = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let state = match c {
+                    '#' => Wireworld::CONDUCTOR,
+                    'H' => Wireworld::HEAD,
+                    't' => Wireworld::TAIL,
+                    _ => continue,
+                };
+                cells.push(((x as Coord, -(y as Coord)), state));
+            }
+        }
+        grid(&cells)
+    }
+
+    /// Check if an electron head reaches `probe` within 20 generations.
+    fn arrives(grid: &QTree<State>, probe: Point) -> bool {
+        let topology = Topology::Unbounded;
+        (1..20).any(|generations| {
+            run(&mut Wireworld, &topology, grid, generations).get(probe) ==
+                Some(&Wireworld::HEAD)
+        })
+    }
+
+    #[test]
+    fn palettes() {
+        let automata: [&dyn Automaton; 3] = [&Wireworld, &BriansBrain,
+                                             &LangtonsAnt];
+        for automaton in automata.iter() {
+            assert_eq!(automaton.palette().len(),
+                       automaton.states() as usize);
+        }
+        let locals: [&dyn Local; 3] = [&Wireworld, &BriansBrain, &LangtonsAnt];
+        for local in locals.iter() {
+            assert_eq!(local.next_state(0, &[0; 8]), 0);
+        }
+    }
+
+    #[test]
+    fn wireworld() {
+        // Electrons travel along wires
+        let wire = circuit(&["tH#####"]);
+        let next = run(&mut Wireworld, &Topology::Unbounded, &wire, 3);
+        assert_eq!(next.get((4, 0)), Some(&Wireworld::HEAD));
+        assert_eq!(next.get((3, 0)), Some(&Wireworld::TAIL));
+        assert_eq!(next.get((0, 0)), Some(&Wireworld::CONDUCTOR));
+
+        // Diodes only let electrons pass from left to right
+        let forward = circuit(&[
+            "....##.....",
+            "tH###.#####",
+            "....##.....",
+        ]);
+        assert!(arrives(&forward, (9, -1)));
+        let backward = circuit(&[
+            "....##.....",
+            "#####.###Ht",
+            "....##.....",
+        ]);
+        assert!(!arrives(&backward, (1, -1)));
+    }
+
+    #[test]
+    fn brians_brain() {
+        // Two firing cells followed by two resting cells move forward
+        let mut cells = vec![];
+        for x in 0..2 {
+            cells.push(((x, 0), BriansBrain::FIRING));
+            cells.push(((x, -1), BriansBrain::RESTING));
+        }
+        let next = run(&mut BriansBrain, &Topology::Unbounded, &grid(&cells),
+                       5);
+        assert_eq!(next.iter().count(), 4);
+        for x in 0..2 {
+            assert_eq!(next.get((x, 5)), Some(&BriansBrain::FIRING));
+            assert_eq!(next.get((x, 4)), Some(&BriansBrain::RESTING));
+        }
+    }
+
+    #[test]
+    fn langtons_ant() {
+        let start = grid(&[((0, 0), LangtonsAnt::ANT)]);
+        // The ant walks around a square, turning right
+        let next = run(&mut LangtonsAnt, &Topology::Unbounded, &start, 4);
+        assert_eq!(next.iter().count(), 4);
+        assert_eq!(next.get((1, 0)), Some(&LangtonsAnt::ON));
+        assert_eq!(next.get((1, -1)), Some(&LangtonsAnt::ON));
+        assert_eq!(next.get((0, -1)), Some(&LangtonsAnt::ON));
+        assert_eq!(next.get((0, 0)), Some(&(LangtonsAnt::ANT + 4)));
+        // Then it turns left on the cell it toggled first
+        let next = run(&mut LangtonsAnt, &Topology::Unbounded, &start, 5);
+        assert_eq!(next.iter().count(), 4);
+        assert_eq!(next.get((0, 0)), None);
+        assert_eq!(next.get((-1, 0)), Some(&(LangtonsAnt::ANT + 3)));
+    }
+
+    #[test]
+    fn topologies() {
+        let area = AABB::new((0, 0), 4);
+        let cells = |tree: &QTree<State>| {
+            let mut cells: Vec<_> = tree.iter()
+                .map(|(point, &state)| (point, state))
+                .collect();
+            cells.sort();
+            cells
+        };
+
+        // An electron goes around a wire spanning a torus
+        let mut wire = grid(&[((-4, 0), Wireworld::TAIL),
+                              ((-3, 0), Wireworld::HEAD)]);
+        for x in -2..4 {
+            wire.set((x, 0), Wireworld::CONDUCTOR);
+        }
+        let torus = Topology::Torus(area.clone());
+        let next = run(&mut Wireworld, &torus, &wire, 7);
+        assert_eq!(next.get((-4, 0)), Some(&Wireworld::HEAD));
+        assert_eq!(cells(&run(&mut Wireworld, &torus, &wire, 8)),
+                   cells(&wire));
+        // But it stops at the edge of a plane
+        let plane = Topology::Plane(area.clone());
+        let next = run(&mut Wireworld, &plane, &wire, 8);
+        assert!(next.iter().all(|(_, &state)| state == Wireworld::CONDUCTOR));
+
+        // A Brian's Brain spaceship comes back mirrored after crossing the
+        // twisted edge of a Klein bottle
+        let ship = grid(&[((0, 0), BriansBrain::FIRING),
+                          ((1, 0), BriansBrain::FIRING),
+                          ((0, -1), BriansBrain::RESTING),
+                          ((1, -1), BriansBrain::RESTING)]);
+        let torus = run(&mut BriansBrain, &torus, &ship, 8);
+        assert_eq!(cells(&torus), cells(&ship));
+        let klein = Topology::KleinBottle(area);
+        let klein = run(&mut BriansBrain, &klein, &ship, 8);
+        assert_eq!(cells(&klein), vec![((-2, -1), BriansBrain::RESTING),
+                                       ((-2, 0), BriansBrain::FIRING),
+                                       ((-1, -1), BriansBrain::RESTING),
+                                       ((-1, 0), BriansBrain::FIRING)]);
+    }
+}
@@ -1,20 +1,22 @@
 //! Data storage.
 
-use super::{Point, point_minmax};
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+
+use super::{Coord, Point, point_minmax};
 
 /// Axis-aligned Bounding Box
 ///
 /// The bottom and left borders are considered to be part of the bounding box,
 /// the top and right borders are not.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AABB {
     center: Point,
-    half_dim: i32,
+    half_dim: Coord,
 }
 
 impl AABB {
     /// Create a new bounding box with a given `center` and half-dimension.
-    pub fn new(center: Point, half_dim: i32) -> AABB {
+    pub fn new(center: Point, half_dim: Coord) -> AABB {
         AABB {
             center,
             half_dim,
@@ -25,8 +27,8 @@ impl AABB {
     /// two points.
     pub fn between(a: Point, b: Point) -> AABB {
         let (min, max) = point_minmax(a, b);
-        let x = (((max.0 - min.0) as f32) / 2.0_f32).floor() as i32 + 1;
-        let y = (((max.1 - min.1) as f32) / 2.0_f32).floor() as i32 + 1;
+        let x = (max.0 - min.0) / 2 + 1;
+        let y = (max.1 - min.1) / 2 + 1;
         let half_dim = if x > y {x} else {y};
         AABB {
             center: (min.0 + x, min.1 + y),
@@ -34,11 +36,50 @@ impl AABB {
         }
     }
 
+    /// Get the center of the bounding box.
+    pub fn center(&self) -> Point {
+        self.center
+    }
+
+    /// Get the half-dimension of the bounding box.
+    pub fn half_dim(&self) -> Coord {
+        self.half_dim
+    }
+
+    /// Create a bounding box with the same center that is larger by `by`
+    /// cells in every direction.
+    pub fn grow(&self, by: Coord) -> AABB {
+        AABB::new(self.center, self.half_dim.saturating_add(by))
+    }
+
+    /// Split the bounding box into four quadrants (north-east, north-west,
+    /// south-east, south-west).
+    ///
+    /// Note: This only works properly if the half-dimension is even. At the
+    /// edges of the coordinate range, the centers of the quadrants are moved
+    /// inwards, so they cover (at least) the part of their quadrant that is
+    /// inside of the range.
+    pub fn quadrants(&self) -> [AABB; 4] {
+        let half_dim = self.half_dim / 2;
+        let east = self.center.0.saturating_add(half_dim);
+        let west = self.center.0.saturating_sub(half_dim);
+        let north = self.center.1.saturating_add(half_dim);
+        let south = self.center.1.saturating_sub(half_dim);
+        [
+            AABB::new((east, north), half_dim),
+            AABB::new((west, north), half_dim),
+            AABB::new((east, south), half_dim),
+            AABB::new((west, south), half_dim),
+        ]
+    }
+
     /// Check if a given `point` is in the bounding box.
     pub fn contains(&self, point: Point) -> bool {
-        let centered = (point.0 - self.center.0, point.1 - self.center.1);
-        (-self.half_dim <= centered.0 && centered.0 < self.half_dim &&
-         -self.half_dim <= centered.1 && centered.1 < self.half_dim)
+        let contains_range = |a: (i128, i128), b: Coord| {
+            a.0 <= i128::from(b) && i128::from(b) < a.1
+        };
+        contains_range(self.x_range(), point.0) &&
+            contains_range(self.y_range(), point.1)
     }
 
     /// Check if the bounding box intersects another one.
@@ -47,41 +88,82 @@ impl AABB {
          Self::intersects_range(self.y_range(), other.y_range()))
     }
 
-    fn intersects_range(a: (i32, i32), b: (i32, i32)) -> bool {
+    /// Check if another bounding box lies completely inside this one.
+    fn encloses(&self, other: &AABB) -> bool {
+        let encloses_range = |a: (i128, i128), b: (i128, i128)| {
+            a.0 <= b.0 && b.1 <= a.1
+        };
+        encloses_range(self.x_range(), other.x_range()) &&
+            encloses_range(self.y_range(), other.y_range())
+    }
+
+    fn intersects_range(a: (i128, i128), b: (i128, i128)) -> bool {
         (a.0 <= b.0 && a.1 > b.0 ||
          b.0 <= a.0 && b.1 > a.0)
     }
 
-    fn x_range(&self) -> (i32, i32) {
-        (self.center.0 - self.half_dim, self.center.0 + self.half_dim)
+    // The ranges are wider than `Coord`, since the borders of bounding boxes
+    // at the edges of the coordinate range can be outside of it
+
+    fn x_range(&self) -> (i128, i128) {
+        Self::range(self.center.0, self.half_dim)
+    }
+
+    fn y_range(&self) -> (i128, i128) {
+        Self::range(self.center.1, self.half_dim)
     }
 
-    fn y_range(&self) -> (i32, i32) {
-        (self.center.1 - self.half_dim, self.center.1 + self.half_dim)
+    fn range(center: Coord, half_dim: Coord) -> (i128, i128) {
+        let (center, half_dim) = (i128::from(center), i128::from(half_dim));
+        (center - half_dim, center + half_dim)
     }
 }
 
 const QTREE_CAP: usize = 4;
 
+/// The state of a cell.
+///
+/// 0 is a dead cell, 1 a live cell. Higher states are used by rules with more
+/// than two states (e.g. dying cells in Generations rules).
+pub type State = u8;
+
+/// The state of a live cell.
+pub const ALIVE: State = 1;
+
 /// A quadtree implementation with integer coordinates.
 ///
+/// Every point in the quadtree carries a value of type `T` (e.g. the `State`
+/// of the cell). The default `QTree<()>` only records which points are set,
+/// without any overhead for the values.
+///
 /// Automatically "reunites" sub-trees if possible and also automatically
-/// extends its boundaries to fit new elements.
+/// extends its boundaries to fit new elements (see `compact` for shrinking
+/// them again).
 #[derive(Debug)]
-pub struct QTree {
+pub struct QTree<T = ()> {
     boundary: AABB,
-    points: Option<[Option<Point>; 4]>,
-    children: Option<[Box<QTree>; 4]>,
+    points: Option<[Option<(Point, T)>; 4]>,
+    children: Option<[Box<QTree<T>>; 4]>,
+}
+
+/// Access to the values stored in a `QTree`.
+///
+/// This is a trait rather than inherent methods so that `QTree<()>` can keep
+/// its simpler `get` and `set` methods, which take precedence over these.
+pub trait CellMap<T> {
+    /// Get the value at a `point`, if there is one.
+    fn get(&self, point: Point) -> Option<&T>;
+
+    /// Add a `point` with a given `value` to the quadtree.
+    ///
+    /// If the point is already set, its value is overwritten.
+    fn set(&mut self, point: Point, value: T);
 }
 
 impl QTree {
     /// Create a new quadtree with a given `boundary` and some initial points.
     pub fn new(boundary: AABB, elements: &Vec<Point>) -> QTree {
-        let mut tree = QTree {
-            boundary,
-            points: Some([None, None, None, None]),
-            children: None,
-        };
+        let mut tree = QTree::empty(boundary);
 
         for point in elements {
             tree.set(*point);
@@ -90,76 +172,49 @@ impl QTree {
         tree
     }
 
-    /// Returns the current boundary of the quad tree.
-    pub fn boundary(&self) -> AABB {
-        self.boundary.clone()
-    }
-
     /// Check if there is something at a `point` in the quadtree.
     pub fn get(&self, point: Point) -> bool {
-        if let Some(ref points) = self.points {
-            points.contains(&Some(point))
-        } else if let Some(ref children) = self.children {
-            children[Self::get_child(&self.boundary, point)].get(point)
-        } else {
-            Self::invalid_state()
-        }
+        self.value(point).is_some()
     }
 
     /// Add a `point` to the quadtree.
     ///
     /// If the point is already set, do nothing.
     pub fn set(&mut self, point: Point) {
-        if !self.boundary.contains(point) {
-            let max_dist = f64::max(point.0.into(), point.1.into());
-            let half_dim = max_dist.log2().ceil().exp2() as i32;
-            let new_boundary = AABB::new(self.boundary.center, half_dim);
-            self.extend(new_boundary);
-        }
-
-        let mut should_subdivide = false;
-        if let Some(ref mut points) = self.points {
-            for p in points {
-                match p {
-                    &mut Some(p) => {
-                        if p == point {
-                            // point already exists
-                            return
-                        }
-                    }
-                    &mut None => {
-                        // Set point here
-                        *p = Some(point);
-                        return
-                    }
-                }
-            }
+        self.insert(point, ());
+    }
+}
 
-            // Can't call subdivide here because we'd borrow mutably twice
-            should_subdivide = true;
+impl<T> QTree<T> {
+    /// Create a new, empty quadtree with a given `boundary`.
+    pub fn empty(boundary: AABB) -> QTree<T> {
+        QTree {
+            boundary,
+            points: Some([None, None, None, None]),
+            children: None,
         }
+    }
 
-        if should_subdivide {
-            self.subdivide();
-        }
+    /// Returns the current boundary of the quad tree.
+    pub fn boundary(&self) -> AABB {
+        self.boundary.clone()
+    }
 
-        if let Some(ref mut children) = self.children {
-            let child = Self::get_child(&self.boundary, point);
-            children[child].set(point);
-        } else {
-            Self::invalid_state()
-        }
+    /// Check if there are no points in the quadtree.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
     }
 
     /// Remove a `point` from the quadtree.
     ///
-    /// If the point is not in the quadtree, do nothing.
+    /// If the point is not in the quadtree, do nothing. The boundary stays
+    /// the same (see `compact` for shrinking it).
     pub fn remove(&mut self, point: Point) {
         let mut check_union = false;
 
         if let Some(ref mut points) = self.points {
-            let index = points.into_iter().position(|p| {
-                *p == Some(point)
+            let index = points.iter().position(|p| {
+                p.as_ref().map(|&(p, _)| p) == Some(point)
             });
             if let Some(index) = index {
                 points[index] = None
@@ -178,12 +233,54 @@ impl QTree {
         }
     }
 
+    /// Shrink the boundary of the quadtree to the region with points in it.
+    ///
+    /// As long as only one quadrant of the root has points in it, that
+    /// quadrant becomes the new root, so the quadtree is re-centred on its
+    /// points. This keeps moving patterns (e.g. spaceships) from leaving an
+    /// ever-growing boundary behind, which makes lookups slower.
+    pub fn compact(&mut self) {
+        loop {
+            let child = match self.children {
+                Some(ref mut children) => {
+                    let mut occupied = children.iter_mut()
+                        .filter(|child| !child.is_empty());
+                    match (occupied.next(), occupied.next()) {
+                        (Some(child), None) => QTree {
+                            boundary: child.boundary.clone(),
+                            points: child.points.take(),
+                            children: child.children.take(),
+                        },
+                        _ => return,
+                    }
+                }
+                None => break,
+            };
+            *self = child;
+        }
+
+        // The points of leaves don't depend on their boundary, so it can
+        // shrink as long as they all fit into one quadrant
+        if self.is_empty() {
+            return
+        }
+        while self.boundary.half_dim % 2 == 0 {
+            let quadrant = self.boundary.quadrants().iter()
+                .find(|quadrant| self.points().all(|p| quadrant.contains(p)))
+                .cloned();
+            match quadrant {
+                Some(quadrant) => self.boundary = quadrant,
+                None => return,
+            }
+        }
+    }
+
     /// Get a vector of all points in an area.
     pub fn query(&self, area: &AABB) -> Vec<Point> {
         if self.boundary.intersects(area) {
-            if let Some(points) = self.points {
+            if let Some(ref points) = self.points {
                 points.iter().filter_map(|p| {
-                    p.and_then(|p| {
+                    p.as_ref().and_then(|&(p, _)| {
                         if area.contains(p) {
                             Some(p)
                         } else {
@@ -201,6 +298,122 @@ impl QTree {
         }
     }
 
+    /// Get an iterator over all points and their values.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            tree: self,
+            index: 0,
+            child_iterator: None,
+        }
+    }
+
+    /// Get an iterator over all points (without their values).
+    pub fn points<'a>(&'a self) -> QTreeIter<'a, T> {
+        QTreeIter {
+            iter: self.iter(),
+        }
+    }
+
+    /// Get the smallest and largest coordinates of all points (the corners
+    /// of their bounding box), or `None` if the quadtree is empty.
+    pub fn bounding_box(&self) -> Option<(Point, Point)> {
+        let mut points = self.points();
+        let first = points.next()?;
+        Some(points.fold((first, first), |(min, max), p| {
+            ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1)))
+        }))
+    }
+
+    /// Create a quadtree with the same points, but with values mapped by `f`.
+    pub fn map<U, F: FnMut(Point, &T) -> U>(&self, mut f: F) -> QTree<U> {
+        self.map_inner(&mut f)
+    }
+
+    fn map_inner<U, F: FnMut(Point, &T) -> U>(&self, f: &mut F) -> QTree<U> {
+        let points = self.points.as_ref().map(|points| {
+            let mut mapped = [None, None, None, None];
+            for (m, p) in mapped.iter_mut().zip(points.iter()) {
+                *m = p.as_ref().map(|(p, value)| (*p, f(*p, value)));
+            }
+            mapped
+        });
+        let children = self.children.as_ref().map(|c| {
+            [
+                Box::new(c[0].map_inner(f)),
+                Box::new(c[1].map_inner(f)),
+                Box::new(c[2].map_inner(f)),
+                Box::new(c[3].map_inner(f)),
+            ]
+        });
+        QTree {
+            boundary: self.boundary.clone(),
+            points,
+            children,
+        }
+    }
+
+    fn value(&self, point: Point) -> Option<&T> {
+        if let Some(ref points) = self.points {
+            points.iter().filter_map(|p| p.as_ref())
+                .find(|&&(p, _)| p == point)
+                .map(|(_, value)| value)
+        } else if let Some(ref children) = self.children {
+            children[Self::get_child(&self.boundary, point)].value(point)
+        } else {
+            Self::invalid_state()
+        }
+    }
+
+    /// Add a `point` with a `value`, growing the quadtree if necessary.
+    ///
+    /// # Panics
+    /// Panics if the quadtree can't grow far enough to contain `point`.
+    fn insert(&mut self, point: Point, value: T) {
+        while !self.boundary.contains(point) {
+            self.grow_towards(point);
+        }
+
+        let mut should_subdivide = false;
+        if let Some(ref mut points) = self.points {
+            // Check for an existing entry first, there may be gaps in front
+            // of it
+            let index = points.iter().position(|p| {
+                p.as_ref().map(|&(p, _)| p) == Some(point)
+            }).or_else(|| points.iter().position(Option::is_none));
+            if let Some(index) = index {
+                points[index] = Some((point, value));
+                return
+            }
+
+            // Can't call subdivide here because we'd borrow mutably twice
+            should_subdivide = true;
+        }
+
+        if should_subdivide {
+            self.subdivide();
+        }
+
+        if let Some(ref mut children) = self.children {
+            let child = Self::get_child(&self.boundary, point);
+            children[child].insert(point, value);
+        } else {
+            Self::invalid_state()
+        }
+    }
+
+    /// Move all points and their values out of the quadtree into `out`.
+    fn drain_into(&mut self, out: &mut Vec<(Point, T)>) {
+        if let Some(ref mut points) = self.points {
+            out.extend(points.iter_mut().filter_map(Option::take));
+        } else if let Some(ref mut children) = self.children {
+            for child in children.iter_mut() {
+                child.drain_into(out);
+            }
+        } else {
+            Self::invalid_state()
+        }
+    }
+
     fn get_child(boundary: &AABB, point: Point) -> usize {
         if point.1 >= boundary.center.1 {
             if point.0 >= boundary.center.0 {
@@ -217,28 +430,52 @@ impl QTree {
         }
     }
 
-    fn extend(&mut self, new_boundary: AABB) {
-        self.boundary = new_boundary;
-
-        // Collect these here because we can't borrow self (to call into_iter)
-        // later
-        let points: Vec<Point> = if self.children.is_some() {
-            self.into_iter().collect()
+    /// Double the size of the quadtree in the direction of `point`.
+    ///
+    /// The current root becomes one of the quadrants of the new one, so none
+    /// of the points have to be moved.
+    ///
+    /// # Panics
+    /// Panics if the new boundary would exceed the coordinate range.
+    fn grow_towards(&mut self, point: Point) {
+        let AABB { center, half_dim } = self.boundary.clone();
+        if half_dim < 1 {
+            // An empty boundary can't contain any points yet
+            self.boundary = AABB::new(center, 1);
+            return
+        }
+        let shift = |c: Coord, p: Coord| if p >= c {
+            c.checked_add(half_dim)
         } else {
-            vec![]
+            c.checked_sub(half_dim)
         };
-        if let Some(ref mut children) = self.children {
-            let bbs = Self::new_bbs(&self.boundary);
-            for i in 0..4 {
-                children[i] = Box::new(QTree::new(bbs[i].clone(), &vec![]));
-            }
-        }
-        // Set these here because we can't borrow self (to call set) earlier
-        if self.children.is_some() {
-            for p in points {
-                self.set(p);
-            }
+        let boundary = match (shift(center.0, point.0),
+                              shift(center.1, point.1),
+                              half_dim.checked_mul(2)) {
+            (Some(x), Some(y), Some(half_dim)) => AABB::new((x, y), half_dim),
+            _ => panic!("Can't grow the quadtree to contain {:?}", point),
+        };
+        if self.children.is_none() {
+            // The points of leaves don't depend on their boundary
+            self.boundary = boundary;
+            return
         }
+
+        let old = QTree {
+            boundary: self.boundary.clone(),
+            points: self.points.take(),
+            children: self.children.take(),
+        };
+        let bbs = boundary.quadrants();
+        let mut children = [
+            Box::new(QTree::empty(bbs[0].clone())),
+            Box::new(QTree::empty(bbs[1].clone())),
+            Box::new(QTree::empty(bbs[2].clone())),
+            Box::new(QTree::empty(bbs[3].clone())),
+        ];
+        *children[Self::get_child(&boundary, center)] = old;
+        self.boundary = boundary;
+        self.children = Some(children);
     }
 
     /// Subdivide the quadtree into four children.
@@ -249,74 +486,363 @@ impl QTree {
     /// # Panics
     /// Panics if `children` is `None`
     fn subdivide(&mut self) {
-        let bbs = Self::new_bbs(&self.boundary);
-        let mut points = [vec![], vec![], vec![], vec![]];
-        for point in self.points.unwrap().iter() {
-            if let &Some(p) = point {
-                for (i, bb) in bbs.iter().enumerate() {
-                    if bb.contains(p) {
-                        points[i].push(p);
-                    }
-                }
-            }
+        let bbs = self.boundary.quadrants();
+        let mut children = [
+            Box::new(QTree::empty(bbs[0].clone())),
+            Box::new(QTree::empty(bbs[1].clone())),
+            Box::new(QTree::empty(bbs[2].clone())),
+            Box::new(QTree::empty(bbs[3].clone())),
+        ];
+        let mut points = vec![];
+        self.drain_into(&mut points);
+        for (p, value) in points {
+            let child = Self::get_child(&self.boundary, p);
+            children[child].insert(p, value);
         }
 
         self.points = None;
-        self.children = Some([
-            Box::new(QTree::new(bbs[0].clone(), &points[0])),
-            Box::new(QTree::new(bbs[1].clone(), &points[1])),
-            Box::new(QTree::new(bbs[2].clone(), &points[2])),
-            Box::new(QTree::new(bbs[3].clone(), &points[3])),
-        ]);
+        self.children = Some(children);
     }
 
     fn check_union(&mut self) {
-        let mut points = [None; 4];
-        {
-            let it = self.into_iter().enumerate();
-            for (i, p) in it {
-                if i >= QTREE_CAP {
-                    // Too many points remaining
-                    return
+        if self.iter().nth(QTREE_CAP).is_some() {
+            // Too many points remaining
+            return
+        }
+
+        let mut points = vec![];
+        self.drain_into(&mut points);
+        let mut united = [None, None, None, None];
+        for (u, p) in united.iter_mut().zip(points) {
+            *u = Some(p);
+        }
+
+        self.points = Some(united);
+        self.children = None;
+    }
+
+    fn invalid_state() -> ! {
+        panic!("Invalid state - No children and no points!")
+    }
+}
+
+/// A geometric transformation of the cells in a quadtree.
+///
+/// Rotations and flips map the cell at `(0, 0)` onto itself. Like everywhere
+/// else, y points up, so `Rotate90` turns `(1, 0)` into `(0, -1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Move cells by the given offset.
+    Translate(Point),
+    /// Rotate by 90 degrees clockwise.
+    Rotate90,
+    /// Rotate by 180 degrees.
+    Rotate180,
+    /// Rotate by 270 degrees clockwise (i.e. 90 degrees anticlockwise).
+    Rotate270,
+    /// Mirror left and right.
+    FlipHorizontal,
+    /// Mirror top and bottom.
+    FlipVertical,
+    /// Swap the x and y coordinates (i.e. mirror along the diagonal through
+    /// the bottom left and top right).
+    Transpose,
+}
+
+impl Transform {
+    /// Get the cell that the cell at `point` is moved to.
+    ///
+    /// # Panics
+    /// Panics if the result is outside of the range of `Coord`.
+    pub fn apply(&self, point: Point) -> Point {
+        self.apply_with(point, |c: Coord| {
+            c.checked_neg().expect("Can't transform the cell")
+        })
+    }
+
+    /// Get the cell that the cell at `point` is moved to when transforming
+    /// only `area`.
+    ///
+    /// Rotations and flips map `area` onto itself, so they turn around its
+    /// center rather than `(0, 0)`.
+    ///
+    /// # Panics
+    /// Panics if the result is outside of the range of `Coord`.
+    pub fn apply_within(&self, area: &AABB, point: Point) -> Point {
+        if let Transform::Translate(_) = *self {
+            return self.apply(point)
+        }
+        let center = area.center();
+        // Relative to the center, the cells of the area range from
+        // `-half_dim` to `half_dim - 1`, which `!` swaps
+        let (x, y) = self.apply_with((point.0 - center.0, point.1 - center.1),
+                                     |c: Coord| !c);
+        (x + center.0, y + center.1)
+    }
+
+    fn apply_with<F: Fn(Coord) -> Coord>(&self, (x, y): Point, negate: F)
+                                         -> Point {
+        match *self {
+            Transform::Translate((dx, dy)) => {
+                match (x.checked_add(dx), y.checked_add(dy)) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => panic!("Can't move {:?} by {:?}", (x, y), (dx, dy)),
                 }
-                points[i] = Some(p);
             }
+            Transform::Rotate90 => (y, negate(x)),
+            Transform::Rotate180 => (negate(x), negate(y)),
+            Transform::Rotate270 => (negate(y), x),
+            Transform::FlipHorizontal => (negate(x), y),
+            Transform::FlipVertical => (x, negate(y)),
+            Transform::Transpose => (y, x),
         }
+    }
+}
 
-        self.points = Some(points);
-        self.children = None;
+/// Which points a set operation keeps: those that are only in the left
+/// quadtree, those that are only in the right one, and those in both.
+#[derive(Debug, Clone, Copy)]
+struct SetOperation {
+    left: bool,
+    right: bool,
+    both: bool,
+}
+
+impl<T: Clone> QTree<T> {
+    /// Get the points that are in either quadtree.
+    ///
+    /// Points in both keep the value from `self`.
+    pub fn union(&self, other: &QTree<T>) -> QTree<T> {
+        self.combine(other,
+                     SetOperation { left: true, right: true, both: true })
     }
 
-    fn new_bbs(old: &AABB) -> [AABB; 4] {
-        let half_dim = old.half_dim / 2;
-        let east = old.center.0 + half_dim;
-        let west = old.center.0 - half_dim;
-        let north = old.center.1 + half_dim;
-        let south = old.center.1 - half_dim;
-        [
-            AABB::new((east, north), half_dim),
-            AABB::new((west, north), half_dim),
-            AABB::new((east, south), half_dim),
-            AABB::new((west, south), half_dim),
-        ]
+    /// Get the points that are in both quadtrees, with the values from
+    /// `self`.
+    pub fn intersection(&self, other: &QTree<T>) -> QTree<T> {
+        self.combine(other,
+                     SetOperation { left: false, right: false, both: true })
     }
 
-    fn invalid_state() -> ! {
-        panic!("Invalid state - No children and no points!")
+    /// Get the points of `self` that aren't in `other`.
+    pub fn difference(&self, other: &QTree<T>) -> QTree<T> {
+        self.combine(other,
+                     SetOperation { left: true, right: false, both: false })
+    }
+
+    /// Get the points that are in exactly one of the quadtrees.
+    pub fn symmetric_difference(&self, other: &QTree<T>) -> QTree<T> {
+        self.combine(other,
+                     SetOperation { left: true, right: true, both: false })
+    }
+
+    /// Get a copy of the quadtree with all cells transformed.
+    ///
+    /// # Panics
+    /// Panics if a cell is moved outside of the range of `Coord`.
+    pub fn transform(&self, transform: Transform) -> QTree<T> {
+        let boundary = self.boundary();
+        let center = boundary.center();
+        let mut tree = QTree::empty(AABB::new(transform.apply(center),
+                                              boundary.half_dim()));
+        for (point, value) in self.iter() {
+            tree.insert(transform.apply(point), value.clone());
+        }
+        tree
+    }
+
+    /// Get a copy of the quadtree in which only the cells in `area` are
+    /// transformed (see `Transform::apply_within`).
+    ///
+    /// Cells that are moved onto cells outside of `area` replace them.
+    ///
+    /// # Panics
+    /// Panics if a cell is moved outside of the range of `Coord`.
+    pub fn transform_area(&self, area: &AABB, transform: Transform)
+                          -> QTree<T> {
+        let mut tree = QTree::empty(self.boundary());
+        let mut moved = vec![];
+        for (point, value) in self.iter() {
+            if area.contains(point) {
+                moved.push((transform.apply_within(area, point),
+                            value.clone()));
+            } else {
+                tree.insert(point, value.clone());
+            }
+        }
+        for (point, value) in moved {
+            tree.insert(point, value);
+        }
+        tree
+    }
+
+    fn combine(&self, other: &QTree<T>, operation: SetOperation)
+               -> QTree<T> {
+        Self::merge(self, other, operation)
+    }
+
+    /// Get a quadtree with the points of `a` and `b` that `operation` keeps.
+    ///
+    /// Both trees are walked together as long as their nodes line up (i.e.
+    /// they are split at the same center, or one tree lies within a quadrant
+    /// of a node of the other), and the result is built from the merged
+    /// children directly. Subtrees that only one of the trees has points in
+    /// are copied (or left out) as a whole, so points are only looked up one
+    /// by one where the nodes don't line up (e.g. at leaves).
+    fn merge(a: &QTree<T>, b: &QTree<T>, operation: SetOperation)
+             -> QTree<T> {
+        if a.is_empty() {
+            return Self::keep(b, operation.right)
+        } else if b.is_empty() {
+            return Self::keep(a, operation.left)
+        }
+
+        if let (Some(a_children), Some(b_children)) = (&a.children,
+                                                       &b.children) {
+            if a.boundary.center == b.boundary.center {
+                let boundary = if a.boundary.half_dim >= b.boundary.half_dim {
+                    a.boundary()
+                } else {
+                    b.boundary()
+                };
+                let child = |i: usize| {
+                    Box::new(Self::merge(&a_children[i], &b_children[i],
+                                         operation))
+                };
+                return Self::node(boundary,
+                                  [child(0), child(1), child(2), child(3)])
+            }
+        }
+        // If one tree lies inside a quadrant of the other, the other
+        // children of that one only have points on one side
+        if let Some(ref a_children) = a.children {
+            if let Some(i) = Self::quadrant_of(&a.boundary, &b.boundary) {
+                let child = |j: usize| Box::new(if i == j {
+                    Self::merge(&a_children[j], b, operation)
+                } else {
+                    Self::keep(&a_children[j], operation.left)
+                });
+                return Self::node(a.boundary(),
+                                  [child(0), child(1), child(2), child(3)])
+            }
+        }
+        if let Some(ref b_children) = b.children {
+            if let Some(i) = Self::quadrant_of(&b.boundary, &a.boundary) {
+                let child = |j: usize| Box::new(if i == j {
+                    Self::merge(a, &b_children[j], operation)
+                } else {
+                    Self::keep(&b_children[j], operation.right)
+                });
+                return Self::node(b.boundary(),
+                                  [child(0), child(1), child(2), child(3)])
+            }
+        }
+        Self::merge_points(a, b, operation)
+    }
+
+    /// Get the points of `a` and `b` that `operation` keeps by copying the
+    /// larger tree as a whole and looking up the points of the smaller one
+    /// (e.g. a leaf) in it one by one.
+    fn merge_points(a: &QTree<T>, b: &QTree<T>, operation: SetOperation)
+                    -> QTree<T> {
+        let a_small = a.children.is_none() ||
+            b.children.is_some() && a.boundary.half_dim <= b.boundary.half_dim;
+        let (small, big, small_kept, big_kept) = if a_small {
+            (a, b, operation.left, operation.right)
+        } else {
+            (b, a, operation.right, operation.left)
+        };
+        let mut tree = Self::keep(big, big_kept);
+        for (point, value) in small.iter() {
+            match big.value(point) {
+                // Points in both trees keep the value from `a`
+                Some(_) if operation.both && a_small => {
+                    tree.insert(point, value.clone());
+                }
+                Some(big_value) if operation.both && !big_kept => {
+                    tree.insert(point, big_value.clone());
+                }
+                Some(_) if !operation.both && big_kept => {
+                    tree.remove(point);
+                }
+                Some(_) => {}
+                None if small_kept => tree.insert(point, value.clone()),
+                None => {}
+            }
+        }
+        tree
+    }
+
+    /// Get a copy of `tree` if it is `kept`, or an empty tree with the same
+    /// boundary otherwise.
+    fn keep(tree: &QTree<T>, kept: bool) -> QTree<T> {
+        if kept {
+            tree.map(|_, value| value.clone())
+        } else {
+            QTree::empty(tree.boundary())
+        }
+    }
+
+    /// Create a node with the given `children`, which becomes a leaf if they
+    /// only have a few points left.
+    fn node(boundary: AABB, children: [Box<QTree<T>>; 4]) -> QTree<T> {
+        let mut tree = QTree {
+            boundary,
+            points: None,
+            children: Some(children),
+        };
+        tree.check_union();
+        tree
+    }
+
+    /// Get the index of the child of a node with the given `boundary` that
+    /// all points in `area` belong to, if `area` is inside of the boundary
+    /// and on one side of its center in both directions.
+    fn quadrant_of(boundary: &AABB, area: &AABB) -> Option<usize> {
+        if !boundary.encloses(area) {
+            return None
+        }
+        let side = |range: (i128, i128), center: Coord| {
+            if range.0 >= i128::from(center) {
+                Some(true)
+            } else if range.1 <= i128::from(center) {
+                Some(false)
+            } else {
+                None
+            }
+        };
+        let east = side(area.x_range(), boundary.center.0)?;
+        let north = side(area.y_range(), boundary.center.1)?;
+        Some(match (east, north) {
+            (true, true) => 0,
+            (false, true) => 1,
+            (true, false) => 2,
+            (false, false) => 3,
+        })
     }
 }
 
-/// An iterator over a `QTree`
-pub struct QTreeIter<'a> {
-    tree: &'a QTree,
+impl<T> CellMap<T> for QTree<T> {
+    fn get(&self, point: Point) -> Option<&T> {
+        self.value(point)
+    }
+
+    fn set(&mut self, point: Point, value: T) {
+        self.insert(point, value)
+    }
+}
+
+/// An iterator over the points in a `QTree` and their values
+pub struct Iter<'a, T: 'a> {
+    tree: &'a QTree<T>,
     index: usize,
-    child_iterator: Option<Box<QTreeIter<'a>>>,
+    child_iterator: Option<Box<Iter<'a, T>>>,
 }
 
-impl<'a> Iterator for QTreeIter<'a> {
-    type Item = Point;
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (Point, &'a T);
 
-    fn next(&mut self) -> Option<Point> {
+    fn next(&mut self) -> Option<(Point, &'a T)> {
         if let Some(ref mut it) = self.child_iterator {
             let n = it.next();
             if n.is_some() {
@@ -327,17 +853,17 @@ impl<'a> Iterator for QTreeIter<'a> {
         }
         if self.index == 4 {
             None
-        } else if let Some(points) = self.tree.points {
+        } else if let Some(ref points) = self.tree.points {
             for p in &points[self.index..] {
                 self.index += 1;
-                if p.is_some() {
-                    return *p
+                if let Some((p, ref value)) = *p {
+                    return Some((p, value))
                 }
             }
             None
         } else if let Some(ref children) = self.tree.children {
             while self.index < 4 {
-                self.child_iterator = Some(Box::new(children[self.index].into_iter()));
+                self.child_iterator = Some(Box::new(children[self.index].iter()));
                 // Can't use unwrap because that moves the value out of the Option
                 if let Some(ref mut it) = self.child_iterator {
                     let n = it.next();
@@ -352,21 +878,67 @@ impl<'a> Iterator for QTreeIter<'a> {
             }
             None
         } else {
-            QTree::invalid_state()
+            QTree::<T>::invalid_state()
         }
     }
 }
 
+/// An iterator over the points in a `QTree`
+pub struct QTreeIter<'a, T: 'a> {
+    iter: Iter<'a, T>,
+}
+
+impl<'a, T> Iterator for QTreeIter<'a, T> {
+    type Item = Point;
+
+    fn next(&mut self) -> Option<Point> {
+        self.iter.next().map(|(p, _)| p)
+    }
+}
+
 impl<'a> IntoIterator for &'a QTree {
     type Item = Point;
-    type IntoIter = QTreeIter<'a>;
+    type IntoIter = QTreeIter<'a, ()>;
 
-    fn into_iter(self) -> QTreeIter<'a> {
-        QTreeIter {
-            tree: self,
-            index: 0,
-            child_iterator: None,
-        }
+    fn into_iter(self) -> QTreeIter<'a, ()> {
+        self.points()
+    }
+}
+
+impl<T: Clone> BitOr<&QTree<T>> for &QTree<T> {
+    type Output = QTree<T>;
+
+    /// Get the union of two quadtrees (see `QTree::union`).
+    fn bitor(self, other: &QTree<T>) -> QTree<T> {
+        self.union(other)
+    }
+}
+
+impl<T: Clone> BitAnd<&QTree<T>> for &QTree<T> {
+    type Output = QTree<T>;
+
+    /// Get the intersection of two quadtrees (see `QTree::intersection`).
+    fn bitand(self, other: &QTree<T>) -> QTree<T> {
+        self.intersection(other)
+    }
+}
+
+impl<T: Clone> Sub<&QTree<T>> for &QTree<T> {
+    type Output = QTree<T>;
+
+    /// Get the difference of two quadtrees (see `QTree::difference`).
+    fn sub(self, other: &QTree<T>) -> QTree<T> {
+        self.difference(other)
+    }
+}
+
+impl<T: Clone> BitXor<&QTree<T>> for &QTree<T> {
+    type Output = QTree<T>;
+
+    /// Get the symmetric difference of two quadtrees (see
+    /// `QTree::symmetric_difference`).
+    fn bitxor(self, other: &QTree<T>) -> QTree<T> {
+        self.symmetric_difference(other)
     }
 }
 
@@ -376,7 +948,7 @@ mod tests {
     use super::*;
 
     mod aabb {
-        use super::AABB;
+        use super::{AABB, QTree};
 
         #[test]
         fn new() {
@@ -420,10 +992,64 @@ mod tests {
             assert!(bb0.intersects(&bb3));
             assert!(bb3.intersects(&bb0));
         }
+
+        #[test]
+        fn grow_quadrants() {
+            let bb = AABB::new((10, 2), 4).grow(4);
+            assert_eq!(bb.half_dim(), 8);
+            assert!(bb.contains((2, -6)));
+            assert!(!bb.contains((18, 2)));
+            let quadrants = bb.quadrants();
+            for x in 2..18 {
+                for y in -6..10 {
+                    let count = quadrants.iter()
+                        .filter(|q| q.contains((x, y))).count();
+                    assert_eq!(count, 1);
+                }
+            }
+            assert!(quadrants[0].contains((10, 2)));
+            assert!(quadrants[3].contains((9, 1)));
+        }
+
+        #[test]
+        fn extremes() {
+            use backend::Coord;
+
+            let (min, max) = (Coord::MIN, Coord::MAX);
+            let everything = AABB::new((0, 0), max);
+            assert!(everything.contains((min + 1, max - 1)));
+            assert!(!everything.contains((min, 0)));
+            assert!(!everything.contains((0, max)));
+
+            // Boxes whose borders are outside of the coordinate range
+            let corner = AABB::new((max - 7, min + 8), 16);
+            assert!(corner.contains((max, min)));
+            assert!(corner.contains((max - 23, min + 23)));
+            assert!(!corner.contains((max - 24, min)));
+            assert!(everything.intersects(&corner));
+            assert!(corner.intersects(&AABB::new((max, min), 4)));
+            assert!(!corner.intersects(&AABB::new((min, min), 4)));
+            assert!(!everything.encloses(&corner));
+            assert!(corner.encloses(&AABB::new((max - 1, min + 1), 1)));
+            let huge = AABB::new((min, max), max);
+            assert!(huge.contains((min, max)));
+            assert!(huge.contains((-2, 0)));
+            assert!(!huge.contains((-1, 0)));
+            assert!(!huge.contains((min, -1)));
+
+            let tree = QTree::new(corner.clone(),
+                                  &vec![(max, min), (max - 20, min + 20),
+                                        (max - 1, min + 1), (max, min + 3),
+                                        (max - 3, min)]);
+            assert!(tree.get((max, min)));
+            assert!(!tree.get((max, min + 1)));
+            assert_eq!(tree.query(&corner).len(), 5);
+            assert_eq!(tree.boundary(), corner);
+        }
     }
 
     mod qtree {
-        use super::{AABB, QTree, Point};
+        use super::{AABB, ALIVE, CellMap, Coord, QTree, Point, State};
         use std::collections::BTreeSet;
 
         #[test]
@@ -447,6 +1073,63 @@ mod tests {
             assert!(!tree.get(point));
         }
 
+        #[test]
+        fn values() {
+            let mut tree = QTree::<State>::empty(AABB::new((0, 0), 4));
+            tree.set((0, 0), ALIVE);
+            tree.set((1, 1), 3);
+            tree.set((-2, 1), 2);
+            tree.set((2, -3), 4);
+            tree.set((-1, -1), 5);
+            assert_eq!(tree.get((0, 0)), Some(&ALIVE));
+            assert_eq!(tree.get((1, 1)), Some(&3));
+            assert_eq!(tree.get((-1, -1)), Some(&5));
+            assert_eq!(tree.get((1, 0)), None);
+
+            tree.set((1, 1), 2);
+            tree.set((2, -3), ALIVE);
+            tree.remove((-2, 1));
+            let actual: BTreeSet<(Point, State)> = tree.iter()
+                .map(|(p, &state)| (p, state)).collect();
+            let mut expected = BTreeSet::new();
+            expected.insert(((0, 0), ALIVE));
+            expected.insert(((1, 1), 2));
+            expected.insert(((2, -3), ALIVE));
+            expected.insert(((-1, -1), 5));
+            assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn values_not_copy() {
+            let mut tree = QTree::<String>::empty(AABB::new((0, 0), 4));
+            let points = [(0, 0), (3, 2), (0, -3), (0, -4), (2, 2), (9, 6)];
+            for point in points.iter() {
+                tree.set(*point, format!("{:?}", point));
+            }
+            for point in points.iter() {
+                assert_eq!(tree.get(*point), Some(&format!("{:?}", point)));
+            }
+            tree.remove((2, 2));
+            tree.remove((9, 6));
+            assert_eq!(tree.get((2, 2)), None);
+            assert_eq!(tree.iter().count(), 4);
+            assert!(tree.children.is_none());
+        }
+
+        #[test]
+        fn map() {
+            let tree = QTree::new(AABB::new((0, 0), 4),
+                                  &vec![(0, 0), (3, 2), (0, -3), (0, -4),
+                                        (2, 2)]);
+            let mapped = tree.map(|p, _| p.0 + p.1);
+            assert_eq!(mapped.get((3, 2)), Some(&5));
+            assert_eq!(mapped.get((0, -4)), Some(&-4));
+            assert_eq!(mapped.get((1, 1)), None);
+            let actual: BTreeSet<Point> = mapped.points().collect();
+            let expected: BTreeSet<Point> = tree.into_iter().collect();
+            assert_eq!(actual, expected);
+        }
+
         #[test]
         fn iterator_simple() {
             let mut tree = QTree::new(AABB::new((1, 3), 5),
@@ -589,7 +1272,333 @@ mod tests {
 
             let actual: BTreeSet<Point> = tree.into_iter().collect();
             assert_eq!(actual, expected);
-            assert_eq!(tree.boundary.half_dim, 16);
+            // The tree only grows towards the new point
+            assert_eq!(tree.boundary.half_dim, 8);
+            assert_eq!(tree.boundary.center, (4, 4));
+        }
+
+        #[test]
+        fn extend_negative() {
+            let mut tree = QTree::new(AABB::new((0, 0), 4),
+                                      &vec![(0, 0), (3, 2), (-4, -4)]);
+            tree.set((-100, -3));
+            assert!(tree.boundary.contains((-100, -3)));
+            assert_eq!(tree.boundary.half_dim, 64);
+            let actual: BTreeSet<Point> = tree.into_iter().collect();
+            let expected: BTreeSet<Point> = [(0, 0), (3, 2), (-4, -4),
+                                             (-100, -3)].iter().cloned()
+                .collect();
+            assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn extend_off_centre() {
+            let mut tree = QTree::new(AABB::new((100, 100), 2), &vec![]);
+            for &point in &[(100, 100), (99, 101), (0, 0), (-50, 300),
+                            (101, 98)] {
+                tree.set(point);
+                assert!(tree.boundary.contains(point));
+            }
+            for &point in &[(100, 100), (99, 101), (0, 0), (-50, 300),
+                            (101, 98)] {
+                assert!(tree.get(point));
+            }
+            assert_eq!(tree.into_iter().count(), 5);
+        }
+
+        #[test]
+        fn extend_keeps_root() {
+            let mut tree = tree_with_children();
+            tree.set((5, -7));
+            // The old root is now the north-west quadrant
+            assert_eq!(tree.boundary.center, (4, -4));
+            assert_eq!(tree.boundary.half_dim, 8);
+            let children = tree.children.as_ref().unwrap();
+            assert_eq!(children[1].boundary.center, (0, 0));
+            assert_eq!(children[1].boundary.half_dim, 4);
+            assert!(children[1].children.is_some());
+            assert!(children[2].get((5, -7)));
+        }
+
+        #[test]
+        fn extend_random() {
+            let mut seed = 2718u32;
+            let mut random = |range: Coord| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 8) as Coord % (2 * range) - range
+            };
+            for _ in 0..20 {
+                let center = (random(1000), random(1000));
+                let mut tree = QTree::<Coord>::empty(AABB::new(center, 2));
+                let mut expected = BTreeSet::new();
+                for i in 0..50 {
+                    let range = if i % 5 == 0 { 1 << 20 } else { 64 };
+                    let point = (random(range), random(range));
+                    tree.set(point, point.0 ^ point.1);
+                    expected.insert(point);
+                    assert!(tree.boundary.contains(point));
+                }
+                for &point in &expected {
+                    assert_eq!(tree.get(point), Some(&(point.0 ^ point.1)));
+                }
+                let actual: BTreeSet<Point> = tree.points().collect();
+                assert_eq!(actual, expected);
+
+                for &point in expected.iter().step_by(2) {
+                    tree.remove(point);
+                }
+                let actual: BTreeSet<Point> = tree.points().collect();
+                let expected: BTreeSet<Point> = expected.iter().skip(1)
+                    .step_by(2).cloned().collect();
+                assert_eq!(actual, expected);
+            }
+        }
+
+        #[test]
+        fn set_operations() {
+            let mut a = QTree::<State>::empty(AABB::new((0, 0), 4));
+            let mut b = QTree::<State>::empty(AABB::new((0, 0), 4));
+            for &point in &[(0, 0), (1, 0), (2, 0), (-3, 3), (3, -4)] {
+                a.set(point, 1);
+            }
+            for &point in &[(1, 0), (2, 0), (20, 20), (-3, -3), (3, -4)] {
+                b.set(point, 2);
+            }
+            let points = |tree: &QTree<State>| {
+                tree.iter().map(|(p, &state)| (p, state))
+                    .collect::<BTreeSet<(Point, State)>>()
+            };
+            let expected: BTreeSet<(Point, State)> = [
+                ((0, 0), 1), ((1, 0), 1), ((2, 0), 1), ((-3, 3), 1),
+                ((3, -4), 1), ((20, 20), 2), ((-3, -3), 2),
+            ].iter().cloned().collect();
+            assert_eq!(points(&a.union(&b)), expected);
+            assert_eq!(points(&(&a | &b)), expected);
+            let expected: BTreeSet<(Point, State)> = [
+                ((1, 0), 1), ((2, 0), 1), ((3, -4), 1),
+            ].iter().cloned().collect();
+            assert_eq!(points(&a.intersection(&b)), expected);
+            assert_eq!(points(&(&a & &b)), expected);
+            let expected: BTreeSet<(Point, State)> = [
+                ((0, 0), 1), ((-3, 3), 1),
+            ].iter().cloned().collect();
+            assert_eq!(points(&a.difference(&b)), expected);
+            assert_eq!(points(&(&a - &b)), expected);
+            let expected: BTreeSet<(Point, State)> = [
+                ((0, 0), 1), ((-3, 3), 1), ((20, 20), 2), ((-3, -3), 2),
+            ].iter().cloned().collect();
+            assert_eq!(points(&a.symmetric_difference(&b)), expected);
+            assert_eq!(points(&(&a ^ &b)), expected);
+
+            let empty = QTree::<State>::empty(AABB::new((0, 0), 4));
+            assert_eq!(points(&(&a | &empty)), points(&a));
+            assert!((&a & &empty).is_empty());
+            assert_eq!(points(&(&empty ^ &b)), points(&b));
+        }
+
+        #[test]
+        fn set_operations_random() {
+            let mut seed = 1618u32;
+            // Cells around `center`, up to `size` cells away
+            let mut soup = |center: Point, half_dim: Coord, size: Coord| {
+                let mut tree = QTree::new(AABB::new(center, half_dim),
+                                          &vec![]);
+                for x in -size..size {
+                    for y in -size..size {
+                        seed = seed.wrapping_mul(1103515245)
+                            .wrapping_add(12345);
+                        if seed >> 16 & 3 == 0 {
+                            tree.set((center.0 + x, center.1 + y));
+                        }
+                    }
+                }
+                tree
+            };
+            // Trees whose nodes line up, ones of which one is inside a node
+            // of the other, and ones whose nodes don't line up at all
+            let mut pairs = vec![
+                (soup((0, 0), 16, 12), soup((0, 0), 16, 12)),
+                (soup((0, 0), 32, 12), soup((16, 16), 8, 6)),
+                (soup((-8, 8), 8, 8), soup((0, 0), 32, 16)),
+                (soup((0, 0), 4, 12), soup((0, 0), 32, 12)),
+                (soup((3, -5), 5, 12), soup((-1, 7), 3, 12)),
+                (soup((0, 0), 5, 6), soup((-1, 2), 2, 3)),
+                (soup((1, -1), 7, 8), soup((3, 2), 1, 2)),
+            ];
+            // With an odd half-dimension, the children of the root grow past
+            // its center
+            pairs.push((
+                QTree::new(AABB::new((0, 0), 5),
+                           &vec![(0, 4), (-1, 1), (-2, 2), (1, -1), (-1, -1)]),
+                QTree::new(AABB::new((-1, 2), 2),
+                           &vec![(-1, 1), (-2, 2), (0, 0), (-3, 0), (-2, 3)]),
+            ));
+            let expected: BTreeSet<Point> = [(-2, 2), (-1, 1)].iter().cloned()
+                .collect();
+            assert_eq!((&pairs[7].0 & &pairs[7].1).points()
+                       .collect::<BTreeSet<_>>(), expected);
+            for (a, b) in &pairs {
+                let set_a: BTreeSet<Point> = a.into_iter().collect();
+                let set_b: BTreeSet<Point> = b.into_iter().collect();
+                let set = |tree: QTree| tree.points()
+                    .collect::<BTreeSet<Point>>();
+                assert_eq!(set(a | b), &set_a | &set_b);
+                assert_eq!(set(a & b), &set_a & &set_b);
+                assert_eq!(set(a - b), &set_a - &set_b);
+                assert_eq!(set(b - a), &set_b - &set_a);
+                assert_eq!(set(a ^ b), &set_a ^ &set_b);
+
+                // The results are proper quadtrees, in which points can be
+                // looked up
+                let union = a | b;
+                for point in &set_a | &set_b {
+                    assert!(union.get(point));
+                    assert!(union.boundary().contains(point));
+                }
+                let intersection = a & b;
+                for point in &set_a - &set_b {
+                    assert!(!intersection.get(point));
+                }
+                // ... and nodes without enough points are collapsed
+                let empty = a - a;
+                assert!(empty.is_empty() && empty.children.is_none());
+            }
+        }
+
+        #[test]
+        fn transform_glider() {
+            use super::Transform::*;
+
+            let glider = QTree::new(AABB::new((0, 0), 4),
+                                    &vec![(0, 0), (1, 0), (2, 0), (0, 1),
+                                          (1, 2)]);
+            let images = [
+                (vec![], [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)]),
+                (vec![Rotate90], [(0, 0), (0, -1), (0, -2), (1, 0), (2, -1)]),
+                (vec![Rotate180],
+                 [(0, 0), (-1, 0), (-2, 0), (0, -1), (-1, -2)]),
+                (vec![Rotate270], [(0, 0), (0, 1), (0, 2), (-1, 0), (-2, 1)]),
+                (vec![FlipHorizontal],
+                 [(0, 0), (-1, 0), (-2, 0), (0, 1), (-1, 2)]),
+                (vec![FlipVertical],
+                 [(0, 0), (1, 0), (2, 0), (0, -1), (1, -2)]),
+                (vec![Transpose], [(0, 0), (0, 1), (0, 2), (1, 0), (2, 1)]),
+                (vec![Transpose, Rotate180],
+                 [(0, 0), (0, -1), (0, -2), (-1, 0), (-2, -1)]),
+            ];
+            let mut seen = BTreeSet::new();
+            for (transforms, expected) in &images {
+                let image = transforms.iter()
+                    .fold(glider.transform(Translate((0, 0))),
+                          |tree, &transform| tree.transform(transform));
+                let cells: BTreeSet<Point> = image.points().collect();
+                assert_eq!(cells, expected.iter().cloned().collect());
+                seen.insert(cells.into_iter().collect::<Vec<_>>());
+            }
+            assert_eq!(seen.len(), 8);
+
+            // The compositions of the other transforms are among the images
+            let rotated = glider.transform(FlipHorizontal)
+                .transform(FlipVertical);
+            assert_eq!(rotated.points().collect::<BTreeSet<_>>(),
+                       glider.transform(Rotate180).points().collect());
+            let moved = glider.transform(Translate((-5, 7)));
+            let cells: BTreeSet<Point> = moved.points().collect();
+            assert_eq!(cells, [(-5, 7), (-4, 7), (-3, 7), (-5, 8), (-4, 9)]
+                       .iter().cloned().collect());
+        }
+
+        #[test]
+        fn transform_area() {
+            use super::Transform::*;
+
+            let points = vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 2),
+                              (10, 10), (-3, 0)];
+            let tree = QTree::new(AABB::new((0, 0), 4), &points)
+                .map(|(x, _), _| x);
+            let area = AABB::new((1, 1), 2);
+            let rotated = tree.transform_area(&area, Rotate90);
+            let cells: BTreeSet<Point> = rotated.points().collect();
+            assert_eq!(cells, [(0, -1), (0, 0), (0, 1), (1, 1), (2, 0),
+                               (10, 10), (-3, 0)].iter().cloned().collect());
+            // The values move with the cells
+            assert_eq!(rotated.get((2, 0)), Some(&1));
+            assert_eq!(rotated.get((0, -1)), Some(&2));
+            assert_eq!(rotated.get((-3, 0)), Some(&-3));
+
+            // Rotations and flips map the area onto itself
+            for &transform in [Rotate90, Rotate180, Rotate270, FlipHorizontal,
+                               FlipVertical, Transpose].iter() {
+                for &point in &points {
+                    let moved = transform.apply_within(&area, point);
+                    assert_eq!(area.contains(point), area.contains(moved));
+                }
+            }
+            let mut back = rotated;
+            for _ in 0..3 {
+                back = back.transform_area(&area, Rotate90);
+            }
+            let cells: BTreeSet<Point> = back.points().collect();
+            assert_eq!(cells, points.iter().cloned().collect());
+
+            // Moved cells replace the ones they land on
+            let moved = tree.transform_area(&AABB::new((0, 0), 1),
+                                            Translate((-3, 0)));
+            assert_eq!(moved.get((-3, 0)), Some(&0));
+            assert_eq!(moved.get((0, 0)), None);
+            assert_eq!(moved.iter().count(), points.len() - 1);
+        }
+
+        #[test]
+        fn compact() {
+            let far = [(100, 100), (101, 100), (102, 100), (100, 101),
+                       (101, 102)];
+            let mut tree = QTree::new(AABB::new((0, 0), 4), &vec![(0, 0)]);
+            for &point in &far {
+                tree.set(point);
+            }
+            let half_dim = tree.boundary.half_dim;
+            tree.compact();
+            assert_eq!(tree.boundary.half_dim, half_dim);
+
+            // Removing the last point near the origin keeps the boundary,
+            // until the tree is compacted
+            tree.remove((0, 0));
+            assert_eq!(tree.boundary.half_dim, half_dim);
+            assert!(tree.boundary.contains((0, 0)));
+            tree.compact();
+            assert!(tree.boundary.half_dim <= 4);
+            assert!(!tree.boundary.contains((0, 0)));
+            let actual: BTreeSet<Point> = tree.into_iter().collect();
+            let expected: BTreeSet<Point> = far.iter().cloned().collect();
+            assert_eq!(actual, expected);
+            assert_eq!(tree.query(&AABB::new((101, 101), 2)).len(), 5);
+
+            // Leaves shrink to their points
+            let mut tree = QTree::new(AABB::new((0, 0), 64),
+                                      &vec![(-30, 20), (-29, 21)]);
+            tree.compact();
+            assert_eq!(tree.boundary.half_dim, 1);
+            assert!(tree.get((-30, 20)));
+            assert!(tree.get((-29, 21)));
+
+            // Empty trees stay as they are
+            let mut tree = QTree::new(AABB::new((0, 0), 64), &vec![]);
+            tree.compact();
+            assert_eq!(tree.boundary.half_dim, 64);
+            assert!(tree.is_empty());
+        }
+
+        #[test]
+        fn bounding_box() {
+            let mut tree = QTree::new(AABB::new((0, 0), 16), &vec![]);
+            assert_eq!(tree.bounding_box(), None);
+            tree.set((3, -2));
+            assert_eq!(tree.bounding_box(), Some(((3, -2), (3, -2))));
+            for &point in &[(-5, 1), (0, 7), (2, -9)] {
+                tree.set(point);
+            }
+            assert_eq!(tree.bounding_box(), Some(((-5, -9), (3, 7))));
         }
     }
 }
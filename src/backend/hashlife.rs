@@ -0,0 +1,759 @@
+//! Gosper's Hashlife algorithm.
+//!
+//! The universe is stored as a quadtree in which identical subtrees are only
+//! stored once ("hash-consing"). Since every node is canonical, the future of
+//! the centre of a node can be memoised per node, which allows advancing huge
+//! (but regular) patterns by enormous numbers of generations.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::mem::size_of;
+
+use super::{Coord, Point};
+use super::data::{AABB, QTree, State};
+use super::rule::{Neighbourhood, Rule};
+
+/// The default memory limit (256 MiB).
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 << 20;
+
+/// The largest level of the root node, so that the coordinates of all cells
+/// (and the offsets used to reach them) fit into an `i64`.
+pub const MAX_LEVEL: u8 = 62;
+
+/// The largest `step_log2` that `HashLife::step` supports, since a step of
+/// `2^step_log2` generations needs a root of level `step_log2 + 3`.
+pub const MAX_STEP_LOG2: u8 = MAX_LEVEL - 3;
+
+/// Index of a node in the arena of a `HashLife` universe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct NodeId(u32);
+
+const DEAD: NodeId = NodeId(0);
+const ALIVE: NodeId = NodeId(1);
+
+/// A node of the hashed quadtree.
+///
+/// Level 0 nodes are single cells, a node of level `n` covers `2^n * 2^n`
+/// cells. The children are ordered north-west, north-east, south-west,
+/// south-east.
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    level: u8,
+    children: [NodeId; 4],
+    population: u64,
+}
+
+/// Errors that can occur when setting up a `HashLife` universe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashLifeError {
+    /// Hashlife only supports rules with two states, but the rule has more.
+    MultiState(State),
+    /// Hashlife only supports unbounded universes.
+    Bounded,
+    /// Hashlife only supports neighbourhoods within the surrounding 3x3
+    /// square.
+    Neighbourhood,
+    /// Hashlife can advance by at most `2^MAX_STEP_LOG2` generations at once,
+    /// but more were requested.
+    Step(u8),
+    /// The universe would have to grow beyond `MAX_LEVEL`, or the generation
+    /// counter would overflow.
+    Overflow,
+}
+
+impl fmt::Display for HashLifeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HashLifeError::MultiState(states) => {
+                write!(f, "Hashlife doesn't support rules with {} states",
+                       states)
+            }
+            HashLifeError::Bounded => {
+                write!(f, "Hashlife doesn't support bounded universes")
+            }
+            HashLifeError::Neighbourhood => {
+                write!(f, "Hashlife doesn't support neighbourhoods larger \
+                           than 3x3")
+            }
+            HashLifeError::Step(step_log2) => {
+                write!(f, "Hashlife can't advance by 2^{} generations at \
+                           once", step_log2)
+            }
+            HashLifeError::Overflow => {
+                write!(f, "the Hashlife universe is too large or too old")
+            }
+        }
+    }
+}
+
+impl Error for HashLifeError {}
+
+/// A universe that is simulated with Hashlife.
+///
+/// The universe is centred on `(0, 0)` and grows automatically to fit the
+/// pattern.
+pub struct HashLife {
+    rule: Rule,
+    nodes: Vec<Node>,
+    /// Interned nodes, indexed by their children
+    index: HashMap<[NodeId; 4], NodeId>,
+    /// Memoised results of `advance`, indexed by node and `step_log2`
+    results: HashMap<(NodeId, u8), NodeId>,
+    /// The empty node of each level
+    empty: Vec<NodeId>,
+    root: NodeId,
+    generation: u64,
+    max_nodes: usize,
+}
+
+impl HashLife {
+    /// Create a new, empty universe that is simulated according to `rule`.
+    ///
+    /// The universe uses approximately `memory_limit` bytes at most; once
+    /// that is exceeded, the memoised results are garbage collected before
+    /// the next step.
+    ///
+    /// # Errors
+    /// Fails if the rule has more than two states or its neighbours don't fit
+    /// into a `Neighbourhood`.
+    pub fn new(rule: Rule, memory_limit: usize)
+               -> Result<HashLife, HashLifeError> {
+        if rule.states() > 2 {
+            return Err(HashLifeError::MultiState(rule.states()))
+        } else if rule.neighbours().mask().is_none() {
+            return Err(HashLifeError::Neighbourhood)
+        }
+
+        let leaf = |population| Node {
+            level: 0,
+            children: [DEAD; 4],
+            population,
+        };
+        let mut universe = HashLife {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            index: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            generation: 0,
+            max_nodes: memory_limit / Self::node_size(),
+        };
+        universe.root = universe.empty(3);
+        Ok(universe)
+    }
+
+    /// Create a new universe containing the points of a quadtree.
+    ///
+    /// # Errors
+    /// Fails if the rule isn't supported (see `new`), or if a point doesn't
+    /// fit into the universe (see `set`).
+    pub fn from_qtree<T>(rule: Rule, memory_limit: usize, tree: &QTree<T>)
+                         -> Result<HashLife, HashLifeError> {
+        let mut universe = HashLife::new(rule, memory_limit)?;
+        for point in tree.points() {
+            universe.set(point)?;
+        }
+        Ok(universe)
+    }
+
+    /// Convert the universe into a quadtree.
+    ///
+    /// Cells whose coordinates don't fit into a `Point` are left out.
+    pub fn to_qtree(&self) -> QTree {
+        let level = u32::from(self.nodes[self.root.0 as usize].level)
+            .min(Coord::BITS - 1);
+        let mut tree = QTree::new(AABB::new((0, 0), 1 << (level - 1)),
+                                  &vec![]);
+        for (x, y) in self.cells() {
+            let point = (Coord::try_from(x).ok(), Coord::try_from(y).ok());
+            if let (Some(x), Some(y)) = point {
+                tree.set((x, y));
+            }
+        }
+        tree
+    }
+
+    /// Get the coordinates of all live cells.
+    pub fn cells(&self) -> Vec<(i64, i64)> {
+        let mut cells = vec![];
+        let level = self.nodes[self.root.0 as usize].level;
+        let half = 1i64 << (level - 1);
+        self.collect_cells(self.root, -half, -half, &mut cells);
+        cells
+    }
+
+    /// Check if the cell at `point` is alive.
+    pub fn get(&self, point: Point) -> bool {
+        let (x, y) = (i64::from(point.0), i64::from(point.1));
+        let mut node = self.nodes[self.root.0 as usize];
+        let half = 1i64 << (node.level - 1);
+        if x < -half || x >= half || y < -half || y >= half {
+            return false
+        }
+        // Coordinates relative to the lower left corner of `node`
+        let (mut x, mut y) = (x + half, y + half);
+        while node.level > 0 && node.population > 0 {
+            let half = 1i64 << (node.level - 1);
+            let child = Self::quadrant(x >= half, y >= half);
+            node = self.nodes[node.children[child].0 as usize];
+            x %= half;
+            y %= half;
+        }
+        node.population > 0
+    }
+
+    /// Set the cell at `point` alive.
+    ///
+    /// # Errors
+    /// Fails (and leaves the cell dead) if the universe would have to grow
+    /// beyond `MAX_LEVEL` to contain `point`.
+    pub fn set(&mut self, point: Point) -> Result<(), HashLifeError> {
+        let (x, y) = (i64::from(point.0), i64::from(point.1));
+        loop {
+            let half = 1i64 << (self.level(self.root) - 1);
+            if -half <= x && x < half && -half <= y && y < half {
+                let root = self.root;
+                self.root = self.set_rec(root, x + half, y + half);
+                return Ok(())
+            }
+            self.expand()?;
+        }
+    }
+
+    /// Get the number of live cells.
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root.0 as usize].population
+    }
+
+    /// Get the number of generations that have been simulated so far.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Get the number of nodes that are currently stored.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Advance the universe by `2^step_log2` generations.
+    ///
+    /// # Errors
+    /// Fails (and keeps the current generation) if `step_log2` is larger
+    /// than `MAX_STEP_LOG2`, if the pattern has grown too large to be
+    /// advanced that far (see `MAX_LEVEL`), or if the generation counter
+    /// would overflow.
+    pub fn step(&mut self, step_log2: u8) -> Result<(), HashLifeError> {
+        if step_log2 > MAX_STEP_LOG2 {
+            return Err(HashLifeError::Step(step_log2))
+        }
+        let generation = self.generation.checked_add(1 << step_log2)
+            .ok_or(HashLifeError::Overflow)?;
+        if self.nodes.len() > self.max_nodes {
+            self.collect_garbage();
+        }
+
+        // Make sure the pattern is in the centre quarter of the root, so that
+        // it can't grow out of the (half as large) result.
+        loop {
+            let root = self.root;
+            let centre = self.centre(root);
+            let centre = self.centre(centre);
+            if self.level(root) >= step_log2 + 3 &&
+                self.population_of(centre) == self.population() {
+                break
+            }
+            self.expand()?;
+        }
+        let root = self.root;
+        self.root = self.advance(root, step_log2);
+        self.generation = generation;
+        Ok(())
+    }
+
+    /// Remove all nodes that aren't part of the current pattern, as well as
+    /// all memoised results that refer to them.
+    pub fn collect_garbage(&mut self) {
+        let mut reachable = vec![false; self.nodes.len()];
+        reachable[DEAD.0 as usize] = true;
+        reachable[ALIVE.0 as usize] = true;
+        let mut stack = self.empty.clone();
+        stack.push(self.root);
+        while let Some(id) = stack.pop() {
+            if !reachable[id.0 as usize] {
+                reachable[id.0 as usize] = true;
+                stack.extend(self.nodes[id.0 as usize].children.iter());
+            }
+        }
+
+        // Children are always created before their parents, so we can remap
+        // them while compacting in order
+        let mut remap = vec![None; self.nodes.len()];
+        let mut nodes = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if reachable[i] {
+                let mut node = *node;
+                if node.level > 0 {
+                    for child in node.children.iter_mut() {
+                        *child = remap[child.0 as usize].unwrap();
+                    }
+                }
+                remap[i] = Some(NodeId(nodes.len() as u32));
+                nodes.push(node);
+            }
+        }
+
+        let get = |id: NodeId| remap[id.0 as usize];
+        self.index = nodes.iter().enumerate()
+            .filter(|&(_, node)| node.level > 0)
+            .map(|(i, node)| (node.children, NodeId(i as u32)))
+            .collect();
+        self.results = self.results.iter()
+            .filter_map(|(&(node, step), &result)| {
+                match (get(node), get(result)) {
+                    (Some(node), Some(result)) => Some(((node, step), result)),
+                    _ => None,
+                }
+            })
+            .collect();
+        self.empty = self.empty.iter().map(|&id| get(id).unwrap()).collect();
+        self.root = get(self.root).unwrap();
+        self.nodes = nodes;
+    }
+
+    /// Approximate memory used by a node, including the hash tables.
+    fn node_size() -> usize {
+        size_of::<Node>() + 2 * size_of::<([NodeId; 4], NodeId)>() +
+            size_of::<((NodeId, u8), NodeId)>()
+    }
+
+    fn level(&self, id: NodeId) -> u8 {
+        self.nodes[id.0 as usize].level
+    }
+
+    fn population_of(&self, id: NodeId) -> u64 {
+        self.nodes[id.0 as usize].population
+    }
+
+    fn children(&self, id: NodeId) -> [NodeId; 4] {
+        self.nodes[id.0 as usize].children
+    }
+
+    fn quadrant(east: bool, north: bool) -> usize {
+        match (east, north) {
+            (false, true) => 0,
+            (true, true) => 1,
+            (false, false) => 2,
+            (true, false) => 3,
+        }
+    }
+
+    /// Get the level 0 node of a dead or live cell.
+    pub(crate) fn cell(alive: bool) -> NodeId {
+        if alive {
+            ALIVE
+        } else {
+            DEAD
+        }
+    }
+
+    /// Get the canonical node with the given children.
+    ///
+    /// The children must have the same level, and the total population must
+    /// fit into a `u64`.
+    pub(crate) fn join(&mut self, nw: NodeId, ne: NodeId, sw: NodeId,
+                       se: NodeId) -> NodeId {
+        let children = [nw, ne, sw, se];
+        if let Some(&id) = self.index.get(&children) {
+            return id
+        }
+        let node = Node {
+            level: self.level(nw) + 1,
+            children,
+            population: children.iter().map(|&c| self.population_of(c)).sum(),
+        };
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        let _ = self.index.insert(children, id);
+        id
+    }
+
+    /// Get the empty node of the given `level`.
+    pub(crate) fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let e = *self.empty.last().unwrap();
+            let next = self.join(e, e, e, e);
+            self.empty.push(next);
+        }
+        self.empty[level as usize]
+    }
+
+    /// Replace the pattern with the node `root` (built with `join`), which
+    /// is centred on `(0, 0)`.
+    ///
+    /// # Errors
+    /// Fails (and keeps the current pattern) if the level of `root` is
+    /// larger than `MAX_LEVEL`.
+    pub(crate) fn set_root(&mut self, root: NodeId)
+                           -> Result<(), HashLifeError> {
+        if self.level(root) > MAX_LEVEL {
+            return Err(HashLifeError::Overflow)
+        }
+        self.root = root;
+        while self.level(self.root) < 3 {
+            self.expand()?;
+        }
+        Ok(())
+    }
+
+    /// Double the size of the universe, keeping the pattern in the centre.
+    ///
+    /// # Errors
+    /// Fails (and keeps the current size) if the root is already at
+    /// `MAX_LEVEL`.
+    fn expand(&mut self) -> Result<(), HashLifeError> {
+        let root = self.root;
+        if self.level(root) >= MAX_LEVEL {
+            return Err(HashLifeError::Overflow)
+        }
+        let [nw, ne, sw, se] = self.children(root);
+        let e = self.empty(self.level(root) - 1);
+        let nw = self.join(e, e, e, nw);
+        let ne = self.join(e, e, ne, e);
+        let sw = self.join(e, sw, e, e);
+        let se = self.join(se, e, e, e);
+        self.root = self.join(nw, ne, sw, se);
+        Ok(())
+    }
+
+    /// Get the centred node of half the size.
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(id);
+        let (nw, ne) = (self.children(nw)[3], self.children(ne)[2]);
+        let (sw, se) = (self.children(sw)[1], self.children(se)[0]);
+        self.join(nw, ne, sw, se)
+    }
+
+    /// Set a cell alive, with coordinates relative to the lower left corner
+    /// of `id`.
+    fn set_rec(&mut self, id: NodeId, x: i64, y: i64) -> NodeId {
+        let level = self.level(id);
+        if level == 0 {
+            return ALIVE
+        }
+        let half = 1i64 << (level - 1);
+        let mut children = self.children(id);
+        let child = Self::quadrant(x >= half, y >= half);
+        children[child] = self.set_rec(children[child], x % half, y % half);
+        self.join(children[0], children[1], children[2], children[3])
+    }
+
+    fn collect_cells(&self, id: NodeId, x: i64, y: i64,
+                     cells: &mut Vec<(i64, i64)>) {
+        let node = self.nodes[id.0 as usize];
+        if node.population == 0 {
+            return
+        } else if node.level == 0 {
+            cells.push((x, y));
+            return
+        }
+        let half = 1i64 << (node.level - 1);
+        let [nw, ne, sw, se] = node.children;
+        self.collect_cells(nw, x, y + half, cells);
+        self.collect_cells(ne, x + half, y + half, cells);
+        self.collect_cells(sw, x, y, cells);
+        self.collect_cells(se, x + half, y, cells);
+    }
+
+    /// Compute the centred node of half the size, `2^step_log2` generations
+    /// into the future.
+    ///
+    /// `step_log2` must be at most the level of the node minus 2.
+    fn advance(&mut self, id: NodeId, step_log2: u8) -> NodeId {
+        let level = self.level(id);
+        debug_assert!(step_log2 + 2 <= level);
+        if self.population_of(id) == 0 {
+            return self.empty(level - 1)
+        }
+        if let Some(&result) = self.results.get(&(id, step_log2)) {
+            return result
+        }
+
+        let result = if level == 2 {
+            self.advance_base(id)
+        } else {
+            let [a, b, c, d] = self.children(id);
+            let [_, a_ne, a_sw, a_se] = self.children(a);
+            let [b_nw, _, b_sw, b_se] = self.children(b);
+            let [c_nw, c_ne, _, c_se] = self.children(c);
+            let [d_nw, d_ne, d_sw, _] = self.children(d);
+            // The nine overlapping nodes of half the size
+            let n = [
+                a,
+                self.join(a_ne, b_nw, a_se, b_sw),
+                b,
+                self.join(a_sw, a_se, c_nw, c_ne),
+                self.join(a_se, b_sw, c_ne, d_nw),
+                self.join(b_sw, b_se, d_nw, d_ne),
+                c,
+                self.join(c_ne, d_nw, c_se, d_sw),
+                d,
+            ];
+
+            // At full speed, both halves of the recursion advance the pattern,
+            // otherwise only the second one does.
+            let full_speed = step_log2 + 2 == level;
+            let mut r = [DEAD; 9];
+            for i in 0..9 {
+                r[i] = if full_speed {
+                    self.advance(n[i], step_log2 - 1)
+                } else {
+                    self.centre(n[i])
+                };
+            }
+            let inner_step = if full_speed { step_log2 - 1 } else { step_log2 };
+            let quadrants = [
+                self.join(r[0], r[1], r[3], r[4]),
+                self.join(r[1], r[2], r[4], r[5]),
+                self.join(r[3], r[4], r[6], r[7]),
+                self.join(r[4], r[5], r[7], r[8]),
+            ];
+            let mut s = [DEAD; 4];
+            for i in 0..4 {
+                s[i] = self.advance(quadrants[i], inner_step);
+            }
+            self.join(s[0], s[1], s[2], s[3])
+        };
+
+        let _ = self.results.insert((id, step_log2), result);
+        result
+    }
+
+    /// Advance a 4x4 node by one generation.
+    fn advance_base(&mut self, id: NodeId) -> NodeId {
+        // Rows from top to bottom
+        let mut grid = [[false; 4]; 4];
+        for (quadrant, &child) in self.children(id).iter().enumerate() {
+            for (cell, &leaf) in self.children(child).iter().enumerate() {
+                let row = (quadrant / 2) * 2 + cell / 2;
+                let col = (quadrant % 2) * 2 + cell % 2;
+                grid[row][col] = leaf == ALIVE;
+            }
+        }
+
+        let mut cells = [DEAD; 4];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let (row, col) = (1 + i / 2, 1 + i % 2);
+            let mut neighbourhood: Neighbourhood = 0;
+            for dr in 0..3 {
+                for dc in 0..3 {
+                    if grid[row + dr - 1][col + dc - 1] {
+                        neighbourhood |= 1 << (dr * 3 + dc);
+                    }
+                }
+            }
+            if self.rule.next(neighbourhood) {
+                *cell = ALIVE;
+            }
+        }
+        self.join(cells[0], cells[1], cells[2], cells[3])
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn universe(rule: &str, points: &[Point]) -> HashLife {
+        let mut universe = HashLife::new(rule.parse().unwrap(),
+                                         DEFAULT_MEMORY_LIMIT).unwrap();
+        for &point in points {
+            universe.set(point).unwrap();
+        }
+        universe
+    }
+
+    fn cells(universe: &HashLife) -> BTreeSet<(i64, i64)> {
+        universe.cells().into_iter().collect()
+    }
+
+    fn translated(points: &[Point], by: Point) -> BTreeSet<(i64, i64)> {
+        points.iter()
+            .map(|p| (i64::from(p.0 + by.0), i64::from(p.1 + by.1)))
+            .collect()
+    }
+
+    const GLIDER: [Point; 5] = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+    const R_PENTOMINO: [Point; 5] = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+
+    #[test]
+    fn set_get() {
+        let mut universe = universe("B3/S23", &[(0, 0), (-5, 3), (100, -70)]);
+        assert!(universe.get((0, 0)));
+        assert!(universe.get((-5, 3)));
+        assert!(universe.get((100, -70)));
+        assert!(!universe.get((1, 0)));
+        assert!(!universe.get((1000, 1000)));
+        assert_eq!(universe.population(), 3);
+        universe.set((0, 0)).unwrap();
+        assert_eq!(universe.population(), 3);
+        assert_eq!(cells(&universe),
+                   translated(&[(0, 0), (-5, 3), (100, -70)], (0, 0)));
+    }
+
+    #[test]
+    fn interning() {
+        let mut empty = universe("B3/S23", &[]);
+        let a = empty.join(DEAD, ALIVE, ALIVE, DEAD);
+        let b = empty.join(DEAD, ALIVE, ALIVE, DEAD);
+        assert_eq!(a, b);
+        assert_ne!(a, empty.join(ALIVE, DEAD, DEAD, ALIVE));
+
+        // Copies of a glider share all of their nodes
+        let mut universe = universe("B3/S23", &GLIDER);
+        universe.collect_garbage();
+        let nodes = universe.node_count();
+        for i in 1..16 {
+            for point in GLIDER.iter() {
+                universe.set((point.0 + 64 * i, point.1 - 64 * i)).unwrap();
+            }
+        }
+        universe.collect_garbage();
+        assert_eq!(universe.population(), 80);
+        assert!(universe.node_count() < 4 * nodes, "{} nodes",
+                universe.node_count());
+    }
+
+    #[test]
+    fn glider() {
+        let mut universe = universe("B3/S23", &GLIDER);
+        universe.step(2).unwrap();
+        assert_eq!(cells(&universe), translated(&GLIDER, (-1, -1)));
+        universe.step(10).unwrap();
+        assert_eq!(universe.generation(), 1028);
+        assert_eq!(cells(&universe), translated(&GLIDER, (-257, -257)));
+    }
+
+    #[test]
+    fn blinker() {
+        let mut universe = universe("B3/S23", &[(-1, 0), (0, 0), (1, 0)]);
+        universe.step(0).unwrap();
+        assert_eq!(cells(&universe),
+                   translated(&[(0, -1), (0, 0), (0, 1)], (0, 0)));
+        universe.step(20).unwrap();
+        assert_eq!(cells(&universe),
+                   translated(&[(0, -1), (0, 0), (0, 1)], (0, 0)));
+        universe.step(MAX_STEP_LOG2).unwrap();
+        assert_eq!(universe.generation(), (1 << MAX_STEP_LOG2) + (1 << 20) + 1);
+        assert_eq!(cells(&universe),
+                   translated(&[(0, -1), (0, 0), (0, 1)], (0, 0)));
+    }
+
+    #[test]
+    fn limits() {
+        // A glider eventually leaves the largest universe
+        let mut glider = universe("B3/S23", &GLIDER);
+        let mut steps = 0;
+        while glider.step(MAX_STEP_LOG2).is_ok() {
+            steps += 1;
+        }
+        assert!(steps > 0);
+        assert_eq!(glider.step(MAX_STEP_LOG2), Err(HashLifeError::Overflow));
+        assert_eq!(glider.generation(), steps << MAX_STEP_LOG2);
+        assert_eq!(glider.cells().len(), 5);
+
+        // ... while a blinker stays small until the generation overflows
+        let mut blinker = universe("B3/S23", &[(-1, 0), (0, 0), (1, 0)]);
+        for _ in 0..(1 << (64 - MAX_STEP_LOG2)) - 1 {
+            blinker.step(MAX_STEP_LOG2).unwrap();
+        }
+        assert_eq!(blinker.step(MAX_STEP_LOG2), Err(HashLifeError::Overflow));
+        assert_eq!(blinker.generation(), 31 << MAX_STEP_LOG2);
+        assert_eq!(blinker.step(MAX_STEP_LOG2 + 1),
+                   Err(HashLifeError::Step(MAX_STEP_LOG2 + 1)));
+
+        // Cells can only be set if the universe can grow large enough
+        let far = i64::from(Coord::MAX) >= 1 << (MAX_LEVEL - 1);
+        assert_eq!(blinker.set((Coord::MAX, 0)).is_err(), far);
+        assert_eq!(blinker.get((Coord::MAX, 0)), !far);
+    }
+
+    #[test]
+    fn r_pentomino() {
+        let mut universe = universe("B3/S23", &R_PENTOMINO);
+        // 1103 = 1024 + 64 + 8 + 4 + 2 + 1
+        for &step in [10, 6, 3, 2, 1, 0].iter() {
+            universe.step(step).unwrap();
+        }
+        assert_eq!(universe.generation(), 1103);
+        assert_eq!(universe.population(), 116);
+        universe.step(16).unwrap();
+        assert_eq!(universe.population(), 116);
+    }
+
+    #[test]
+    fn other_rules() {
+        // HighLife replicator
+        let replicator = [
+            (0, 2), (1, 2), (2, 2),
+            (-1, 1), (2, 1),
+            (-2, 0), (2, 0),
+            (-2, -1), (1, -1),
+            (-2, -2), (-1, -2), (0, -2),
+        ];
+        let mut universe = universe("B36/S23", &replicator);
+        for _ in 0..3 {
+            universe.step(2).unwrap();
+        }
+        let expected: BTreeSet<(i64, i64)> = translated(&replicator, (-2, 2))
+            .union(&translated(&replicator, (2, -2)))
+            .cloned().collect();
+        assert_eq!(cells(&universe), expected);
+
+        let rule = Rule::conway().with_states(3).unwrap();
+        assert_eq!(HashLife::new(rule, DEFAULT_MEMORY_LIMIT).err(),
+                   Some(HashLifeError::MultiState(3)));
+        let rule = "B3/S23M2".parse().unwrap();
+        assert_eq!(HashLife::new(rule, DEFAULT_MEMORY_LIMIT).err(),
+                   Some(HashLifeError::Neighbourhood));
+    }
+
+    #[test]
+    fn garbage_collection() {
+        let mut limited = HashLife::new(Rule::conway(), 0).unwrap();
+        let mut unlimited = universe("B3/S23", &R_PENTOMINO);
+        for &point in R_PENTOMINO.iter() {
+            limited.set(point).unwrap();
+        }
+        for _ in 0..20 {
+            limited.step(4).unwrap();
+            unlimited.step(4).unwrap();
+            assert_eq!(cells(&limited), cells(&unlimited));
+        }
+        assert!(limited.node_count() < unlimited.node_count());
+
+        unlimited.collect_garbage();
+        assert_eq!(unlimited.population(), limited.population());
+        unlimited.step(4).unwrap();
+        limited.step(4).unwrap();
+        assert_eq!(cells(&limited), cells(&unlimited));
+    }
+
+    #[test]
+    fn qtree_conversion() {
+        let tree = QTree::new(AABB::new((0, 0), 8), &R_PENTOMINO.to_vec());
+        let mut universe = HashLife::from_qtree(Rule::conway(),
+                                                DEFAULT_MEMORY_LIMIT, &tree)
+            .unwrap();
+        let back: BTreeSet<Point> = universe.to_qtree().into_iter().collect();
+        let expected: BTreeSet<Point> = tree.into_iter().collect();
+        assert_eq!(back, expected);
+
+        universe.step(1).unwrap();
+        assert_eq!(universe.to_qtree().into_iter().count(), 7);
+    }
+}
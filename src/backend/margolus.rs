@@ -0,0 +1,354 @@
+//! Block cellular automata on the Margolus neighbourhood.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Coord, Point};
+use super::automaton::{Automaton, Colour};
+use super::data::{ALIVE, CellMap, QTree, State};
+use super::topology::Topology;
+
+/// The offsets of the cells of a block from its bottom left cell, in the
+/// order of their bits.
+const BLOCK: [Point; 4] = [(0, 1), (1, 1), (0, 0), (1, 0)];
+
+/// The block in which all cells are alive.
+const FULL: u8 = 15;
+
+/// Errors that can occur while creating a Margolus rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MargolusError {
+    /// The rulestring isn't of the form `MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;
+    /// 14;15`.
+    Format(String),
+    /// The table doesn't map every block to a different block, so the rule
+    /// can't be run backwards.
+    NotInvertible,
+    /// The table fills empty blocks without emptying full ones, which would
+    /// fill the whole (unbounded) universe.
+    Background,
+}
+
+impl fmt::Display for MargolusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MargolusError::Format(ref rule) => {
+                write!(f, "invalid Margolus rulestring \"{}\" (expected e.g. \
+                           MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15)", rule)
+            }
+            MargolusError::NotInvertible => {
+                write!(f, "Margolus rules must map every block to a \
+                           different block")
+            }
+            MargolusError::Background => {
+                write!(f, "Margolus rules that fill empty blocks must empty \
+                           full ones")
+            }
+        }
+    }
+}
+
+impl Error for MargolusError {}
+
+/// A reversible block cellular automaton on the Margolus neighbourhood.
+///
+/// The universe is partitioned into 2x2 blocks, which are replaced according
+/// to a table of 16 entries. The bits of a block are 1 for the top left, 2
+/// for the top right, 4 for the bottom left and 8 for the bottom right cell.
+/// After every generation, the partition is shifted by one cell diagonally.
+///
+/// Rules like Critters and Tron turn empty blocks into full ones and vice
+/// versa. Like Golly, we emulate them by storing every other generation
+/// inverted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Margolus {
+    /// The table the rule was created from
+    table: [u8; 16],
+    /// The tables that are applied in even and odd generations
+    tables: [[u8; 16]; 2],
+    /// The inverses of `tables`
+    inverses: [[u8; 16]; 2],
+    /// Whether the blocks are currently anchored at odd coordinates
+    phase: bool,
+}
+
+impl Margolus {
+    /// Create a rule that replaces each block `b` by `table[b]`.
+    ///
+    /// # Errors
+    /// Fails if two blocks are mapped to the same block, or if empty blocks
+    /// are filled without emptying full ones.
+    pub fn new(table: [u8; 16]) -> Result<Margolus, MargolusError> {
+        let mut seen = [false; 16];
+        for &block in table.iter() {
+            if block > FULL || seen[block as usize] {
+                return Err(MargolusError::NotInvertible)
+            }
+            seen[block as usize] = true;
+        }
+
+        let tables = if table[0] == 0 {
+            [table, table]
+        } else if table[0] == FULL && table[FULL as usize] == 0 {
+            // Odd generations are stored inverted
+            let mut tables = [[0; 16]; 2];
+            for block in 0..16 {
+                tables[0][block] = FULL - table[block];
+                tables[1][block] = table[FULL as usize - block];
+            }
+            tables
+        } else {
+            return Err(MargolusError::Background)
+        };
+        let mut inverses = [[0; 16]; 2];
+        for (table, inverse) in tables.iter().zip(inverses.iter_mut()) {
+            for (block, &next) in table.iter().enumerate() {
+                inverse[next as usize] = block as u8;
+            }
+        }
+
+        Ok(Margolus {
+            table,
+            tables,
+            inverses,
+            phase: false,
+        })
+    }
+
+    /// Create the billiard ball machine, in which balls travel diagonally
+    /// and bounce off each other and off walls.
+    pub fn billiard_ball() -> Margolus {
+        Margolus::new([0, 8, 4, 3, 2, 5, 9, 7, 1, 6, 10, 11, 12, 13, 14, 15])
+            .unwrap()
+    }
+
+    /// Create Critters, which has gliders that travel diagonally.
+    pub fn critters() -> Margolus {
+        Margolus::new([15, 14, 13, 3, 11, 5, 6, 1, 7, 9, 10, 2, 12, 4, 8, 0])
+            .unwrap()
+    }
+
+    /// Create Tron, which inverts empty and full blocks.
+    pub fn tron() -> Margolus {
+        Margolus::new([15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 0])
+            .unwrap()
+    }
+
+    /// Get the table the rule was created from.
+    pub fn table(&self) -> [u8; 16] {
+        self.table
+    }
+
+    /// Check if the blocks are currently anchored at odd coordinates.
+    pub fn phase(&self) -> bool {
+        self.phase
+    }
+
+    /// Replace the blocks of `current` according to `table`.
+    ///
+    /// Only blocks that contain live cells are looked at, since empty blocks
+    /// stay empty. Blocks that cross a joined edge of the topology are
+    /// joined as well.
+    fn apply(table: &[u8; 16], phase: bool, topology: &Topology,
+             current: &QTree<State>) -> QTree<State> {
+        let shift = phase as Coord;
+        let anchor = |coord: Coord| ((coord - shift) & !1) + shift;
+        let blocks: BTreeSet<Point> = current.points()
+            .map(|point| (anchor(point.0), anchor(point.1)))
+            .collect();
+
+        let mut next = QTree::<State>::empty(current.boundary());
+        for corner in blocks {
+            let mut cells = [None; 4];
+            let mut block = 0;
+            for (bit, &offset) in BLOCK.iter().enumerate() {
+                cells[bit] = topology.neighbour(corner, offset)
+                    .map(|(cell, _)| cell);
+                if let Some(cell) = cells[bit] {
+                    if current.get(cell) == Some(&ALIVE) {
+                        block |= 1 << bit;
+                    }
+                }
+            }
+            let block = table[block];
+            for (bit, &cell) in cells.iter().enumerate() {
+                match cell {
+                    Some(cell) if block & 1 << bit != 0 => {
+                        next.set(cell, ALIVE)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        next
+    }
+}
+
+impl Automaton for Margolus {
+    fn name(&self) -> &'static str {
+        "Margolus"
+    }
+
+    fn states(&self) -> State {
+        2
+    }
+
+    fn palette(&self) -> Vec<Colour> {
+        vec![[0.0, 0.0, 0.0], [0.85, 0.85, 0.85]]
+    }
+
+    fn next(&mut self, topology: &Topology, current: &QTree<State>)
+            -> QTree<State> {
+        let table = &self.tables[self.phase as usize];
+        let next = Self::apply(table, self.phase, topology, current);
+        self.phase = !self.phase;
+        next
+    }
+
+    fn previous(&mut self, topology: &Topology, current: &QTree<State>)
+                -> Option<QTree<State>> {
+        self.phase = !self.phase;
+        let inverse = &self.inverses[self.phase as usize];
+        Some(Self::apply(inverse, self.phase, topology, current))
+    }
+}
+
+impl FromStr for Margolus {
+    type Err = MargolusError;
+
+    /// Parse a rulestring in MCell's notation, which lists the table:
+    /// `MS,D0;8;4;3;2;5;9;7;1;6;10;11;12;13;14;15`.
+    fn from_str(s: &str) -> Result<Margolus, MargolusError> {
+        let format_error = || MargolusError::Format(s.to_string());
+        let s = s.trim();
+        match s.get(..4) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("MS,D") => {}
+            _ => return Err(format_error()),
+        }
+        let blocks = s[4..].split(';')
+            .map(|block| block.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format_error())?;
+        if blocks.len() != 16 {
+            return Err(format_error())
+        }
+        let mut table = [0; 16];
+        table.copy_from_slice(&blocks);
+        Margolus::new(table)
+    }
+}
+
+impl fmt::Display for Margolus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blocks: Vec<String> = self.table.iter()
+            .map(|block| block.to_string())
+            .collect();
+        write!(f, "MS,D{}", blocks.join(";"))
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use backend::data::AABB;
+
+    fn tree(points: &[Point]) -> QTree<State> {
+        QTree::new(AABB::new((0, 0), 512), &points.to_vec())
+            .map(|_, _| ALIVE)
+    }
+
+    fn cells(tree: &QTree<State>) -> BTreeSet<Point> {
+        tree.points().collect()
+    }
+
+    #[test]
+    fn billiard_ball() {
+        let mut rule = Margolus::billiard_ball();
+        let mut current = tree(&[(0, 0)]);
+        for _ in 0..5 {
+            current = rule.next(&Topology::Unbounded, &current);
+        }
+        assert_eq!(cells(&current), [(5, 5)].iter().cloned().collect());
+        assert!(rule.phase());
+
+        // Two balls collide head-on and leave at right angles
+        let mut rule = Margolus::billiard_ball();
+        let mut current = tree(&[(0, 0), (5, 5)]);
+        for _ in 0..6 {
+            current = rule.next(&Topology::Unbounded, &current);
+        }
+        assert_eq!(cells(&current),
+                   [(-1, 6), (6, -1)].iter().cloned().collect());
+    }
+
+    #[test]
+    fn reversible() {
+        // Some pseudo-random soup
+        let mut seed = 4321u32;
+        let mut soup = vec![];
+        for x in 0..16 {
+            for y in 0..16 {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                if seed >> 16 & 1 == 1 {
+                    soup.push((x, y));
+                }
+            }
+        }
+        let torus = Topology::Torus(AABB::new((8, 8), 8));
+
+        for rule in &[Margolus::billiard_ball(), Margolus::critters(),
+                      Margolus::tron()] {
+            for topology in &[Topology::Unbounded, torus.clone()] {
+                let mut rule = rule.clone();
+                let start = tree(&soup);
+                let mut current = tree(&soup);
+                for _ in 0..25 {
+                    current = rule.next(topology, &current);
+                }
+                assert_ne!(cells(&current), cells(&start));
+                for _ in 0..25 {
+                    current = rule.previous(topology, &current).unwrap();
+                }
+                assert_eq!(cells(&current), cells(&start));
+                assert!(!rule.phase());
+            }
+        }
+    }
+
+    #[test]
+    fn inverted_generations() {
+        // Tron empties full blocks and fills empty ones, but the next
+        // generation is stored inverted, so the full block stays and the
+        // other blocks are inverted instead
+        let mut rule = Margolus::tron();
+        let current = tree(&[(0, 0), (1, 0), (0, 1), (1, 1), (4, 4)]);
+        let next = rule.next(&Topology::Unbounded, &current);
+        let expected = [(0, 0), (1, 0), (0, 1), (1, 1), (4, 5), (5, 4), (5, 5)];
+        assert_eq!(cells(&next), expected.iter().cloned().collect());
+    }
+
+    #[test]
+    fn parse() {
+        let rule: Margolus = "MS,D15;14;13;3;11;5;6;1;7;9;10;2;12;4;8;0"
+            .parse().unwrap();
+        assert_eq!(rule, Margolus::critters());
+        assert_eq!(rule.to_string(),
+                   "MS,D15;14;13;3;11;5;6;1;7;9;10;2;12;4;8;0");
+        assert_eq!(Margolus::billiard_ball().to_string().parse(),
+                   Ok(Margolus::billiard_ball()));
+
+        assert_eq!("MS,D0;1;2".parse::<Margolus>(),
+                   Err(MargolusError::Format("MS,D0;1;2".to_string())));
+        assert_eq!("B3/S23".parse::<Margolus>(),
+                   Err(MargolusError::Format("B3/S23".to_string())));
+        assert_eq!("MS,D0;0;2;3;4;5;6;7;8;9;10;11;12;13;14;15"
+                   .parse::<Margolus>(),
+                   Err(MargolusError::NotInvertible));
+        assert_eq!("MS,D1;0;2;3;4;5;6;7;8;9;10;11;12;13;14;15"
+                   .parse::<Margolus>(),
+                   Err(MargolusError::Background));
+    }
+}
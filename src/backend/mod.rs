@@ -1,15 +1,42 @@
 //! The actual backend (i.e., the magic happens here).
 
+extern crate num_bigint;
+
 use gui;
 use std::sync::{mpsc, Arc};
+use std::sync::mpsc::Sender;
 use std::thread;
 
+pub mod automaton;
 pub mod data;
-mod updater;
-use self::updater::Updater;
+pub mod hashlife;
+pub mod margolus;
+pub mod neighbours;
+pub mod pattern;
+pub mod rule;
+pub mod topology;
+pub mod updater;
+use self::updater::{Engine, Updater};
+
+/// An integer cell coordinate.
+///
+/// This is `i32` by default, or `i64` with the `i64-coordinates` feature, for
+/// patterns that travel far (e.g. gliders after billions of generations).
+#[cfg(not(feature = "i64-coordinates"))]
+pub type Coord = i32;
+/// An integer cell coordinate (64 bits wide with the `i64-coordinates`
+/// feature).
+#[cfg(feature = "i64-coordinates")]
+pub type Coord = i64;
 
 /// A 2D, integer point
-pub type Point = (i32, i32);
+pub type Point = (Coord, Coord);
+
+pub use self::num_bigint::BigInt;
+
+/// A 2D point whose coordinates can be arbitrarily large (e.g. the position
+/// of the camera, which may be outside of the range of `Coord`).
+pub type BigPoint = (BigInt, BigInt);
 
 /// Creates a "minimal" and "maximal" point from two points.
 ///
@@ -31,7 +58,27 @@ pub fn point_minmax(a: Point, b: Point) -> (Point, Point) {
     }
 }
 
-use self::data::{AABB, QTree};
+use self::automaton::Automaton;
+use self::data::{AABB, ALIVE, QTree, State};
+use self::rule::Rule;
+use self::topology::Topology;
+
+/// A change to a running game, from a key press or sent through
+/// `Controller::controls`.
+pub enum Control {
+    /// Switch the engine (see `Updater::set_engine`).
+    Engine(Engine),
+    /// Change the topology of the universe (see `Updater::set_topology`).
+    Topology(Topology),
+    /// Set the number of threads of the naive engine (see
+    /// `Updater::set_threads`), at least 1.
+    Threads(usize),
+    /// Simulate an automaton instead of the rule (see
+    /// `Updater::set_automaton` and `GUI::set_automaton`).
+    Automaton(Option<Box<dyn Automaton>>),
+    /// Centre the view on a cell (see `GUI::set_origin`).
+    Origin(BigPoint),
+}
 
 /// The controller glues the whole game together and controls the logic flow.
 ///
@@ -39,33 +86,56 @@ use self::data::{AABB, QTree};
 pub struct Controller {
     gui: gui::GUI,
     updater: thread::JoinHandle<()>,
+    controls: Sender<Control>,
 }
 
 impl Controller {
-    /// Constructs a new controller.
+    /// Constructs a new controller, starting with a glider.
     ///
     /// **Note:** Since we construct the GUI in here, this _must_ be called
     /// from the main thread.
     pub fn new() -> Controller {
-        let (data_send, data_recv) = mpsc::channel();
-
-        let data = Arc::new(QTree::new(
+        let data = QTree::new(
             AABB::new((0, 0), 4),
             &vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)]
-        ));
+        ).map(|_, _| ALIVE);
+        Self::with_pattern(data, Rule::default())
+    }
 
-        let gui = gui::GUI::new(data_recv);
+    /// Constructs a new controller that starts with the cells in `data`,
+    /// which evolve according to `rule`.
+    ///
+    /// **Note:** Since we construct the GUI in here, this _must_ be called
+    /// from the main thread.
+    pub fn with_pattern(data: QTree<State>, rule: Rule) -> Controller {
+        let (data_send, data_recv) = mpsc::channel();
+        let (controls, control_recv) = mpsc::channel();
+        let (updater_send, updater_recv) = mpsc::channel();
+        let (engine_send, engine_recv) = mpsc::channel();
+        let data = Arc::new(data);
+        let gui = gui::GUI::new(data_recv, &rule, control_recv,
+                                (updater_send, engine_recv));
 
         let updater = thread::spawn(|| {
-            Updater::new(data, data_send).run();
+            Updater::new(data, rule, data_send).run(updater_recv,
+                                                    engine_send);
         });
 
         Controller {
             gui,
             updater,
+            controls,
         }
     }
 
+    /// Get a sender through which the running game can be changed, e.g.
+    /// from another thread.
+    ///
+    /// The GUI applies the controls before drawing the next frame.
+    pub fn controls(&self) -> Sender<Control> {
+        self.controls.clone()
+    }
+
     /// Runs the game.
     ///
     /// **Note:** Since we poll GLFW events in here, this _must_ be called
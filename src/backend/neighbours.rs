@@ -0,0 +1,198 @@
+//! Neighbourhoods of different shapes and sizes.
+
+use std::collections::BTreeSet;
+
+use super::{Coord, Point};
+use super::rule::Neighbourhood;
+
+/// The bits of a `Neighbourhood` in the von Neumann neighbourhood.
+const VON_NEUMANN_MASK: Neighbourhood = 2 | 8 | 32 | 128;
+
+/// The bits of a `Neighbourhood` in the hexagonal neighbourhood.
+const HEXAGONAL_MASK: Neighbourhood = 1 | 2 | 8 | 32 | 128 | 256;
+
+/// The shape of a neighbourhood (and the grid it lives on).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// The eight surrounding cells of a square grid.
+    Moore,
+    /// The four orthogonally adjacent cells of a square grid.
+    VonNeumann,
+    /// The six adjacent cells of a hexagonal grid.
+    ///
+    /// The hexagonal grid is mapped onto the square grid by skewing it, so
+    /// that the cells at `(1, 1)` and `(-1, -1)` are not neighbours.
+    Hexagonal,
+    /// The twelve triangles that share at least a corner with a triangle.
+    ///
+    /// Triangles alternate between pointing up (where `x + y` is even) and
+    /// pointing down along each row.
+    Triangular,
+}
+
+/// The cells that count as the neighbours of a cell.
+///
+/// Neighbourhoods with a range larger than 1 consist of all cells that can
+/// be reached in at most `range` steps through the neighbourhood of range 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbours {
+    shape: Shape,
+    range: u8,
+}
+
+impl Neighbours {
+    /// Create a neighbourhood with a given `shape` and `range`.
+    ///
+    /// # Panics
+    /// Panics if `range` is 0.
+    pub fn new(shape: Shape, range: u8) -> Neighbours {
+        assert!(range > 0, "Neighbourhoods need a range of at least 1");
+        Neighbours {
+            shape,
+            range,
+        }
+    }
+
+    /// Get the shape of the neighbourhood.
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    /// Get the range of the neighbourhood.
+    pub fn range(&self) -> u8 {
+        self.range
+    }
+
+    /// Get the offsets of the neighbours of the cell at `point`.
+    ///
+    /// The offsets only depend on the position of the cell for triangular
+    /// neighbourhoods.
+    pub fn offsets(&self, point: Point) -> Vec<Point> {
+        let mut reached = BTreeSet::new();
+        let _ = reached.insert(point);
+        let mut frontier = vec![point];
+        for _ in 0..self.range {
+            let mut next = vec![];
+            for cell in frontier {
+                for offset in self.adjacent(cell) {
+                    let neighbour = (cell.0 + offset.0, cell.1 + offset.1);
+                    if reached.insert(neighbour) {
+                        next.push(neighbour);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        let _ = reached.remove(&point);
+        reached.into_iter()
+            .map(|cell| (cell.0 - point.0, cell.1 - point.1))
+            .collect()
+    }
+
+    /// Get the largest distance of a neighbour along either axis.
+    pub fn reach(&self) -> Coord {
+        [(0, 0), (1, 0)].iter()
+            .flat_map(|&point| self.offsets(point))
+            .map(|offset| offset.0.abs().max(offset.1.abs()))
+            .max().unwrap_or(0)
+    }
+
+    /// Get the bits of a `Neighbourhood` that belong to the neighbours, if
+    /// they all lie in the surrounding 3x3 square (regardless of position).
+    pub fn mask(&self) -> Option<Neighbourhood> {
+        if self.range > 1 {
+            return None
+        }
+        match self.shape {
+            Shape::Moore => Some(VON_NEUMANN_MASK | 1 | 4 | 64 | 256),
+            Shape::VonNeumann => Some(VON_NEUMANN_MASK),
+            Shape::Hexagonal => Some(HEXAGONAL_MASK),
+            Shape::Triangular => None,
+        }
+    }
+
+    /// The offsets of the neighbours in the neighbourhood of range 1.
+    fn adjacent(&self, point: Point) -> Vec<Point> {
+        match self.shape {
+            Shape::Moore => vec![
+                (-1,  1), (0,  1), (1,  1),
+                (-1,  0),          (1,  0),
+                (-1, -1), (0, -1), (1, -1),
+            ],
+            Shape::VonNeumann => vec![(0, 1), (-1, 0), (1, 0), (0, -1)],
+            Shape::Hexagonal => vec![
+                (-1,  1), (0,  1),
+                (-1,  0),          (1,  0),
+                          (0, -1), (1, -1),
+            ],
+            Shape::Triangular => {
+                // The long side is at the bottom of triangles pointing up
+                let up = (point.0 + point.1) % 2 == 0;
+                let (long, short) = if up { (-1, 1) } else { (1, -1) };
+                let mut offsets = vec![(-2, 0), (-1, 0), (1, 0), (2, 0)];
+                offsets.extend((-2..3).map(|x| (x, long)));
+                offsets.extend((-1..2).map(|x| (x, short)));
+                offsets
+            }
+        }
+    }
+}
+
+impl Default for Neighbours {
+    fn default() -> Neighbours {
+        Neighbours::new(Shape::Moore, 1)
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use backend::rule::Rule;
+
+    fn count(shape: Shape, range: u8) -> usize {
+        Neighbours::new(shape, range).offsets((0, 0)).len()
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(count(Shape::Moore, 1), 8);
+        assert_eq!(count(Shape::Moore, 2), 24);
+        assert_eq!(count(Shape::VonNeumann, 1), 4);
+        assert_eq!(count(Shape::VonNeumann, 2), 12);
+        assert_eq!(count(Shape::Hexagonal, 1), 6);
+        assert_eq!(count(Shape::Hexagonal, 2), 18);
+        assert_eq!(count(Shape::Triangular, 1), 12);
+    }
+
+    #[test]
+    fn triangular() {
+        let neighbours = Neighbours::new(Shape::Triangular, 1);
+        let up = neighbours.offsets((0, 0));
+        assert!(up.contains(&(0, -1)));
+        assert!(up.contains(&(2, -1)));
+        assert!(up.contains(&(1, 1)));
+        assert!(!up.contains(&(2, 1)));
+        let down = neighbours.offsets((1, 0));
+        assert!(down.contains(&(2, 1)));
+        assert!(!down.contains(&(2, -1)));
+        // Neighbourhoods are symmetric
+        for &(x, y) in up.iter() {
+            assert!(neighbours.offsets((x, y)).contains(&(-x, -y)));
+        }
+        assert_eq!(neighbours.reach(), 2);
+    }
+
+    #[test]
+    fn masks() {
+        for &shape in &[Shape::Moore, Shape::VonNeumann, Shape::Hexagonal] {
+            let neighbours = Neighbours::new(shape, 1);
+            let mask = neighbours.offsets((0, 0)).into_iter()
+                .fold(0, |mask, offset| mask | Rule::neighbour_bit(offset));
+            assert_eq!(neighbours.mask(), Some(mask));
+            assert_eq!(neighbours.reach(), 1);
+        }
+        assert_eq!(Neighbours::new(Shape::Moore, 2).mask(), None);
+        assert_eq!(Neighbours::new(Shape::Moore, 3).reach(), 3);
+    }
+}
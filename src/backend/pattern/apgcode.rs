@@ -0,0 +1,334 @@
+//! apgcodes, the names under which apgsearch and Catagolue catalogue objects
+//! (e.g. `xs4_33` for the block).
+//!
+//! An apgcode consists of a prefix with the kind of the object and its
+//! population or period, followed by its cells in extended Wechsler format:
+//! The object is split into strips of 5 rows, which are separated by `z`.
+//! Each column of a strip is written as one of the characters `0` to `v`,
+//! whose value has a bit for each live cell, with the top one being the
+//! lowest. `w` and `x` stand for 2 and 3 empty columns, `y` followed by `0`
+//! to `z` for 4 to 39 of them.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+
+use super::tree;
+use backend::{Coord, Point};
+use backend::data::{ALIVE, QTree, State};
+use backend::hashlife::{DEFAULT_MEMORY_LIMIT, HashLife, HashLifeError};
+use backend::rule::Rule;
+
+/// The characters for the values of columns and lengths of runs.
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// The largest number of empty columns a single `y` run stands for.
+const MAX_RUN: usize = 39;
+
+/// Errors that can occur when computing or decoding an apgcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApgcodeError {
+    /// The pattern doesn't return to its original shape within the given
+    /// number of generations.
+    NotPeriodic(u32),
+    /// The rule (or the pattern) can't be simulated with Hashlife.
+    Rule(HashLifeError),
+    /// The code doesn't start with `xs`, `xp` or `xq`, a number and `_`.
+    Prefix,
+    /// The code contains an invalid character.
+    UnexpectedCharacter(char),
+    /// The code ends with a `y`.
+    Truncated,
+}
+
+impl fmt::Display for ApgcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApgcodeError::NotPeriodic(generations) => {
+                write!(f, "the pattern doesn't repeat within {} generations",
+                       generations)
+            }
+            ApgcodeError::Rule(ref error) => write!(f, "{}", error),
+            ApgcodeError::Prefix => {
+                write!(f, "invalid prefix (expected e.g. xs4_)")
+            }
+            ApgcodeError::UnexpectedCharacter(c) => {
+                write!(f, "unexpected character '{}'", c)
+            }
+            ApgcodeError::Truncated => write!(f, "unexpected end of code"),
+        }
+    }
+}
+
+impl Error for ApgcodeError {}
+
+/// Compute the apgcode of the object `cells` under `rule`.
+///
+/// Still lifes are named `xs<population>_...`, oscillators `xp<period>_...`
+/// and spaceships `xq<period>_...`. Of all phases and orientations, the one
+/// with the shortest (and then alphabetically first) representation is
+/// used. Cells in any state but 0 count as alive.
+///
+/// # Errors
+/// Fails if the rule or the pattern isn't supported by Hashlife, or if the
+/// pattern doesn't return to its original shape within `max_period`
+/// generations.
+pub fn apgcode(cells: &QTree<State>, rule: &Rule, max_period: u32)
+               -> Result<String, ApgcodeError> {
+    let mut universe = HashLife::new(rule.clone(), DEFAULT_MEMORY_LIMIT)
+        .map_err(ApgcodeError::Rule)?;
+    for point in cells.points() {
+        universe.set(point).map_err(ApgcodeError::Rule)?;
+    }
+
+    let (first, origin) = normalise(cells.points());
+    let mut phases = vec![];
+    for period in 1..max_period + 1 {
+        universe.step(0).map_err(ApgcodeError::Rule)?;
+        let (phase, corner) = normalise(universe.to_qtree().points());
+        if phase == first {
+            phases.push(phase);
+            let code = shortest(phases.iter().map(canonical_cells));
+            return Ok(match (period, corner == origin) {
+                (1, true) => format!("xs{}_{}", first.len(), code),
+                (_, true) => format!("xp{}_{}", period, code),
+                (_, false) => format!("xq{}_{}", period, code),
+            })
+        }
+        phases.push(phase);
+    }
+    Err(ApgcodeError::NotPeriodic(max_period))
+}
+
+/// Encode the cells of a single phase of an object in extended Wechsler
+/// format (i.e. an apgcode without its prefix), in the orientation with the
+/// shortest (and then alphabetically first) representation.
+pub fn canonical(cells: &QTree<State>) -> String {
+    canonical_cells(&normalise(cells.points()).0)
+}
+
+/// Decode an apgcode (or just the part after its prefix) into the cells of
+/// the object, with its leftmost column at x = 0 and its top row at y = 0.
+///
+/// # Errors
+/// Fails if the prefix isn't `xs`, `xp` or `xq` followed by a number and
+/// `_`, or if the cells contain an invalid character.
+pub fn decode(code: &str) -> Result<QTree<State>, ApgcodeError> {
+    let cells = match code.find('_') {
+        Some(index) => {
+            let (kind, number) = code[..index].split_at(index.min(2));
+            if !["xs", "xp", "xq"].contains(&kind) ||
+                number.parse::<u32>().is_err() {
+                return Err(ApgcodeError::Prefix)
+            }
+            &code[index + 1..]
+        }
+        None => code,
+    };
+
+    let value = |c: char| {
+        DIGITS.iter().position(|&digit| digit as char == c)
+            .ok_or(ApgcodeError::UnexpectedCharacter(c))
+    };
+    let mut points = vec![];
+    let (mut x, mut strip) = (0, 0);
+    let mut chars = cells.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            'w' => x += 2,
+            'x' => x += 3,
+            'y' => {
+                let run = chars.next().ok_or(ApgcodeError::Truncated)?;
+                x += 4 + value(run)? as Coord;
+            }
+            'z' => {
+                x = 0;
+                strip += 1;
+            }
+            _ => {
+                let column = value(c)?;
+                for row in 0..5 {
+                    if column & (1 << row) != 0 {
+                        points.push(((x, -(5 * strip + row)), ALIVE));
+                    }
+                }
+                x += 1;
+            }
+        }
+    }
+    Ok(tree(points))
+}
+
+/// Convert `points` to coordinates with y pointing down, relative to the
+/// top left corner of their bounding box. Returns them and that corner.
+fn normalise<I>(points: I) -> (BTreeSet<Point>, Point)
+    where I: IntoIterator<Item = Point> {
+    let points: Vec<Point> = points.into_iter().map(|(x, y)| (x, -y))
+        .collect();
+    let left = points.iter().map(|p| p.0).min().unwrap_or(0);
+    let top = points.iter().map(|p| p.1).min().unwrap_or(0);
+    let points = points.iter().map(|&(x, y)| (x - left, y - top)).collect();
+    (points, (left, top))
+}
+
+/// Encode normalised `cells` in the orientation with the shortest
+/// representation.
+fn canonical_cells(cells: &BTreeSet<Point>) -> String {
+    shortest((0..8).map(|symmetry| {
+        let transformed = cells.iter().map(|&(x, y)| {
+            let (x, y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+            let x = if symmetry & 1 != 0 { -x } else { x };
+            let y = if symmetry & 2 != 0 { -y } else { y };
+            // `normalise` flips y back, which is fine since we try both
+            (x, -y)
+        });
+        wechsler(&normalise(transformed).0)
+    }))
+}
+
+/// Get the shortest and then alphabetically first of `codes`.
+fn shortest<I: Iterator<Item = String>>(codes: I) -> String {
+    codes.min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .unwrap_or_default()
+}
+
+/// Encode normalised `cells` in extended Wechsler format.
+fn wechsler(cells: &BTreeSet<Point>) -> String {
+    if cells.is_empty() {
+        return "0".to_string()
+    }
+    let width = cells.iter().map(|p| p.0).max().unwrap_or(0) + 1;
+    let height = cells.iter().map(|p| p.1).max().unwrap_or(0) + 1;
+    let mut code = String::new();
+    for strip in 0..(height + 4) / 5 {
+        if strip > 0 {
+            code.push('z');
+        }
+        let mut zeroes = 0;
+        for x in 0..width {
+            let column: usize = (0..5)
+                .filter(|&row| cells.contains(&(x, 5 * strip + row)))
+                .map(|row| 1 << row)
+                .sum();
+            if column == 0 {
+                zeroes += 1;
+                continue
+            }
+            while zeroes > 0 {
+                let run = zeroes.min(MAX_RUN);
+                match run {
+                    1 => code.push('0'),
+                    2 => code.push('w'),
+                    3 => code.push('x'),
+                    _ => {
+                        code.push('y');
+                        code.push(DIGITS[run - 4] as char);
+                    }
+                }
+                zeroes -= run;
+            }
+            code.push(DIGITS[column] as char);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+
+    /// Create a pattern from rows of `.` and `o`.
+    fn pattern(rows: &[&str]) -> QTree<State> {
+        let mut cells = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == 'o' {
+                    cells.push(((x as Coord, -(y as Coord)), ALIVE));
+                }
+            }
+        }
+        tree(cells)
+    }
+
+    fn code(rows: &[&str]) -> String {
+        apgcode(&pattern(rows), &Rule::conway(), 100).unwrap()
+    }
+
+    #[test]
+    fn still_lifes() {
+        assert_eq!(code(&["oo", "oo"]), "xs4_33");
+        assert_eq!(code(&[".oo.", "o..o", ".oo."]), "xs6_696");
+        assert_eq!(code(&["oo.", "o.o", ".o."]), "xs5_253");
+        assert_eq!(code(&[".oo.", "o..o", ".o.o", "..o."]), "xs7_2596");
+        assert_eq!(code(&[".o.", "o.o", ".o."]), "xs4_252");
+        assert_eq!(code(&[".oo.", "o..o", "o..o", ".oo."]), "xs8_6996");
+        assert_eq!(code(&["oo", "oo", "", "", "", "", "", "oo", "oo"]),
+                   "xs8_33zcc");
+        assert_eq!(code(&[]), "xs0_0");
+    }
+
+    #[test]
+    fn oscillators() {
+        assert_eq!(code(&["ooo"]), "xp2_7");
+        assert_eq!(code(&[".ooo", "ooo."]), "xp2_7e");
+        assert_eq!(code(&["oo..", "o...", "...o", "..oo"]), "xp2_318c");
+    }
+
+    #[test]
+    fn spaceships() {
+        assert_eq!(code(&[".o.", "..o", "ooo"]), "xq4_153");
+        assert_eq!(code(&[".o..o", "o....", "o...o", "oooo."]), "xq4_6frc");
+    }
+
+    #[test]
+    fn classification_errors() {
+        let r_pentomino = pattern(&[".oo", "oo.", ".o."]);
+        assert_eq!(apgcode(&r_pentomino, &Rule::conway(), 50),
+                   Err(ApgcodeError::NotPeriodic(50)));
+        let rule = Rule::conway().with_states(3).unwrap();
+        assert_eq!(apgcode(&r_pentomino, &rule, 50),
+                   Err(ApgcodeError::Rule(HashLifeError::MultiState(3))));
+    }
+
+    #[test]
+    fn runs() {
+        // 40 empty columns take two runs
+        let cells = pattern(&["o........................................o"]);
+        assert_eq!(canonical(&cells), "1yz01");
+        let expected: BTreeSet<Point> = [(0, 0), (41, 0)].iter().cloned()
+            .collect();
+        assert_eq!(decode("1yz01").unwrap().points().collect::<BTreeSet<_>>(),
+                   expected);
+
+        let expected: BTreeSet<Point> = [(0, 0), (3, 0), (7, -1), (12, -4),
+                                         (0, -5)].iter().cloned().collect();
+        assert_eq!(decode("1w1x2y0gz1").unwrap().points()
+                   .collect::<BTreeSet<_>>(), expected);
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let codes = ["xs4_33", "xs6_696", "xs8_33zcc", "xp2_7", "xp2_318c",
+                     "xq4_153", "xq4_6frc", "xs0_0"];
+        for &code in codes.iter() {
+            let cells = decode(code).unwrap();
+            assert_eq!(apgcode(&cells, &Rule::conway(), 100).unwrap(), code);
+        }
+
+        let glider: BTreeSet<Point> = [(0, 0), (1, 0), (2, 0), (2, -1),
+                                       (1, -2)].iter().cloned().collect();
+        assert_eq!(decode("xq4_153").unwrap().points()
+                   .collect::<BTreeSet<_>>(), glider);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(decode("xs4_3!").unwrap_err(),
+                   ApgcodeError::UnexpectedCharacter('!'));
+        assert_eq!(decode("xs4_1y").unwrap_err(), ApgcodeError::Truncated);
+        assert_eq!(decode("yl144_1").unwrap_err(), ApgcodeError::Prefix);
+        assert_eq!(decode("xs_33").unwrap_err(), ApgcodeError::Prefix);
+        assert_eq!(decode("_33").unwrap_err(), ApgcodeError::Prefix);
+    }
+}
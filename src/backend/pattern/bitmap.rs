@@ -0,0 +1,212 @@
+//! Importing patterns from images (PNG, BMP, GIF and the other formats the
+//! `image` crate can read), e.g. ones that were sketched in an image editor.
+
+extern crate image;
+
+use std::path::Path;
+
+use self::image::{DynamicImage, ImageResult};
+use super::tree;
+use backend::Coord;
+use backend::data::{ALIVE, QTree, State};
+
+/// The default threshold, halfway between black and white.
+pub const DEFAULT_THRESHOLD: u8 = 128;
+
+/// Check if `bytes` are the contents of an image file in a format that can
+/// be imported.
+pub fn is_image(bytes: &[u8]) -> bool {
+    image::guess_format(bytes).is_ok()
+}
+
+/// Converts images into patterns.
+///
+/// The image is divided into square blocks of pixels, each of which becomes
+/// one cell. The top left block is at `(0, 0)`. By default, each pixel is a
+/// cell, which is alive if it is darker than `DEFAULT_THRESHOLD` (i.e. the
+/// pattern is drawn in black on white).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitmapImport {
+    threshold: u8,
+    invert: bool,
+    cell_size: u32,
+}
+
+impl BitmapImport {
+    /// Create an importer with the default settings.
+    pub fn new() -> BitmapImport {
+        BitmapImport {
+            threshold: DEFAULT_THRESHOLD,
+            invert: false,
+            cell_size: 1,
+        }
+    }
+
+    /// Make cells alive if the average brightness of their pixels is below
+    /// `threshold` (or at least `threshold` if inverted).
+    pub fn with_threshold(self, threshold: u8) -> BitmapImport {
+        BitmapImport {
+            threshold,
+            ..self
+        }
+    }
+
+    /// Make bright cells alive instead of dark ones (or not).
+    pub fn with_invert(self, invert: bool) -> BitmapImport {
+        BitmapImport {
+            invert,
+            ..self
+        }
+    }
+
+    /// Make each cell `cell_size` pixels wide and high.
+    ///
+    /// Blocks at the right and bottom edges of images whose size isn't a
+    /// multiple of `cell_size` are smaller.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is 0.
+    pub fn with_cell_size(self, cell_size: u32) -> BitmapImport {
+        assert!(cell_size > 0, "Cells have to be at least one pixel wide");
+        BitmapImport {
+            cell_size,
+            ..self
+        }
+    }
+
+    /// Read the image file at `path` and convert it.
+    ///
+    /// # Errors
+    /// Fails if the file can't be read or isn't a supported image.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> ImageResult<QTree<State>> {
+        Ok(self.convert(&image::open(path)?))
+    }
+
+    /// Convert the contents of an image file.
+    ///
+    /// # Errors
+    /// Fails if `bytes` aren't a supported image.
+    pub fn load(&self, bytes: &[u8]) -> ImageResult<QTree<State>> {
+        Ok(self.convert(&image::load_from_memory(bytes)?))
+    }
+
+    /// Convert an image.
+    ///
+    /// Transparent pixels count as white.
+    pub fn convert(&self, image: &DynamicImage) -> QTree<State> {
+        let image = image.to_luma_alpha();
+        let (width, height) = image.dimensions();
+        let mut cells = vec![];
+        for row in 0..height.div_ceil(self.cell_size) {
+            for column in 0..width.div_ceil(self.cell_size) {
+                let (mut sum, mut count) = (0u64, 0u64);
+                let top = row * self.cell_size;
+                let left = column * self.cell_size;
+                for y in top..(top + self.cell_size).min(height) {
+                    for x in left..(left + self.cell_size).min(width) {
+                        let [luma, alpha] = image.get_pixel(x, y).data;
+                        let (luma, alpha) = (luma as u64, alpha as u64);
+                        sum += (luma * alpha + 255 * (255 - alpha)) / 255;
+                        count += 1;
+                    }
+                }
+                let dark = sum < self.threshold as u64 * count;
+                if dark != self.invert {
+                    cells.push(((column as Coord, -(row as Coord)), ALIVE));
+                }
+            }
+        }
+        tree(cells)
+    }
+}
+
+impl Default for BitmapImport {
+    fn default() -> BitmapImport {
+        BitmapImport::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use super::image::{GrayAlphaImage, LumaA};
+    use backend::Point;
+
+    const GLIDER_PNG: &[u8] =
+        include_bytes!("../../../resource/fixtures/glider.png");
+    const GLIDER_BMP: &[u8] =
+        include_bytes!("../../../resource/fixtures/glider.bmp");
+    const GLIDER_GIF: &[u8] =
+        include_bytes!("../../../resource/fixtures/glider.gif");
+    /// 2x2 blocks of 4x4 pixels in black, dark grey, light grey and white
+    const SHADES_PNG: &[u8] =
+        include_bytes!("../../../resource/fixtures/shades.png");
+
+    fn points(cells: &QTree<State>) -> BTreeSet<Point> {
+        cells.points().collect()
+    }
+
+    #[test]
+    fn formats() {
+        let expected: BTreeSet<Point> = [(2, -1), (3, -2), (1, -3), (2, -3),
+                                         (3, -3)].iter().cloned().collect();
+        for &bytes in [GLIDER_PNG, GLIDER_BMP, GLIDER_GIF].iter() {
+            assert!(is_image(bytes));
+            let cells = BitmapImport::new().load(bytes).unwrap();
+            assert_eq!(points(&cells), expected);
+        }
+        assert!(!is_image(b"x = 3, y = 3\nbo$2bo$3o!"));
+        assert!(BitmapImport::new().load(b"#Life 1.06\n0 0\n").is_err());
+    }
+
+    #[test]
+    fn threshold() {
+        let import = BitmapImport::new().with_cell_size(4);
+        let cells = import.clone().load(SHADES_PNG).unwrap();
+        let expected: BTreeSet<Point> = [(0, 0), (1, 0)].iter().cloned()
+            .collect();
+        assert_eq!(points(&cells), expected);
+
+        let cells = import.clone().with_threshold(200).load(SHADES_PNG)
+            .unwrap();
+        let expected: BTreeSet<Point> = [(0, 0), (1, 0), (0, -1)].iter()
+            .cloned().collect();
+        assert_eq!(points(&cells), expected);
+
+        let cells = import.with_invert(true).load(SHADES_PNG).unwrap();
+        let expected: BTreeSet<Point> = [(0, -1), (1, -1)].iter().cloned()
+            .collect();
+        assert_eq!(points(&cells), expected);
+
+        // Every pixel on its own
+        let cells = BitmapImport::new().load(SHADES_PNG).unwrap();
+        assert_eq!(cells.iter().count(), 2 * 16 - 1);
+    }
+
+    #[test]
+    fn downsampling() {
+        // A 5x5 glider in 2x2 blocks, of which the ones at the edges are
+        // smaller
+        let cells = BitmapImport::new().with_cell_size(2).load(GLIDER_PNG)
+            .unwrap();
+        let expected: BTreeSet<Point> = [(1, -1)].iter().cloned().collect();
+        assert_eq!(points(&cells), expected);
+        let cells = BitmapImport::new().with_cell_size(2).with_threshold(200)
+            .load(GLIDER_PNG).unwrap();
+        let expected: BTreeSet<Point> = [(1, 0), (0, -1), (1, -1)].iter()
+            .cloned().collect();
+        assert_eq!(points(&cells), expected);
+    }
+
+    #[test]
+    fn transparency() {
+        let mut image = GrayAlphaImage::from_pixel(2, 1, LumaA([0, 0]));
+        image.put_pixel(1, 0, LumaA([0, 255]));
+        let cells = BitmapImport::new()
+            .convert(&DynamicImage::ImageLumaA8(image));
+        let expected: BTreeSet<Point> = [(1, 0)].iter().cloned().collect();
+        assert_eq!(points(&cells), expected);
+    }
+}
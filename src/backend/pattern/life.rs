@@ -0,0 +1,303 @@
+//! The Life 1.05 and Life 1.06 formats.
+//!
+//! Life 1.05 files consist of blocks of cells drawn as text, each of which
+//! starts with the position of its top left cell:
+//!
+//! ```text
+//! #Life 1.05
+//! #D Glider
+//! #N
+//! #P -1 -1
+//! .*
+//! ..*
+//! ***
+//! ```
+//!
+//! Life 1.06 files just list the coordinates of all live cells:
+//!
+//! ```text
+//! #Life 1.06
+//! 0 -1
+//! 1 0
+//! -1 1
+//! 0 1
+//! 1 1
+//! ```
+//!
+//! In both formats, the y axis points down.
+
+use std::convert::TryFrom;
+
+use super::{cell, coordinates, rows, tree, Metadata, ParseError,
+            ParseErrorKind, Pattern};
+use backend::Coord;
+use backend::data::{ALIVE, QTree, State};
+
+/// The header of Life 1.05 files.
+const HEADER_105: &str = "#Life 1.05";
+
+/// The header of Life 1.06 files.
+const HEADER_106: &str = "#Life 1.06";
+
+/// The maximum number of cells per line in a Life 1.05 file.
+const LINE_LENGTH: Coord = 80;
+
+/// Check that the first line of `input` is `header`.
+fn check_header(input: &str, header: &str) -> Result<(), ParseError> {
+    match input.lines().next() {
+        Some(line) if line.trim_end() == header => Ok(()),
+        _ => Err(ParseError::new(1, 1, ParseErrorKind::MissingHeader)),
+    }
+}
+
+/// Parse a pattern in Life 1.05 format.
+///
+/// `#D` lines are comments, `#R` gives the rule (`#N` stands for Conway's
+/// Game of Life). Dead cells are written as `.` and live cells as `*`.
+///
+/// # Errors
+/// Fails if the header or a position is malformed, or if the pattern
+/// contains an invalid character or cells outside of the supported
+/// coordinates.
+pub fn parse_105(input: &str) -> Result<Pattern, ParseError> {
+    check_header(input, HEADER_105)?;
+    let mut metadata = Metadata::default();
+    let mut cells = vec![];
+    let mut position = (0, 0);
+    let mut row = 0;
+    for (number, line) in input.lines().enumerate().skip(1) {
+        let line = line.trim_end();
+        if line.starts_with('#') {
+            let tag = line.get(..2).unwrap_or(line);
+            let text = line.get(2..).unwrap_or("").trim();
+            match tag {
+                "#D" => metadata.comments.push(text.to_string()),
+                "#N" => metadata.rule = None,
+                "#R" => metadata.rule = Some(text.to_string()),
+                "#P" => {
+                    position = coordinates(text).ok_or_else(|| {
+                        let kind = ParseErrorKind::Position;
+                        ParseError::new(number + 1, 3, kind)
+                    })?;
+                    row = 0;
+                }
+                _ => {}
+            }
+            continue
+        }
+        for (index, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                '*' => {
+                    let point = Coord::try_from(index).ok()
+                        .and_then(|x| cell(position, (x, row)))
+                        .ok_or_else(|| {
+                            let kind = ParseErrorKind::OutOfRange;
+                            ParseError::new(number + 1, index + 1, kind)
+                        })?;
+                    cells.push((point, ALIVE));
+                }
+                _ => {
+                    let kind = ParseErrorKind::UnexpectedCharacter(c);
+                    return Err(ParseError::new(number + 1, index + 1, kind))
+                }
+            }
+        }
+        row += 1;
+    }
+
+    Ok(Pattern {
+        cells: tree(cells),
+        metadata,
+    })
+}
+
+/// Write `cells` in Life 1.05 format.
+///
+/// The comments and rule are taken from `metadata`. All cells that aren't
+/// dead are written as `*`. Patterns that are wider than 80 cells are split
+/// into several blocks.
+pub fn write_105(cells: &QTree<State>, metadata: &Metadata) -> String {
+    let mut out = format!("{}\n", HEADER_105);
+    for comment in &metadata.comments {
+        out.push_str(&format!("#D {}\n", comment));
+    }
+    match metadata.rule {
+        Some(ref rule) => out.push_str(&format!("#R {}\n", rule)),
+        None => out.push_str("#N\n"),
+    }
+
+    let (min, max) = match cells.bounding_box() {
+        Some(bounding_box) => bounding_box,
+        None => return out,
+    };
+    let rows = rows(cells);
+    let mut left = min.0;
+    while left <= max.0 {
+        let right = left.saturating_add(LINE_LENGTH);
+        let block: Vec<(Coord, Vec<Coord>)> = rows.iter()
+            .map(|(&y, row)| (y, row.range(left..right).map(|(&x, _)| x)
+                              .collect::<Vec<_>>()))
+            .filter(|(_, row)| !row.is_empty())
+            .collect();
+        if let (Some(first), Some(last)) = (block.first(), block.last()) {
+            out.push_str(&format!("#P {} {}\n", left, first.0));
+            let mut block = block.iter().peekable();
+            for y in first.0..last.0 + 1 {
+                let mut x = left;
+                if block.peek().map(|&&(row, _)| row) == Some(y) {
+                    for &cell in &block.next().unwrap().1 {
+                        for _ in x..cell {
+                            out.push('.');
+                        }
+                        out.push('*');
+                        x = cell + 1;
+                    }
+                } else {
+                    out.push('.');
+                }
+                out.push('\n');
+            }
+        }
+        left = right;
+    }
+    out
+}
+
+/// Parse a pattern in Life 1.06 format.
+///
+/// Lines after the header that start with `#` are ignored.
+///
+/// # Errors
+/// Fails if the header or a line of coordinates is malformed, or if a cell
+/// is outside of the supported coordinates.
+pub fn parse_106(input: &str) -> Result<Pattern, ParseError> {
+    check_header(input, HEADER_106)?;
+    let mut cells = vec![];
+    for (number, line) in input.lines().enumerate().skip(1) {
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue
+        }
+        let column = line.len() - line.trim_start().len() + 1;
+        let point = coordinates(text).ok_or_else(|| {
+            ParseError::new(number + 1, column, ParseErrorKind::Coordinates)
+        })?;
+        let point = cell((0, 0), point).ok_or_else(|| {
+            ParseError::new(number + 1, column, ParseErrorKind::OutOfRange)
+        })?;
+        cells.push((point, ALIVE));
+    }
+
+    Ok(Pattern {
+        cells: tree(cells),
+        metadata: Metadata::default(),
+    })
+}
+
+/// Write the coordinates of all `cells` that aren't dead in Life 1.06
+/// format.
+pub fn write_106(cells: &QTree<State>) -> String {
+    let mut out = format!("{}\n", HEADER_106);
+    for (y, row) in rows(cells) {
+        for x in row.keys() {
+            out.push_str(&format!("{} {}\n", x, y));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use backend::Point;
+    use backend::data::{AABB, CellMap};
+
+    fn cells(pattern: &Pattern) -> BTreeSet<Point> {
+        pattern.cells.points().collect()
+    }
+
+    fn glider() -> BTreeSet<Point> {
+        [(0, 1), (1, 0), (-1, -1), (0, -1), (1, -1)].iter().cloned().collect()
+    }
+
+    #[test]
+    fn life_105() {
+        let input = "#Life 1.05\n\
+                     #D Glider\n\
+                     #N\n\
+                     #P -1 -1\n\
+                     .*\n\
+                     ..*\n\
+                     ***\n";
+        let pattern = parse_105(input).unwrap();
+        assert_eq!(cells(&pattern), glider());
+        assert_eq!(pattern.metadata.comments, vec!["Glider".to_string()]);
+        assert_eq!(pattern.metadata.rule, None);
+        assert_eq!(write_105(&pattern.cells, &pattern.metadata), input);
+
+        // Several blocks, with a rule
+        let pattern = parse_105("#Life 1.05\n#R 23/36\n#P 0 0\n**\n\n.*\n\
+                                 #P 10 -5\n*\n").unwrap();
+        let expected: BTreeSet<Point> = [(0, 0), (1, 0), (1, -2), (10, 5)]
+            .iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata.rule, Some("23/36".to_string()));
+    }
+
+    #[test]
+    fn life_105_wide() {
+        let mut tree = QTree::<State>::empty(AABB::new((0, 0), 128));
+        for x in 0..100 {
+            tree.set((x, -x % 3), ALIVE);
+        }
+        let output = write_105(&tree, &Metadata::default());
+        assert!(output.lines().all(|line| line.len() <= LINE_LENGTH as usize));
+        assert_eq!(output.lines().filter(|line| line.starts_with("#P"))
+                   .collect::<Vec<_>>(), vec!["#P 0 0", "#P 80 0"]);
+        let pattern = parse_105(&output).unwrap();
+        assert_eq!(pattern.cells.points().collect::<BTreeSet<_>>(),
+                   tree.points().collect());
+    }
+
+    #[test]
+    fn life_106() {
+        let input = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let pattern = parse_106(input).unwrap();
+        assert_eq!(cells(&pattern), glider());
+        assert_eq!(write_106(&pattern.cells), input);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_105("#Life 1.06\n").unwrap_err(),
+                   ParseError::new(1, 1, ParseErrorKind::MissingHeader));
+        assert_eq!(parse_105("#Life 1.05\n#P 1 x\n").unwrap_err(),
+                   ParseError::new(2, 3, ParseErrorKind::Position));
+        assert_eq!(parse_105("#Life 1.05\n.*O\n").unwrap_err(),
+                   ParseError::new(2, 3,
+                                   ParseErrorKind::UnexpectedCharacter('O')));
+        assert_eq!(parse_106("").unwrap_err(),
+                   ParseError::new(1, 1, ParseErrorKind::MissingHeader));
+        assert_eq!(parse_106("#Life 1.06\n0 1\n  2 3 4\n").unwrap_err(),
+                   ParseError::new(3, 3, ParseErrorKind::Coordinates));
+
+        // Coordinates that fit into a `Coord`, but not into a quadtree
+        let input = format!("#Life 1.06\n0 0\n {} 0\n", Coord::MAX);
+        assert_eq!(parse_106(&input).unwrap_err(),
+                   ParseError::new(3, 2, ParseErrorKind::OutOfRange));
+        let input = format!("#Life 1.06\n0 {}\n", Coord::MIN);
+        assert_eq!(parse_106(&input).unwrap_err(),
+                   ParseError::new(2, 1, ParseErrorKind::OutOfRange));
+        let input = format!("#Life 1.06\n{} {}\n", Coord::MAX - 1,
+                            Coord::MAX);
+        let points: Vec<Point> = parse_106(&input).unwrap().cells.points()
+            .collect();
+        assert_eq!(points, vec![(Coord::MAX - 1, -Coord::MAX)]);
+        let input = format!("#Life 1.05\n#P {} 0\n.**\n", Coord::MAX - 1);
+        assert_eq!(parse_105(&input).unwrap_err(),
+                   ParseError::new(3, 2, ParseErrorKind::OutOfRange));
+    }
+}
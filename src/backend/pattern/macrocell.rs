@@ -0,0 +1,648 @@
+//! Golly's macrocell format (`.mc`), which stores the quadtree of a pattern
+//! with identical subtrees only written once.
+//!
+//! ```text
+//! [M2] (golly 2.0)
+//! #R B3/S23
+//! .*$..*$***$
+//! 4 0 0 0 1
+//! ```
+//!
+//! Every line after the header describes a node. Nodes are numbered from 1 in
+//! the order they appear, 0 stands for an empty node. A node of level `n`
+//! covers `2^n * 2^n` cells. Nodes of level 3 are drawn as text, with `$`
+//! ending each row, all other nodes list their level and their north-west,
+//! north-east, south-west and south-east children. Patterns with more than
+//! two states use nodes of level 1 that list the states of their four cells
+//! instead. The last node is the root, which is centred on `(0, 0)`. Like in
+//! most other formats, the y axis points down, so the row `y` of the file
+//! ends up at `-1 - y` (which keeps the root centred like a `HashLife` one).
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::mem;
+
+use super::{fits, tree, Metadata, ParseError, ParseErrorKind, Pattern};
+use backend::{Coord, Point};
+use backend::data::{ALIVE, QTree, State};
+use backend::hashlife::{HashLife, NodeId};
+
+/// The header of macrocell files.
+const HEADER: &str = "[M2]";
+
+/// The largest number of cells `parse` expands a pattern into. Larger
+/// patterns can only be read with `parse_into`.
+pub const MAX_POPULATION: u64 = 1 << 24;
+
+/// The level of the nodes that are drawn as text.
+const LEAF_LEVEL: u8 = 3;
+
+/// The largest level at which the coordinates of all cells fit into an
+/// `i64`.
+const MAX_LEVEL: u8 = 63;
+
+/// A node of a macrocell file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    /// An 8x8 node, with one byte per row from top to bottom and the lowest
+    /// bit for the leftmost cell.
+    Leaf([u8; 8]),
+    /// A 2x2 node of a multi-state pattern.
+    States([State; 4]),
+    /// A node of the given level with the numbers of its children.
+    Branch(u8, [usize; 4]),
+}
+
+impl Node {
+    fn level(&self) -> u8 {
+        match *self {
+            Node::Leaf(_) => LEAF_LEVEL,
+            Node::States(_) => 1,
+            Node::Branch(level, _) => level,
+        }
+    }
+}
+
+/// Parse a pattern in macrocell format.
+///
+/// `#R` gives the rule and `#C` lines are comments, other lines starting
+/// with `#` are ignored.
+///
+/// # Errors
+/// Fails if the header is missing, if a node is malformed or refers to a
+/// node that doesn't exist or isn't one level below it, if the pattern has
+/// more than `MAX_POPULATION` cells, or if it has cells whose coordinates
+/// don't fit into a `Point`.
+pub fn parse(input: &str) -> Result<Pattern, ParseError> {
+    let (nodes, lines, metadata) = read(input)?;
+    let root_line = lines.last().cloned().unwrap_or(1);
+    let mut cells = vec![];
+    if let Some(root) = nodes.last() {
+        match populations(&nodes).last() {
+            Some(&Some(population)) if population <= MAX_POPULATION => {}
+            _ => {
+                return Err(ParseError::new(root_line, 1,
+                                           ParseErrorKind::TooManyCells))
+            }
+        }
+        let half = 1i64 << (root.level() - 1);
+        expand(&nodes, nodes.len(), (-half, -half), &mut cells).ok_or_else(|| {
+            ParseError::new(root_line, 1, ParseErrorKind::OutOfRange)
+        })?;
+    }
+
+    Ok(Pattern {
+        cells: tree(cells),
+        metadata,
+    })
+}
+
+/// Parse a two-state pattern in macrocell format into `universe`, replacing
+/// its pattern.
+///
+/// Unlike `parse`, this builds the nodes of the universe directly from those
+/// of the file, so patterns of any population can be read as long as the
+/// file itself is small. The cells end up at the same positions as with
+/// `parse`.
+///
+/// # Errors
+/// Fails (and keeps the pattern of `universe`) like `parse`, except that the
+/// population may be larger than `MAX_POPULATION` (as long as it fits into a
+/// `u64`), if a cell has a state other than 0 or 1, or if the root is larger
+/// than `hashlife::MAX_LEVEL`.
+pub fn parse_into(input: &str, universe: &mut HashLife)
+                  -> Result<Metadata, ParseError> {
+    let (nodes, lines, metadata) = read(input)?;
+    let root_line = lines.last().cloned().unwrap_or(1);
+    if populations(&nodes).last() == Some(&None) {
+        return Err(ParseError::new(root_line, 1,
+                                   ParseErrorKind::TooManyCells))
+    }
+    let mut ids: Vec<NodeId> = Vec::with_capacity(nodes.len());
+    for (node, &line) in nodes.iter().zip(&lines) {
+        let id = match *node {
+            Node::Leaf(rows) => leaf(universe, &rows, (0, 0), LEAF_LEVEL),
+            Node::States(states) => {
+                if let Some(&state) = states.iter().find(|&&s| s > ALIVE) {
+                    let kind = ParseErrorKind::InvalidState(u32::from(state));
+                    return Err(ParseError::new(line, 1, kind))
+                }
+                let cells: Vec<_> = states.iter()
+                    .map(|&state| HashLife::cell(state == ALIVE))
+                    .collect();
+                universe.join(cells[0], cells[1], cells[2], cells[3])
+            }
+            Node::Branch(level, children) => {
+                let empty = universe.empty(level - 1);
+                let child = |number: usize| match number {
+                    0 => empty,
+                    _ => ids[number - 1],
+                };
+                let [nw, ne, sw, se] = children;
+                let (nw, ne, sw, se) = (child(nw), child(ne), child(sw),
+                                        child(se));
+                universe.join(nw, ne, sw, se)
+            }
+        };
+        ids.push(id);
+    }
+    let root = match ids.last() {
+        Some(&root) => root,
+        None => universe.empty(LEAF_LEVEL),
+    };
+    universe.set_root(root).map_err(|_| {
+        ParseError::new(root_line, 1, ParseErrorKind::OutOfRange)
+    })?;
+    Ok(metadata)
+}
+
+/// Read the nodes of a macrocell file, the lines they are on and the
+/// metadata.
+fn read(input: &str)
+        -> Result<(Vec<Node>, Vec<usize>, Metadata), ParseError> {
+    match input.lines().next() {
+        Some(line) if line.starts_with(HEADER) => {}
+        _ => return Err(ParseError::new(1, 1, ParseErrorKind::MissingHeader)),
+    }
+    let mut metadata = Metadata::default();
+    let mut nodes = vec![];
+    let mut lines = vec![];
+    for (number, line) in input.lines().enumerate().skip(1) {
+        let number = number + 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue
+        } else if line.starts_with('#') {
+            let tag = line.get(..2).unwrap_or(line);
+            let text = line.get(2..).unwrap_or("").trim();
+            match tag {
+                "#R" => metadata.rule = Some(text.to_string()),
+                "#C" => metadata.comments.push(text.to_string()),
+                _ => {}
+            }
+            continue
+        }
+        let node = if line.starts_with(['.', '*', '$']) {
+            parse_leaf(number, line)?
+        } else {
+            parse_node(number, line, &nodes)?
+        };
+        nodes.push(node);
+        lines.push(number);
+    }
+    Ok((nodes, lines, metadata))
+}
+
+/// Count the cells of all `nodes`, or `None` where that doesn't fit into a
+/// `u64`.
+fn populations(nodes: &[Node]) -> Vec<Option<u64>> {
+    let mut populations: Vec<Option<u64>> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let population = match *node {
+            Node::Leaf(rows) => {
+                Some(rows.iter().map(|row| u64::from(row.count_ones())).sum())
+            }
+            Node::States(states) => {
+                Some(states.iter().filter(|&&state| state != 0).count() as u64)
+            }
+            Node::Branch(_, children) => {
+                children.iter().try_fold(0u64, |sum, &child| match child {
+                    0 => Some(sum),
+                    _ => sum.checked_add(populations[child - 1]?),
+                })
+            }
+        };
+        populations.push(population);
+    }
+    populations
+}
+
+/// Build the node of the given `level` for the part of a leaf with its top
+/// left corner at `corner`.
+fn leaf(universe: &mut HashLife, rows: &[u8; 8], corner: (usize, usize),
+        level: u8) -> NodeId {
+    let (x, y) = corner;
+    if level == 0 {
+        return HashLife::cell(rows[y] & (1 << x) != 0)
+    }
+    let half = 1 << (level - 1);
+    let nw = leaf(universe, rows, (x, y), level - 1);
+    let ne = leaf(universe, rows, (x + half, y), level - 1);
+    let sw = leaf(universe, rows, (x, y + half), level - 1);
+    let se = leaf(universe, rows, (x + half, y + half), level - 1);
+    universe.join(nw, ne, sw, se)
+}
+
+/// Parse an 8x8 node that is drawn as text.
+fn parse_leaf(number: usize, line: &str) -> Result<Node, ParseError> {
+    let mut rows = [0u8; 8];
+    let (mut x, mut y) = (0, 0);
+    for (index, c) in line.chars().enumerate() {
+        let error = |kind| ParseError::new(number, index + 1, kind);
+        match c {
+            '$' => {
+                x = 0;
+                y += 1;
+            }
+            '.' | '*' if x < 8 && y < 8 => {
+                if c == '*' {
+                    rows[y] |= 1 << x;
+                }
+                x += 1;
+            }
+            '.' | '*' => return Err(error(ParseErrorKind::Node)),
+            _ => return Err(error(ParseErrorKind::UnexpectedCharacter(c))),
+        }
+    }
+    Ok(Node::Leaf(rows))
+}
+
+/// Parse a node that lists its level and its children (or states), which
+/// have to be among the `nodes` that were read before.
+fn parse_node(number: usize, line: &str, nodes: &[Node])
+              -> Result<Node, ParseError> {
+    let error = |kind| ParseError::new(number, 1, kind);
+    let fields = line.split_whitespace()
+        .map(str::parse::<u64>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error(ParseErrorKind::Node))?;
+    let (level, children) = match *fields.as_slice() {
+        [level, nw, ne, sw, se] if (1..=MAX_LEVEL as u64).contains(&level) => {
+            (level as u8, [nw, ne, sw, se])
+        }
+        _ => return Err(error(ParseErrorKind::Node)),
+    };
+
+    if level == 1 {
+        let mut states = [0; 4];
+        for (state, &child) in states.iter_mut().zip(children.iter()) {
+            if child > State::MAX as u64 {
+                let child = child.min(u32::MAX as u64) as u32;
+                return Err(error(ParseErrorKind::InvalidState(child)))
+            }
+            *state = child as State;
+        }
+        return Ok(Node::States(states))
+    }
+    let mut indices = [0; 4];
+    for (index, &child) in indices.iter_mut().zip(children.iter()) {
+        let child = child as usize;
+        let child_level = nodes.get(child.wrapping_sub(1)).map(Node::level);
+        if child != 0 && child_level != Some(level - 1) {
+            return Err(error(ParseErrorKind::Node))
+        }
+        *index = child;
+    }
+    Ok(Node::Branch(level, indices))
+}
+
+/// Add the cells of the node with the given `number`, whose top left corner
+/// is at `corner`, to `cells`.
+///
+/// Returns `None` if the coordinates of a cell don't fit into a `Point`.
+fn expand(nodes: &[Node], number: usize, corner: (i64, i64),
+          cells: &mut Vec<(Point, State)>) -> Option<()> {
+    let (x, y) = corner;
+    match nodes[number - 1] {
+        Node::Leaf(rows) => {
+            for (dy, &row) in rows.iter().enumerate() {
+                for dx in 0..8 {
+                    if row & (1 << dx) != 0 {
+                        cells.push((point(x + dx, y + dy as i64)?, ALIVE));
+                    }
+                }
+            }
+        }
+        Node::States(states) => {
+            for (i, &state) in states.iter().enumerate() {
+                if state != 0 {
+                    let (dx, dy) = ((i % 2) as i64, (i / 2) as i64);
+                    cells.push((point(x + dx, y + dy)?, state));
+                }
+            }
+        }
+        Node::Branch(level, children) => {
+            let half = 1i64 << (level - 1);
+            for (i, &child) in children.iter().enumerate() {
+                if child != 0 {
+                    let corner = (x + half * (i % 2) as i64,
+                                  y + half * (i / 2) as i64);
+                    expand(nodes, child, corner, cells)?;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+/// Convert the position of a cell in a file (with y pointing down) into a
+/// `Point`, if it fits.
+fn point(x: i64, y: i64) -> Option<Point> {
+    Some((Coord::try_from(x).ok()?, Coord::try_from(-1 - y).ok()?))
+        .filter(|&point| fits(point))
+}
+
+/// Write `cells` in macrocell format.
+///
+/// The comments and rule are taken from `metadata`. Identical subtrees are
+/// only written once, so that patterns consisting of many copies of the same
+/// objects stay small, no matter how far apart they are.
+pub fn write(cells: &QTree<State>, metadata: &Metadata) -> String {
+    let mut out = format!("{} (conway)\n", HEADER);
+    if let Some(ref rule) = metadata.rule {
+        out.push_str(&format!("#R {}\n", rule));
+    }
+    for comment in &metadata.comments {
+        out.push_str(&format!("#C {}\n", comment));
+    }
+
+    // Like in the file, y points down
+    let cells: Vec<(i64, i64, State)> = cells.iter()
+        .map(|((x, y), &state)| (i64::from(x), -1 - i64::from(y), state))
+        .collect();
+    if cells.is_empty() {
+        return out
+    }
+    let multi_state = cells.iter().any(|&(_, _, state)| state > ALIVE);
+    let leaf_level = if multi_state { 1 } else { LEAF_LEVEL };
+    let extent = cells.iter()
+        .map(|&(x, y, _)| (x + 1).max(-x).max(y + 1).max(-y))
+        .max().unwrap_or(0);
+    let mut level = leaf_level;
+    while 1i64 << (level - 1) < extent {
+        level += 1;
+    }
+
+    let half = 1i64 << (level - 1);
+    let mut writer = Writer {
+        out,
+        numbers: HashMap::new(),
+        leaf_level,
+    };
+    let _ = writer.node(cells, (-half, -half), level);
+    writer.out
+}
+
+/// Numbers the distinct nodes of a pattern while writing them.
+struct Writer {
+    out: String,
+    numbers: HashMap<Node, usize>,
+    leaf_level: u8,
+}
+
+impl Writer {
+    /// Write the node of the given `level` with its top left corner at
+    /// `corner` and containing `cells`, unless an identical node was written
+    /// before. Returns the number of the node.
+    fn node(&mut self, cells: Vec<(i64, i64, State)>, corner: (i64, i64),
+            level: u8) -> usize {
+        if cells.is_empty() {
+            return 0
+        }
+        let (x0, y0) = corner;
+        let node = if level > self.leaf_level {
+            let half = 1i64 << (level - 1);
+            let mut quadrants = [vec![], vec![], vec![], vec![]];
+            for cell in cells {
+                let east = (cell.0 - x0 >= half) as usize;
+                let south = (cell.1 - y0 >= half) as usize;
+                quadrants[2 * south + east].push(cell);
+            }
+            let mut children = [0; 4];
+            for (i, quadrant) in quadrants.iter_mut().enumerate() {
+                let corner = (x0 + half * (i % 2) as i64,
+                              y0 + half * (i / 2) as i64);
+                children[i] = self.node(mem::take(quadrant), corner,
+                                        level - 1);
+            }
+            Node::Branch(level, children)
+        } else if level == 1 {
+            let mut states = [0; 4];
+            for (x, y, state) in cells {
+                states[(2 * (y - y0) + x - x0) as usize] = state;
+            }
+            Node::States(states)
+        } else {
+            let mut rows = [0u8; 8];
+            for (x, y, _) in cells {
+                rows[(y - y0) as usize] |= 1 << (x - x0);
+            }
+            Node::Leaf(rows)
+        };
+
+        if let Some(&number) = self.numbers.get(&node) {
+            return number
+        }
+        match node {
+            Node::Leaf(rows) => {
+                let last = rows.iter().rposition(|&row| row != 0).unwrap_or(0);
+                for &row in &rows[..last + 1] {
+                    for x in 0..8 - row.leading_zeros() {
+                        let alive = row & (1 << x) != 0;
+                        self.out.push(if alive { '*' } else { '.' });
+                    }
+                    self.out.push('$');
+                }
+            }
+            Node::States(states) => {
+                self.out.push_str(&format!("1 {} {} {} {}", states[0],
+                                           states[1], states[2], states[3]));
+            }
+            Node::Branch(level, children) => {
+                self.out.push_str(&format!("{} {} {} {} {}", level,
+                                           children[0], children[1],
+                                           children[2], children[3]));
+            }
+        }
+        self.out.push('\n');
+        let number = self.numbers.len() + 1;
+        let _ = self.numbers.insert(node, number);
+        number
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use backend::hashlife::DEFAULT_MEMORY_LIMIT;
+    use backend::rule::Rule;
+
+    fn cells(pattern: &Pattern) -> BTreeSet<(Point, State)> {
+        pattern.cells.iter().map(|(point, &state)| (point, state)).collect()
+    }
+
+    #[test]
+    fn glider() {
+        let input = "[M2] (golly 2.0)\n\
+                     #R B3/S23\n\
+                     #C A glider\n\
+                     .*$..*$***$\n\
+                     4 0 0 0 1\n";
+        let pattern = parse(input).unwrap();
+        let expected: BTreeSet<_> = [(1, -1), (2, -2), (0, -3), (1, -3),
+                                     (2, -3)]
+            .iter().map(|&point| (point, ALIVE)).collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata, Metadata {
+            comments: vec!["A glider".to_string()],
+            rule: Some("B3/S23".to_string()),
+            ..Metadata::default()
+        });
+
+        // A glider in the root, which is the smallest possible one
+        let input = "[M2] (conway)\n#C A glider\n.*$..*$***$\n";
+        let pattern = parse(input).unwrap();
+        let expected: BTreeSet<_> = [(-3, 3), (-2, 2), (-4, 1), (-3, 1),
+                                     (-2, 1)]
+            .iter().map(|&point| (point, ALIVE)).collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(write(&pattern.cells, &pattern.metadata), input);
+
+        let pattern = parse("[M2]\n").unwrap();
+        assert_eq!(pattern.cells.iter().count(), 0);
+        assert_eq!(write(&pattern.cells, &pattern.metadata), "[M2] (conway)\n");
+    }
+
+    #[test]
+    fn multi_state() {
+        let pattern = parse("[M2] (golly 2.0)\n#R WireWorld\n\
+                             1 0 1 2 3\n1 3 0 0 255\n2 1 2 0 1\n").unwrap();
+        let expected: BTreeSet<_> = [
+            ((-1, 1), 1), ((-2, 0), 2), ((-1, 0), 3), ((0, 1), 3),
+            ((1, 0), 255), ((1, -1), 1), ((0, -2), 2), ((1, -2), 3),
+        ].iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        let output = write(&pattern.cells, &pattern.metadata);
+        assert_eq!(cells(&parse(&output).unwrap()), expected);
+        assert_eq!(output.lines().count(), 5);
+    }
+
+    #[test]
+    fn shared_subtrees() {
+        // A grid of 64x64 gliders 16 cells apart
+        let glider = [(1, -1), (2, -2), (0, -3), (1, -3), (2, -3)];
+        let mut points = vec![];
+        for i in -32..32 {
+            for j in -32..32 {
+                for &(x, y) in glider.iter() {
+                    points.push(((16 * i + x, -16 * j + y), ALIVE));
+                }
+            }
+        }
+        let grid = tree(points);
+        let output = write(&grid, &Metadata::default());
+        // One leaf and one node per level from 4 to 10
+        assert_eq!(output.lines().count(), 9, "{}", output);
+        assert_eq!(cells(&parse(&output).unwrap()),
+                   grid.iter().map(|(point, &state)| (point, state))
+                   .collect());
+
+        // Two gliders far apart
+        let points = glider.iter()
+            .flat_map(|&(x, y)| vec![((x - 1_000_000, y + 1_000_000), ALIVE),
+                                     ((x + 1_000_000, y), ALIVE)])
+            .collect();
+        let gliders = tree(points);
+        let output = write(&gliders, &Metadata::default());
+        assert!(output.len() < 1000, "{} bytes", output.len());
+        assert_eq!(cells(&parse(&output).unwrap()),
+                   gliders.iter().map(|(point, &state)| (point, state))
+                   .collect());
+    }
+
+    #[test]
+    fn hashlife() {
+        let universe = || {
+            HashLife::new(Rule::conway(), DEFAULT_MEMORY_LIMIT).unwrap()
+        };
+        let points = |pattern: &Pattern| -> BTreeSet<(i64, i64)> {
+            pattern.cells.points()
+                .map(|(x, y)| (i64::from(x), i64::from(y)))
+                .collect()
+        };
+
+        // The cells end up where `parse` puts them
+        let input = "[M2] (golly 2.0)\n#C A glider\n.*$..*$***$\n\
+                     4 0 0 0 1\n5 2 0 0 2\n";
+        let mut glider = universe();
+        let metadata = parse_into(input, &mut glider).unwrap();
+        assert_eq!(metadata.comments, vec!["A glider".to_string()]);
+        let cells: BTreeSet<_> = glider.cells().into_iter().collect();
+        assert_eq!(cells, points(&parse(input).unwrap()));
+        assert_eq!(cells.len(), 10);
+
+        // A full 2^20 x 2^20 square is too large to be expanded
+        let mut input = "[M2]\n".to_string();
+        input.push_str(&"********$".repeat(8));
+        input.push('\n');
+        for level in 4..21 {
+            let number = level - 3;
+            input.push_str(&format!("{} {} {} {} {}\n", level, number, number,
+                                    number, number));
+        }
+        assert_eq!(parse(&input).unwrap_err(),
+                   ParseError::new(19, 1, ParseErrorKind::TooManyCells));
+        let mut square = universe();
+        parse_into(&input, &mut square).unwrap();
+        assert_eq!(square.population(), 1 << 40);
+        assert!(square.get((-1 << 19, (1 << 19) - 1)));
+        assert!(!square.get((-1 << 19, 1 << 19)));
+
+        // ... and a 2^40 x 2^40 one has too many cells to be counted
+        for level in 21..41 {
+            let number = level - 3;
+            input.push_str(&format!("{} {} {} {} {}\n", level, number, number,
+                                    number, number));
+        }
+        assert_eq!(parse_into(&input, &mut square).unwrap_err(),
+                   ParseError::new(39, 1, ParseErrorKind::TooManyCells));
+        assert_eq!(square.population(), 1 << 40);
+
+        // Hashlife only supports two states and slightly smaller universes
+        let input = "[M2]\n1 0 1 2 0\n1 1 1 1 1\n2 2 0 0 0\n";
+        assert_eq!(parse_into(input, &mut universe()).unwrap_err(),
+                   ParseError::new(2, 1, ParseErrorKind::InvalidState(2)));
+        let mut input = "[M2]\n*$\n".to_string();
+        for level in 4..64 {
+            input.push_str(&format!("{} {} 0 0 0\n", level, level - 3));
+        }
+        assert_eq!(parse_into(&input, &mut universe()).unwrap_err(),
+                   ParseError::new(62, 1, ParseErrorKind::OutOfRange));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("x = 3, y = 3\n").unwrap_err(),
+                   ParseError::new(1, 1, ParseErrorKind::MissingHeader));
+        assert_eq!(parse("[M2]\n*$\n4 0 0 0 2\n").unwrap_err(),
+                   ParseError::new(3, 1, ParseErrorKind::Node));
+        assert_eq!(parse("[M2]\n*$\n5 0 0 0 1\n").unwrap_err(),
+                   ParseError::new(3, 1, ParseErrorKind::Node));
+        assert_eq!(parse("[M2]\n*$\n4 0 0 1\n").unwrap_err(),
+                   ParseError::new(3, 1, ParseErrorKind::Node));
+        assert_eq!(parse("[M2]\n*.x$\n").unwrap_err(),
+                   ParseError::new(2, 3,
+                                   ParseErrorKind::UnexpectedCharacter('x')));
+        assert_eq!(parse("[M2]\n.........*$\n").unwrap_err(),
+                   ParseError::new(2, 9, ParseErrorKind::Node));
+        assert_eq!(parse("[M2]\n1 0 0 0 256\n").unwrap_err(),
+                   ParseError::new(2, 1, ParseErrorKind::InvalidState(256)));
+
+        // A cell at (-2^40, -2^40), which only fits into 64-bit coordinates
+        let mut input = "[M2]\n*$\n".to_string();
+        for level in 4..42 {
+            let number = level - 3;
+            input.push_str(&format!("{} {} 0 0 0\n", level, number));
+        }
+        if cfg!(feature = "i64-coordinates") {
+            let cells = parse(&input).unwrap().cells;
+            let points: Vec<Point> = cells.points().collect();
+            assert_eq!(points, vec![(-1 << 40, (1 << 40) - 1)]);
+        } else {
+            assert_eq!(parse(&input).unwrap_err(),
+                       ParseError::new(40, 1, ParseErrorKind::OutOfRange));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Reading and writing pattern files.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use super::{Coord, Point};
+use super::data::{AABB, CellMap, QTree, State};
+
+pub mod apgcode;
+pub mod bitmap;
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+/// The file formats patterns can be read from and written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The run length encoded format (`.rle`).
+    Rle,
+    /// The plaintext format (`.cells`), a grid of `.` and `O`.
+    Plaintext,
+    /// Life 1.05, with blocks of cells at given positions.
+    Life105,
+    /// Life 1.06, a list of the coordinates of live cells.
+    Life106,
+    /// Golly's macrocell format (`.mc`), a quadtree in which identical
+    /// subtrees are only written once.
+    Macrocell,
+}
+
+impl Format {
+    /// Guess the format of a pattern file from its contents.
+    ///
+    /// Files that aren't recognized as any other format are assumed to be
+    /// RLE files.
+    pub fn detect(input: &str) -> Format {
+        let first = input.lines().map(str::trim).find(|line| !line.is_empty())
+            .unwrap_or("");
+        if first.starts_with("#Life 1.05") {
+            Format::Life105
+        } else if first.starts_with("#Life 1.06") {
+            Format::Life106
+        } else if first.starts_with("[M2]") {
+            Format::Macrocell
+        } else if first.starts_with('!') ||
+            first.chars().all(|c| c == '.' || c == 'O' || c == '*') {
+            Format::Plaintext
+        } else {
+            Format::Rle
+        }
+    }
+
+    /// Parse a pattern in this format.
+    ///
+    /// # Errors
+    /// Fails if the input is malformed.
+    pub fn parse(self, input: &str) -> Result<Pattern, ParseError> {
+        match self {
+            Format::Rle => rle::parse(input),
+            Format::Plaintext => plaintext::parse(input),
+            Format::Life105 => life::parse_105(input),
+            Format::Life106 => life::parse_106(input),
+            Format::Macrocell => macrocell::parse(input),
+        }
+    }
+
+    /// Write `cells` in this format.
+    ///
+    /// Formats that can't represent all of `metadata` (or states above 1)
+    /// leave out what they can't represent.
+    pub fn write(self, cells: &QTree<State>, metadata: &Metadata) -> String {
+        match self {
+            Format::Rle => rle::write(cells, metadata),
+            Format::Plaintext => plaintext::write(cells, metadata),
+            Format::Life105 => life::write_105(cells, metadata),
+            Format::Life106 => life::write_106(cells),
+            Format::Macrocell => macrocell::write(cells, metadata),
+        }
+    }
+}
+
+/// Parse a pattern in any of the supported formats.
+///
+/// # Errors
+/// Fails if the input is malformed in the format it was detected as.
+pub fn parse(input: &str) -> Result<Pattern, ParseError> {
+    Format::detect(input).parse(input)
+}
+
+/// A pattern read from a file.
+#[derive(Debug)]
+pub struct Pattern {
+    /// The cells of the pattern (with y pointing up, unlike in most files).
+    pub cells: QTree<State>,
+    /// Information about the pattern.
+    pub metadata: Metadata,
+}
+
+/// Information about a pattern that isn't part of the cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The name of the pattern.
+    pub name: Option<String>,
+    /// The author of the pattern.
+    pub author: Option<String>,
+    /// Comments, one per line.
+    pub comments: Vec<String>,
+    /// The rule the pattern is meant to be run with.
+    pub rule: Option<String>,
+}
+
+/// An error that occurred while parsing a pattern file.
+///
+/// Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line at which the error occurred.
+    pub line: usize,
+    /// The column at which the error occurred.
+    pub column: usize,
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+}
+
+/// The different kinds of `ParseError`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The file ended before the header line.
+    MissingHeader,
+    /// The header line isn't of the form `x = 3, y = 3, rule = B3/S23`.
+    Header,
+    /// A position line isn't of the form `#P 3 -2`.
+    Position,
+    /// A line of a coordinate list isn't of the form `3 -2`.
+    Coordinates,
+    /// An unexpected character.
+    UnexpectedCharacter(char),
+    /// A macrocell node that is malformed or refers to a node that doesn't
+    /// exist.
+    Node,
+    /// A run count that is 0 or too large.
+    InvalidCount,
+    /// A state that is larger than 255.
+    InvalidState(u32),
+    /// Cells whose coordinates don't fit into a `Point`, or are at the limits
+    /// of its range.
+    OutOfRange,
+    /// More cells than can be loaded at once (see
+    /// `macrocell::MAX_POPULATION`).
+    TooManyCells,
+}
+
+impl ParseError {
+    /// Create an error at a given `line` and `column`.
+    fn new(line: usize, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line,
+            column,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            ParseErrorKind::MissingHeader => write!(f, "missing header"),
+            ParseErrorKind::Header => {
+                write!(f, "invalid header (expected e.g. x = 3, y = 3, \
+                           rule = B3/S23)")
+            }
+            ParseErrorKind::Position => {
+                write!(f, "invalid position (expected e.g. #P 3 -2)")
+            }
+            ParseErrorKind::Coordinates => {
+                write!(f, "invalid coordinates (expected e.g. 3 -2)")
+            }
+            ParseErrorKind::UnexpectedCharacter(c) => {
+                write!(f, "unexpected character '{}'", c)
+            }
+            ParseErrorKind::Node => {
+                write!(f, "invalid node (expected e.g. 4 1 0 0 2)")
+            }
+            ParseErrorKind::InvalidCount => write!(f, "invalid run count"),
+            ParseErrorKind::InvalidState(state) => {
+                write!(f, "invalid state {} (must be at most 255)", state)
+            }
+            ParseErrorKind::OutOfRange => {
+                write!(f, "pattern exceeds the supported coordinates")
+            }
+            ParseErrorKind::TooManyCells => {
+                write!(f, "pattern has too many cells to be loaded")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parse two whitespace-separated coordinates.
+fn coordinates(text: &str) -> Option<Point> {
+    let coords: Vec<_> = text.split_whitespace()
+        .map(|coord| coord.parse::<Coord>())
+        .collect();
+    match coords.as_slice() {
+        [Ok(x), Ok(y)] => Some((*x, *y)),
+        _ => None,
+    }
+}
+
+/// Group the `cells` by row, with the rows going down like in most files
+/// (i.e. by `-y`).
+fn rows(cells: &QTree<State>) -> BTreeMap<Coord, BTreeMap<Coord, State>> {
+    let mut rows = BTreeMap::<Coord, BTreeMap<Coord, State>>::new();
+    for ((x, y), &state) in cells.iter() {
+        let _ = rows.entry(-y).or_default().insert(x, state);
+    }
+    rows
+}
+
+/// Check if `point` fits into a quadtree created by `tree`, which can't hold
+/// cells at `Coord::MIN` or `Coord::MAX`.
+fn fits(point: Point) -> bool {
+    AABB::new((0, 0), Coord::MAX).contains(point)
+}
+
+/// Get the cell that is `offset` cells right of and below `origin` (with y
+/// pointing down, like in most files), if it fits into a quadtree (see
+/// `fits`).
+fn cell(origin: Point, offset: Point) -> Option<Point> {
+    let x = origin.0.checked_add(offset.0)?;
+    let y = origin.1.checked_add(offset.1)?.checked_neg()?;
+    Some((x, y)).filter(|&point| fits(point))
+}
+
+/// Create a quadtree with the given `cells`, whose boundary is large enough
+/// to contain all of them.
+///
+/// # Panics
+/// Panics if a cell doesn't fit into a quadtree (see `fits`).
+fn tree(cells: Vec<(Point, State)>) -> QTree<State> {
+    let extent = cells.iter()
+        .map(|&((x, y), _)| {
+            assert!(fits((x, y)), "Can't store the cell at {:?}", (x, y));
+            (x + 1).max(-x).max(y + 1).max(-y)
+        })
+        .max().unwrap_or(0);
+    // The boundary can't be as large as the next power of two for cells at
+    // the edges of the coordinate range
+    let half_dim = Coord::try_from((extent.max(4) as u64).next_power_of_two())
+        .unwrap_or(Coord::MAX);
+    let mut tree = QTree::<State>::empty(AABB::new((0, 0), half_dim));
+    for (point, state) in cells {
+        tree.set(point, state);
+    }
+    tree
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const FORMATS: [Format; 5] = [Format::Rle, Format::Plaintext,
+                                  Format::Life105, Format::Life106,
+                                  Format::Macrocell];
+
+    #[test]
+    fn detect() {
+        assert_eq!(Format::detect("#N Glider\nx = 3, y = 3\nbo$2bo$3o!"),
+                   Format::Rle);
+        assert_eq!(Format::detect("x = 0, y = 0\n!"), Format::Rle);
+        assert_eq!(Format::detect("!Name: Glider\n.O\n..O\nOOO\n"),
+                   Format::Plaintext);
+        assert_eq!(Format::detect("\n.*.\n"), Format::Plaintext);
+        assert_eq!(Format::detect("#Life 1.05\n#P 0 0\n*\n"),
+                   Format::Life105);
+        assert_eq!(Format::detect("#Life 1.06\n0 0\n"), Format::Life106);
+        assert_eq!(Format::detect("[M2] (golly 2.0)\n*$\n"),
+                   Format::Macrocell);
+    }
+
+    #[test]
+    fn round_trip() {
+        let cells = tree(vec![((0, 1), 1), ((1, 0), 1), ((-1, -1), 1),
+                              ((0, -1), 1), ((1, -1), 1), ((-7, 12), 1)]);
+        let expected: BTreeSet<Point> = cells.points().collect();
+        let metadata = Metadata {
+            comments: vec!["A glider and a cell".to_string()],
+            ..Metadata::default()
+        };
+        for &format in &FORMATS {
+            let output = format.write(&cells, &metadata);
+            assert_eq!(Format::detect(&output), format);
+            let pattern = parse(&output).unwrap();
+            let points: BTreeSet<Point> = pattern.cells.points().collect();
+            // Plaintext files don't store the position of the pattern
+            if format == Format::Plaintext {
+                assert_eq!(points.len(), expected.len());
+            } else {
+                assert_eq!(points, expected);
+            }
+        }
+    }
+}
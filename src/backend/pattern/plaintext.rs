@@ -0,0 +1,172 @@
+//! The plaintext format (`.cells`), which draws the cells as text.
+//!
+//! ```text
+//! !Name: Glider
+//! .O.
+//! ..O
+//! OOO
+//! ```
+
+use std::convert::TryFrom;
+
+use super::{cell, rows, tree, Metadata, ParseError, ParseErrorKind, Pattern};
+use backend::{Coord, Point};
+use backend::data::{ALIVE, QTree, State};
+
+/// Parse a pattern in plaintext format.
+///
+/// Dead cells are written as `.` and live cells as `O` (or `*`). Lines that
+/// start with `!` are comments, of which `!Name:` and `!Author:` set the name
+/// and author of the pattern. The top left cell is at `(0, 0)`.
+///
+/// # Errors
+/// Fails if a row contains any other characters, or if there are cells
+/// outside of the supported coordinates.
+pub fn parse(input: &str) -> Result<Pattern, ParseError> {
+    let mut metadata = Metadata::default();
+    let mut cells = vec![];
+    // Blank lines only count as rows once the first row was read
+    let mut y = None;
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            let text = comment.trim();
+            if let Some(name) = text.strip_prefix("Name:") {
+                metadata.name = Some(name.trim().to_string());
+            } else if let Some(author) = text.strip_prefix("Author:") {
+                metadata.author = Some(author.trim().to_string());
+            } else {
+                metadata.comments.push(text.to_string());
+            }
+            continue
+        }
+        let row = match y {
+            None if line.is_empty() => continue,
+            None => 0,
+            Some(y) => y + 1,
+        };
+        y = Some(row);
+        for (index, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                'O' | '*' => cells.push((position(number, index, row)?,
+                                         ALIVE)),
+                _ => {
+                    let kind = ParseErrorKind::UnexpectedCharacter(c);
+                    return Err(ParseError::new(number + 1, index + 1, kind))
+                }
+            }
+        }
+    }
+
+    Ok(Pattern {
+        cells: tree(cells),
+        metadata,
+    })
+}
+
+/// Get the cell at the given column `index` of a `row` (counted from the
+/// top), which is on the line with the given (0-based) `number`.
+fn position(number: usize, index: usize, row: usize)
+            -> Result<Point, ParseError> {
+    let offset = Coord::try_from(index).ok().zip(Coord::try_from(row).ok());
+    offset.and_then(|offset| cell((0, 0), offset)).ok_or_else(|| {
+        ParseError::new(number + 1, index + 1, ParseErrorKind::OutOfRange)
+    })
+}
+
+/// Write `cells` in plaintext format.
+///
+/// The name, author and comments are taken from `metadata`. All cells that
+/// aren't dead are written as `O`.
+pub fn write(cells: &QTree<State>, metadata: &Metadata) -> String {
+    let mut out = String::new();
+    if let Some(ref name) = metadata.name {
+        out.push_str(&format!("!Name: {}\n", name));
+    }
+    if let Some(ref author) = metadata.author {
+        out.push_str(&format!("!Author: {}\n", author));
+    }
+    for comment in &metadata.comments {
+        out.push_str(&format!("!{}\n", comment));
+    }
+
+    let (min, max) = match cells.bounding_box() {
+        Some(bounding_box) => bounding_box,
+        None => return out,
+    };
+    let rows = rows(cells);
+    for y in -max.1..-min.1 + 1 {
+        let mut x = min.0;
+        for &cell in rows.get(&y).iter().flat_map(|row| row.keys()) {
+            for _ in x..cell {
+                out.push('.');
+            }
+            out.push('O');
+            x = cell + 1;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use backend::Point;
+
+    fn cells(pattern: &Pattern) -> BTreeSet<Point> {
+        pattern.cells.points().collect()
+    }
+
+    #[test]
+    fn glider() {
+        let input = "!Name: Glider\n\
+                     !Author: Richard K. Guy\n\
+                     !The smallest spaceship.\n\
+                     .O\n\
+                     ..O\n\
+                     OOO\n";
+        let pattern = parse(input).unwrap();
+        let expected: BTreeSet<Point> = [(1, 0), (2, -1), (0, -2), (1, -2),
+                                         (2, -2)].iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata, Metadata {
+            name: Some("Glider".to_string()),
+            author: Some("Richard K. Guy".to_string()),
+            comments: vec!["The smallest spaceship.".to_string()],
+            rule: None,
+        });
+        assert_eq!(write(&pattern.cells, &pattern.metadata), input);
+    }
+
+    #[test]
+    fn blank_rows() {
+        let input = "!Comment\n\n*.*\n\n..O\n";
+        let pattern = parse(input).unwrap();
+        let expected: BTreeSet<Point> = [(0, 0), (2, 0), (2, -2)]
+            .iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(write(&pattern.cells, &pattern.metadata),
+                   "!Comment\nO.O\n\n..O\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(".O.\n.x.").unwrap_err(),
+                   ParseError::new(2, 2,
+                                   ParseErrorKind::UnexpectedCharacter('x')));
+
+        // Cells outside of the supported coordinates, which would take
+        // gigabytes of input to reach
+        let max = Coord::MAX as usize;
+        assert_eq!(position(4, max, 0).unwrap_err(),
+                   ParseError::new(5, max + 1, ParseErrorKind::OutOfRange));
+        assert_eq!(position(4, 2, max + 1).unwrap_err(),
+                   ParseError::new(5, 3, ParseErrorKind::OutOfRange));
+        assert_eq!(position(4, max - 1, max),
+                   Ok((Coord::MAX - 1, -Coord::MAX)));
+    }
+}
@@ -0,0 +1,512 @@
+//! The run length encoded format (`.rle`) used by most pattern collections.
+//!
+//! ```text
+//! #N Glider
+//! #O Richard K. Guy
+//! x = 3, y = 3, rule = B3/S23
+//! bob$2bo$3o!
+//! ```
+
+use std::convert::TryFrom;
+
+use super::{cell, coordinates, rows, tree, Metadata, ParseError,
+            ParseErrorKind, Pattern};
+use backend::{Coord, Point};
+use backend::data::{ALIVE, QTree, State};
+
+/// The maximum length of the lines of the cells written by `write`.
+const LINE_LENGTH: usize = 70;
+
+/// Parse a pattern in RLE format.
+///
+/// The header may be preceded by comment lines: `#N` (name), `#O` (author),
+/// `#C` (comment) and `#P` or `#R` (position of the top left cell, which is
+/// at `(0, 0)` otherwise). Other comment lines are ignored. Rows go down in
+/// RLE files, so they end up at decreasing y coordinates.
+///
+/// Dead cells are written as `b` or `.`, live cells as `o`. Multi-state
+/// patterns use `A` to `X` for the states 1 to 24 and `pA` to `yO` for the
+/// states 25 to 255.
+///
+/// Everything after the `!` at the end of the pattern is ignored. If it is
+/// missing, the pattern ends at the end of the input.
+///
+/// # Errors
+/// Fails if the header is missing or malformed, or if the pattern contains
+/// an invalid character, run count or state, or cells outside of the
+/// supported coordinates.
+pub fn parse(input: &str) -> Result<Pattern, ParseError> {
+    let mut metadata = Metadata::default();
+    let mut position = (0, 0);
+    let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut header = false;
+    for (number, line) in &mut lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue
+        } else if trimmed.starts_with('#') {
+            parse_comment(number, line, &mut metadata, &mut position)?;
+        } else {
+            header = true;
+            metadata.rule = parse_header(number, line)?;
+            break
+        }
+    }
+    if !header {
+        let end = input.lines().count() + 1;
+        return Err(ParseError::new(end, 1, ParseErrorKind::MissingHeader))
+    }
+
+    let mut cells = vec![];
+    let (mut x, mut y): Point = (0, 0);
+    let mut count: Option<u32> = None;
+    // The value of a `p` to `y` prefix of a multi-state letter
+    let mut prefix: Option<u32> = None;
+    'lines: for (number, line) in lines {
+        for (index, c) in line.chars().enumerate() {
+            let error = |kind| ParseError::new(number, index + 1, kind);
+            let state = match (prefix.take(), c) {
+                (Some(high), 'A'..='X') => {
+                    high + (c as u32 - 'A' as u32) + 1
+                }
+                (None, '0'..='9') => {
+                    let digit = c as u32 - '0' as u32;
+                    count = count.unwrap_or(0).checked_mul(10)
+                        .and_then(|count| count.checked_add(digit));
+                    if count.is_none() {
+                        return Err(error(ParseErrorKind::InvalidCount))
+                    }
+                    continue
+                }
+                (None, 'p'..='y') => {
+                    prefix = Some(24 * (c as u32 - 'p' as u32 + 1));
+                    continue
+                }
+                (None, '!') if count.is_none() => break 'lines,
+                (None, '$') => {
+                    let run = run_length(&mut count)
+                        .ok_or_else(|| error(ParseErrorKind::InvalidCount))?;
+                    y = y.checked_add(run)
+                        .ok_or_else(|| error(ParseErrorKind::InvalidCount))?;
+                    x = 0;
+                    continue
+                }
+                (None, 'b') | (None, '.') => 0,
+                (None, 'o') => 1,
+                (None, 'A'..='X') => c as u32 - 'A' as u32 + 1,
+                (None, c) if c.is_whitespace() => continue,
+                _ => {
+                    return Err(error(ParseErrorKind::UnexpectedCharacter(c)))
+                }
+            };
+            if state > State::MAX as u32 {
+                return Err(error(ParseErrorKind::InvalidState(state)))
+            }
+            let run = run_length(&mut count)
+                .ok_or_else(|| error(ParseErrorKind::InvalidCount))?;
+            if state != 0 {
+                for i in 0..run {
+                    let point = x.checked_add(i)
+                        .and_then(|x| cell(position, (x, y)))
+                        .ok_or_else(|| error(ParseErrorKind::OutOfRange))?;
+                    cells.push((point, state as State));
+                }
+            }
+            x = x.checked_add(run)
+                .ok_or_else(|| error(ParseErrorKind::InvalidCount))?;
+        }
+    }
+
+    Ok(Pattern {
+        cells: tree(cells),
+        metadata,
+    })
+}
+
+/// Write `cells` in RLE format.
+///
+/// The name, author, comments and rule are taken from `metadata`. If the top
+/// left corner of the bounding box isn't at `(0, 0)`, its position is given
+/// in a `#R` line, so that `parse` puts the cells back where they were.
+///
+/// Patterns with cells in states above 1 are written with multi-state
+/// letters. Runs of the same state are combined, even across empty rows, and
+/// lines are wrapped after 70 characters.
+pub fn write(cells: &QTree<State>, metadata: &Metadata) -> String {
+    let mut out = String::new();
+    if let Some(ref name) = metadata.name {
+        out.push_str(&format!("#N {}\n", name));
+    }
+    if let Some(ref author) = metadata.author {
+        out.push_str(&format!("#O {}\n", author));
+    }
+    for comment in &metadata.comments {
+        out.push_str(&format!("#C {}\n", comment));
+    }
+    let (min, max) = cells.bounding_box().unwrap_or(((0, 1), (-1, 0)));
+    // Rows go down in RLE files
+    if (min.0, -max.1) != (0, 0) {
+        out.push_str(&format!("#R {} {}\n", min.0, -max.1));
+    }
+    // Patterns spanning the coordinate range are wider than `Coord::MAX`
+    let width = i128::from(max.0) - i128::from(min.0) + 1;
+    let height = i128::from(max.1) - i128::from(min.1) + 1;
+    out.push_str(&format!("x = {}, y = {}", width, height));
+    if let Some(ref rule) = metadata.rule {
+        out.push_str(&format!(", rule = {}", rule));
+    }
+    out.push('\n');
+
+    let multi_state = cells.iter().any(|(_, &state)| state > ALIVE);
+    let mut runs: Vec<(i128, String)> = vec![];
+    let mut previous = None;
+    for (&y, row) in &rows(cells) {
+        if let Some(previous) = previous {
+            runs.push((i128::from(y) - previous, "$".to_string()));
+        }
+        previous = Some(i128::from(y));
+        let mut x = i128::from(min.0);
+        let mut run: Option<(i128, State)> = None;
+        for (&cell, &state) in row {
+            let cell = i128::from(cell);
+            match run {
+                Some((ref mut length, run_state))
+                    if run_state == state && cell == x => *length += 1,
+                _ => {
+                    if let Some((length, run_state)) = run {
+                        runs.push((length, letter(run_state, multi_state)));
+                    }
+                    if cell > x {
+                        runs.push((cell - x, letter(0, multi_state)));
+                    }
+                    run = Some((1, state));
+                }
+            }
+            x = cell + 1;
+        }
+        if let Some((length, state)) = run {
+            runs.push((length, letter(state, multi_state)));
+        }
+    }
+    runs.push((1, "!".to_string()));
+
+    let mut line = String::new();
+    for (length, letter) in runs {
+        let run = if length == 1 {
+            letter
+        } else {
+            format!("{}{}", length, letter)
+        };
+        if line.len() + run.len() > LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        line.push_str(&run);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+/// Get the letter(s) for a `state`.
+fn letter(state: State, multi_state: bool) -> String {
+    match (state, multi_state) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (1..=24, true) => ((b'A' + state - 1) as char).to_string(),
+        _ => {
+            let prefix = (b'p' + (state - 25) / 24) as char;
+            let letter = (b'A' + (state - 25) % 24) as char;
+            format!("{}{}", prefix, letter)
+        }
+    }
+}
+
+/// Take the run count that was read (1 if there was none).
+///
+/// Returns `None` if the count is 0 or too large.
+fn run_length(count: &mut Option<u32>) -> Option<Coord> {
+    match count.take() {
+        None => Some(1),
+        Some(0) => None,
+        Some(count) => Coord::try_from(count).ok(),
+    }
+}
+
+/// Parse a comment line (starting with `#`) into `metadata` and `position`.
+fn parse_comment(number: usize, line: &str, metadata: &mut Metadata,
+                 position: &mut Point) -> Result<(), ParseError> {
+    let indent = line.len() - line.trim_start().len();
+    let line = line.trim();
+    let tag = line.get(..2).unwrap_or(line);
+    let text = line.get(2..).unwrap_or("").trim().to_string();
+    match tag {
+        "#N" => metadata.name = Some(text),
+        "#O" => metadata.author = Some(text),
+        "#C" | "#c" => metadata.comments.push(text),
+        "#P" | "#R" => {
+            *position = coordinates(&text).ok_or_else(|| {
+                ParseError::new(number, indent + 3, ParseErrorKind::Position)
+            })?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Parse the header line (`x = 3, y = 3, rule = B3/S23`) and return the
+/// rule, if there is one.
+///
+/// The rule has to come last, since it may contain commas itself.
+fn parse_header(number: usize, line: &str)
+                -> Result<Option<String>, ParseError> {
+    let error = |column| {
+        ParseError::new(number, column, ParseErrorKind::Header)
+    };
+    let (sizes, rule) = match line.find("rule") {
+        Some(index) => (&line[..index], Some(index)),
+        None => (line, None),
+    };
+
+    let mut keys = vec![];
+    let mut column = 1;
+    for part in sizes.split(',') {
+        let start = column + part.len() - part.trim_start().len();
+        column += part.chars().count() + 1;
+        if part.trim().is_empty() {
+            continue
+        }
+        let mut assignment = part.splitn(2, '=').map(str::trim);
+        let key = assignment.next().unwrap_or("");
+        let value = assignment.next().map(str::parse::<u32>);
+        match (key, value) {
+            ("x", Some(Ok(_))) | ("y", Some(Ok(_)))
+                if !keys.contains(&key) => keys.push(key),
+            _ => return Err(error(start)),
+        }
+    }
+    if keys.len() != 2 {
+        return Err(error(1))
+    }
+
+    match rule {
+        Some(index) => {
+            let column = line[..index].chars().count() + 1;
+            let value = line[index + 4..].trim();
+            if !value.starts_with('=') || value[1..].trim().is_empty() {
+                return Err(error(column))
+            }
+            Ok(Some(value[1..].trim().to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use backend::data::{ALIVE, CellMap};
+
+    fn cells(pattern: &Pattern) -> BTreeSet<(Point, State)> {
+        pattern.cells.iter().map(|(point, &state)| (point, state)).collect()
+    }
+
+    #[test]
+    fn glider() {
+        let pattern = parse("#N Glider\n\
+                             #O Richard K. Guy\n\
+                             #C The smallest spaceship.\n\
+                             #C Found in 1969.\n\
+                             x = 3, y = 3, rule = B3/S23\n\
+                             bob$2bo$3o!\n").unwrap();
+        let expected: BTreeSet<_> = [(1, 0), (2, -1), (0, -2), (1, -2),
+                                     (2, -2)]
+            .iter().map(|&point| (point, ALIVE)).collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata, Metadata {
+            name: Some("Glider".to_string()),
+            author: Some("Richard K. Guy".to_string()),
+            comments: vec!["The smallest spaceship.".to_string(),
+                           "Found in 1969.".to_string()],
+            rule: Some("B3/S23".to_string()),
+        });
+    }
+
+    #[test]
+    fn runs() {
+        // Runs may be split across lines
+        let pattern = parse("x = 36, y = 9\n\
+                             24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o1\n\
+                             2b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$\n\
+                             11bo3bo$12b2o!")
+            .unwrap();
+        assert_eq!(pattern.cells.iter().count(), 36);
+        assert_eq!(pattern.cells.get((24, 0)), Some(&ALIVE));
+        assert_eq!(pattern.cells.get((35, -3)), Some(&ALIVE));
+        assert_eq!(pattern.cells.get((13, -8)), Some(&ALIVE));
+        assert_eq!(pattern.metadata.rule, None);
+
+        // Blank lines skip rows, the terminator is optional
+        let pattern = parse("x = 2, y = 4\n2o3$2o").unwrap();
+        assert_eq!(pattern.cells.get((1, -3)), Some(&ALIVE));
+        assert_eq!(pattern.cells.iter().count(), 4);
+    }
+
+    #[test]
+    fn multi_state() {
+        let pattern = parse("x = 5, y = 2, rule = WireWorld\n\
+                             .AB2C$pA.yO!").unwrap();
+        let expected: BTreeSet<_> = [
+            ((1, 0), 1), ((2, 0), 2), ((3, 0), 3), ((4, 0), 3),
+            ((0, -1), 25), ((2, -1), 255),
+        ].iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata.rule, Some("WireWorld".to_string()));
+    }
+
+    #[test]
+    fn position() {
+        let pattern = parse("#P -1 2\nx = 2, y = 1, rule = \
+                             R5,C0,M1,S34..58,B34..45,NM\n2o!").unwrap();
+        let expected: BTreeSet<_> = [((-1, -2), ALIVE), ((0, -2), ALIVE)]
+            .iter().cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        assert_eq!(pattern.metadata.rule,
+                   Some("R5,C0,M1,S34..58,B34..45,NM".to_string()));
+    }
+
+    #[test]
+    fn extreme_positions() {
+        // Beyond half of the range, the boundary can't be a power of two
+        let pattern = parse("#R 2000000000 0
+x = 1, y = 1
+o!").unwrap();
+        let expected: BTreeSet<_> = [((2000000000, 0), ALIVE)].iter()
+            .cloned().collect();
+        assert_eq!(cells(&pattern), expected);
+        let input = format!("#R {} 0
+x = 7, y = 1
+7o!", Coord::MAX - 7);
+        assert_eq!(cells(&parse(&input).unwrap()).len(), 7);
+
+        // Cells past the end of the range
+        let input = format!("#R {} 0
+x = 20, y = 1
+20o!", Coord::MAX - 7);
+        assert_eq!(parse(&input).unwrap_err(),
+                   ParseError::new(3, 3, ParseErrorKind::OutOfRange));
+        let input = format!("#R 0 {}
+x = 1, y = 2
+$o!", Coord::MAX);
+        assert_eq!(parse(&input).unwrap_err(),
+                   ParseError::new(3, 2, ParseErrorKind::OutOfRange));
+        let input = format!("#R 0 {}
+x = 1, y = 1
+o!", Coord::MIN);
+        assert_eq!(parse(&input).unwrap_err(),
+                   ParseError::new(3, 1, ParseErrorKind::OutOfRange));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("").unwrap_err(),
+                   ParseError::new(1, 1, ParseErrorKind::MissingHeader));
+        assert_eq!(parse("#C No pattern\n").unwrap_err(),
+                   ParseError::new(2, 1, ParseErrorKind::MissingHeader));
+        assert_eq!(parse("x = 3, y = three\no!").unwrap_err(),
+                   ParseError::new(1, 8, ParseErrorKind::Header));
+        assert_eq!(parse("x = 3\no!").unwrap_err(),
+                   ParseError::new(1, 1, ParseErrorKind::Header));
+        assert_eq!(parse("x = 3, y = 3, rule =\no!").unwrap_err(),
+                   ParseError::new(1, 15, ParseErrorKind::Header));
+        assert_eq!(parse("#P 1\nx = 1, y = 1\no!").unwrap_err(),
+                   ParseError::new(1, 3, ParseErrorKind::Position));
+        assert_eq!(parse("x = 3, y = 3\nbo$2bz!").unwrap_err(),
+                   ParseError::new(2, 6,
+                                   ParseErrorKind::UnexpectedCharacter('z')));
+        assert_eq!(parse("x = 1, y = 1\n3!").unwrap_err(),
+                   ParseError::new(2, 2,
+                                   ParseErrorKind::UnexpectedCharacter('!')));
+        assert_eq!(parse("x = 1, y = 1\npo!").unwrap_err(),
+                   ParseError::new(2, 2,
+                                   ParseErrorKind::UnexpectedCharacter('o')));
+        assert_eq!(parse("x = 1, y = 1\nyP!").unwrap_err(),
+                   ParseError::new(2, 2, ParseErrorKind::InvalidState(256)));
+        assert_eq!(parse("x = 1, y = 1\n0o!").unwrap_err(),
+                   ParseError::new(2, 2, ParseErrorKind::InvalidCount));
+        assert_eq!(parse("x = 1, y = 1\n99999999999o!").unwrap_err(),
+                   ParseError::new(2, 10, ParseErrorKind::InvalidCount));
+        assert_eq!(parse("x = 1, y = 1\n3!").unwrap_err().to_string(),
+                   "line 2, column 2: unexpected character '!'");
+    }
+
+    #[test]
+    fn write_glider() {
+        let pattern = parse("#N Glider\n#C A comment\n\
+                             x = 3, y = 3, rule = B3/S23\n\
+                             bob$2bo$3o!").unwrap();
+        assert_eq!(write(&pattern.cells, &pattern.metadata),
+                   "#N Glider\n#C A comment\n\
+                    x = 3, y = 3, rule = B3/S23\n\
+                    bo$2bo$3o!\n");
+
+        let pattern = parse("x = 0, y = 0\n!").unwrap();
+        assert_eq!(write(&pattern.cells, &Metadata::default()),
+                   "x = 0, y = 0\n!\n");
+    }
+
+    #[test]
+    fn write_extreme_positions() {
+        // The pattern spans the whole coordinate range
+        let cells = tree(vec![((-Coord::MAX, Coord::MAX - 1), ALIVE),
+                              ((Coord::MAX - 1, -Coord::MAX), ALIVE)]);
+        let size = 2 * i128::from(Coord::MAX);
+        assert_eq!(write(&cells, &Metadata::default()),
+                   format!("#R {} {}\nx = {}, y = {}\no{}${}bo!\n",
+                           -Coord::MAX, 1 - Coord::MAX, size, size,
+                           size - 1, size - 1));
+    }
+
+    #[test]
+    fn write_wrapped() {
+        // Every row alternates between live and dead cells
+        let row = "ob".repeat(30);
+        let input = format!("x = 60, y = 3\n{}$2${}!", row, row);
+        let output = write(&parse(&input).unwrap().cells,
+                           &Metadata::default());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "x = 59, y = 4");
+        assert!(lines[1..].iter().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(lines[1..].concat(), format!("{}o3${}o!", &row[..58],
+                                                &row[..58]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let corpus = [
+            "#N Glider\n#O Richard K. Guy\n#C The smallest spaceship.\n\
+             x = 3, y = 3, rule = B3/S23\nbob$2bo$3o!",
+            "#N Gosper glider gun\nx = 36, y = 9, rule = B3/S23\n\
+             24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo\n\
+             3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!",
+            "#C Blank rows\nx = 2, y = 6\n2o5$bo!",
+            "#P -7 3\nx = 4, y = 2, rule = B36/S23\n4o$o2bo!",
+            "x = 5, y = 2, rule = WireWorld\n.AB2C$pA.yO!",
+            "x = 4, y = 1, rule = B2/S/C3\nABAB!",
+            "x = 1, y = 1, rule = R5,C0,M1,S34..58,B34..45,NM\no!",
+            "x = 0, y = 0\n!",
+        ];
+        for input in corpus.iter() {
+            let pattern = parse(input).unwrap();
+            let output = write(&pattern.cells, &pattern.metadata);
+            let parsed = parse(&output).unwrap();
+            assert_eq!(cells(&parsed), cells(&pattern), "{}", output);
+            assert_eq!(parsed.metadata, pattern.metadata);
+            assert_eq!(write(&parsed.cells, &parsed.metadata), output);
+        }
+    }
+}
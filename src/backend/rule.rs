@@ -0,0 +1,901 @@
+//! Rules that decide how cells evolve from one generation to the next.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::Point;
+use super::data::{ALIVE, State};
+use super::neighbours::{Neighbours, Shape};
+
+/// A neighbourhood of a cell, encoded as a 9-bit mask.
+///
+/// The bits are laid out row by row, starting at the top left:
+///
+/// ```text
+/// 1   2   4
+/// 8  16  32
+/// 64 128 256
+/// ```
+///
+/// The bit in the middle (`CENTRE`) is the cell itself.
+pub type Neighbourhood = u16;
+
+/// The bit of a `Neighbourhood` that belongs to the cell itself.
+pub const CENTRE: Neighbourhood = 1 << 4;
+
+/// All bits of a `Neighbourhood` except for `CENTRE`.
+const OUTER: Neighbourhood = 0x1ef;
+
+/// The Hensel letters for each neighbour count up to 4, in canonical order.
+///
+/// The letters for 5 to 8 neighbours are the same as for 3 to 0 neighbours,
+/// respectively, with the neighbourhoods inverted.
+const LETTERS: [&str; 5] = ["", "ce", "ceaikn", "ceaiknjqry", "ceaiknjqrytwz"];
+
+/// One representative neighbourhood for each letter in `LETTERS`.
+///
+/// The remaining neighbourhoods of a letter are its rotations and
+/// reflections.
+const REPRESENTATIVES: [&[Neighbourhood]; 5] = [
+    &[0],
+    &[1, 2],
+    &[5, 10, 3, 40, 33, 68],
+    &[69, 42, 11, 7, 98, 13, 14, 70, 41, 97],
+    &[325, 170, 15, 45, 99, 71, 106, 102, 43, 101, 105, 78, 108],
+];
+
+/// An isotropic rule for two-state cellular automata on the Moore
+/// neighbourhood.
+///
+/// Whether a cell is alive in the next generation depends on whether it is
+/// alive now and on which cells in its neighbourhood are alive. Rules can be
+/// outer-totalistic (`B3/S23`), in which case only the number of live
+/// neighbours matters, or non-totalistic (`B2n3/S23-q`), in which case Hensel
+/// notation is used to distinguish neighbourhoods with the same count.
+///
+/// Rules can also be Generations rules (`B2/S/C3`), which have more than two
+/// states: A live cell that doesn't survive starts dying and goes through the
+/// remaining states one generation at a time before it is dead. Dying cells
+/// don't count as live neighbours, but they can't be reborn either.
+///
+/// Totalistic rules can use other `Neighbours` than the Moore neighbourhood,
+/// which are given as a suffix: `M` (Moore), `V` (von Neumann), `H`
+/// (hexagonal) or `T` (triangular), optionally followed by the range
+/// (`B2/S34H`, `B3/S23M2`). Only counts up to 8 can be written, so larger
+/// neighbourhoods never have more live neighbours than that for births or
+/// survivals.
+///
+/// Larger than Life rules (`R5,C0,M1,S34..58,B34..45,NM`) have a range of
+/// neighbour counts for births and survivals instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// Whether a cell is alive in the next generation, indexed by
+    /// `Neighbourhood`.
+    transitions: Vec<bool>,
+    /// The number of states, including the dead state.
+    states: State,
+    /// The cells that count as neighbours.
+    neighbours: Neighbours,
+    /// The counts of a Larger than Life rule, which replace `transitions`.
+    ltl: Option<Ltl>,
+}
+
+/// Birth and survival conditions of a Larger than Life rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ltl {
+    /// Whether a live cell counts itself as a neighbour.
+    middle: bool,
+    /// Inclusive range of counts on which a live cell survives.
+    survival: (u32, u32),
+    /// Inclusive range of counts on which a dead cell is born.
+    birth: (u32, u32),
+}
+
+/// Errors that can occur while parsing a rulestring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    /// The rulestring isn't of the form `B3/S23`, `23/3`, `B2/S/C3`, `/2/3`
+    /// or `R5,C0,M1,S34..58,B34..45,NM`.
+    Format(String),
+    /// An unexpected character in the rulestring.
+    InvalidCharacter(char),
+    /// A neighbour count larger than 8.
+    InvalidCount(u8),
+    /// A Hensel letter that doesn't exist for the neighbour count it follows.
+    InvalidLetter(u8, char),
+    /// The number of states of a Generations rule isn't a number between 2
+    /// and 255.
+    InvalidStates(String),
+    /// The rule lets cells be born with zero neighbours, which would fill the
+    /// whole (unbounded) universe.
+    BirthOnZero,
+    /// Hensel notation is only supported on the Moore neighbourhood.
+    NotTotalistic,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuleError::Format(ref rule) => {
+                write!(f, "invalid rulestring \"{}\" (expected e.g. B3/S23 \
+                           or 23/3)", rule)
+            }
+            RuleError::InvalidCharacter(c) => {
+                write!(f, "unexpected character '{}' in rulestring", c)
+            }
+            RuleError::InvalidCount(count) => {
+                write!(f, "invalid neighbour count {}", count)
+            }
+            RuleError::InvalidLetter(count, letter) => {
+                write!(f, "invalid letter '{}' for neighbour count {}",
+                       letter, count)
+            }
+            RuleError::InvalidStates(ref states) => {
+                write!(f, "invalid number of states \"{}\"", states)
+            }
+            RuleError::BirthOnZero => {
+                write!(f, "B0 rules are not supported")
+            }
+            RuleError::NotTotalistic => {
+                write!(f, "Hensel notation requires the Moore neighbourhood")
+            }
+        }
+    }
+}
+
+impl Error for RuleError {}
+
+impl Rule {
+    /// Create a new outer-totalistic rule from the neighbour counts on which a
+    /// dead cell is born and on which a live cell survives.
+    ///
+    /// # Errors
+    /// Fails if a count is larger than 8 or if `birth` contains 0.
+    pub fn new(birth: &[u8], survival: &[u8]) -> Result<Rule, RuleError> {
+        let mut rule = Rule::empty();
+        for &count in birth {
+            rule.set_count(count, None, false)?;
+        }
+        for &count in survival {
+            rule.set_count(count, None, true)?;
+        }
+        rule.check()
+    }
+
+    /// Conway's Game of Life (B3/S23).
+    pub fn conway() -> Rule {
+        Rule::new(&[3], &[2, 3]).unwrap()
+    }
+
+    /// Turn the rule into a Generations rule with the given number of
+    /// `states` (including the dead state).
+    ///
+    /// # Errors
+    /// Fails if `states` is smaller than 2.
+    pub fn with_states(self, states: State) -> Result<Rule, RuleError> {
+        if states < 2 {
+            Err(RuleError::InvalidStates(states.to_string()))
+        } else {
+            Ok(Rule {
+                states,
+                ..self
+            })
+        }
+    }
+
+    /// Get the number of states (including the dead state).
+    pub fn states(&self) -> State {
+        self.states
+    }
+
+    /// Use other `neighbours` than the Moore neighbourhood.
+    ///
+    /// # Errors
+    /// Fails if the rule isn't totalistic (unless `neighbours` is the Moore
+    /// neighbourhood).
+    pub fn with_neighbours(self, neighbours: Neighbours)
+                           -> Result<Rule, RuleError> {
+        if neighbours != Neighbours::default() && !self.is_totalistic() {
+            Err(RuleError::NotTotalistic)
+        } else {
+            Ok(Rule {
+                neighbours,
+                ..self
+            })
+        }
+    }
+
+    /// Get the cells that count as neighbours.
+    pub fn neighbours(&self) -> Neighbours {
+        self.neighbours
+    }
+
+    /// Check if the cell in the middle of `neighbourhood` is alive in the
+    /// next generation.
+    ///
+    /// Cells that are not part of the `neighbours` of the rule are ignored.
+    /// If the neighbours don't fit into a `Neighbourhood`, use `next_count`
+    /// instead.
+    pub fn next(&self, neighbourhood: Neighbourhood) -> bool {
+        match self.neighbours.mask() {
+            Some(mask) if mask != OUTER || self.ltl.is_some() => {
+                let count = (neighbourhood & mask).count_ones();
+                self.next_count(neighbourhood & CENTRE != 0, count)
+            }
+            _ => self.transitions[neighbourhood as usize],
+        }
+    }
+
+    /// Check if a cell is alive in the next generation, given whether it is
+    /// `alive` now and its number of live neighbours.
+    ///
+    /// This only makes sense for totalistic rules.
+    pub fn next_count(&self, alive: bool, count: u32) -> bool {
+        if let Some(ltl) = self.ltl {
+            let count = if alive && ltl.middle { count + 1 } else { count };
+            let (min, max) = if alive { ltl.survival } else { ltl.birth };
+            return min <= count && count <= max
+        } else if count > 8 {
+            return false
+        }
+        let centre = if alive { CENTRE } else { 0 };
+        let rep = Self::representatives(count as u8)[0];
+        self.transitions[(rep | centre) as usize]
+    }
+
+    /// Get the state of a cell in the next generation.
+    ///
+    /// `neighbourhood` must only contain the cells that are `ALIVE`, in
+    /// particular `CENTRE` must be set exactly if `state` is `ALIVE`.
+    pub fn next_state(&self, state: State, neighbourhood: Neighbourhood)
+                      -> State {
+        match state {
+            0 | ALIVE if self.next(neighbourhood) => ALIVE,
+            0 => 0,
+            _ if state + 1 < self.states => state + 1,
+            _ => 0,
+        }
+    }
+
+    /// Get the state of a cell in the next generation, given its number of
+    /// live neighbours.
+    ///
+    /// This only makes sense for totalistic rules.
+    pub fn next_state_count(&self, state: State, count: u32) -> State {
+        match state {
+            0 | ALIVE if self.next_count(state == ALIVE, count) => ALIVE,
+            0 => 0,
+            _ if state + 1 < self.states => state + 1,
+            _ => 0,
+        }
+    }
+
+    /// Check if the rule only depends on the number of live neighbours.
+    pub fn is_totalistic(&self) -> bool {
+        (0..self.transitions.len()).all(|n| {
+            let count = (n as Neighbourhood & OUTER).count_ones() as u8;
+            let alive = n as Neighbourhood & CENTRE != 0;
+            let rep = Self::representatives(count)[0];
+            let rep = if alive { rep | CENTRE } else { rep };
+            self.transitions[n] == self.transitions[rep as usize]
+        })
+    }
+
+    /// Get the bit of a `Neighbourhood` that belongs to the cell at `offset`
+    /// from the centre.
+    ///
+    /// # Panics
+    /// Panics if `offset` isn't in the Moore neighbourhood.
+    pub fn neighbour_bit(offset: Point) -> Neighbourhood {
+        assert!(-1 <= offset.0 && offset.0 <= 1 &&
+                -1 <= offset.1 && offset.1 <= 1,
+                "{:?} is not in the Moore neighbourhood", offset);
+        1 << ((1 - offset.1) * 3 + offset.0 + 1)
+    }
+
+    fn empty() -> Rule {
+        Rule {
+            transitions: vec![false; 512],
+            states: 2,
+            neighbours: Neighbours::default(),
+            ltl: None,
+        }
+    }
+
+    fn check(self) -> Result<Rule, RuleError> {
+        if self.transitions[0] {
+            Err(RuleError::BirthOnZero)
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn letters(count: u8) -> &'static str {
+        LETTERS[if count > 4 { 8 - count } else { count } as usize]
+    }
+
+    fn representatives(count: u8) -> Vec<Neighbourhood> {
+        if count > 4 {
+            REPRESENTATIVES[(8 - count) as usize].iter()
+                .map(|n| n ^ OUTER).collect()
+        } else {
+            REPRESENTATIVES[count as usize].to_vec()
+        }
+    }
+
+    /// Enable all transitions for `count` live neighbours, or only those
+    /// belonging to `letter` if given.
+    fn set_count(&mut self, count: u8, letter: Option<char>, alive: bool)
+                 -> Result<(), RuleError> {
+        if count > 8 {
+            return Err(RuleError::InvalidCount(count))
+        }
+        let representatives = Self::representatives(count);
+        let representatives = match letter {
+            Some(letter) => {
+                let index = Self::letters(count).find(letter)
+                    .ok_or(RuleError::InvalidLetter(count, letter))?;
+                vec![representatives[index]]
+            }
+            None => representatives,
+        };
+        let centre = if alive { CENTRE } else { 0 };
+        for rep in representatives {
+            for n in Self::symmetries(rep).iter() {
+                self.transitions[(n | centre) as usize] = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get all rotations and reflections of a neighbourhood.
+    fn symmetries(n: Neighbourhood) -> [Neighbourhood; 8] {
+        let mut result = [n; 8];
+        for i in 1..4 {
+            result[i] = Self::rotate(result[i - 1]);
+        }
+        for i in 4..8 {
+            result[i] = Self::reflect(result[i - 4]);
+        }
+        result
+    }
+
+    /// Rotate a neighbourhood by 90 degrees clockwise.
+    fn rotate(n: Neighbourhood) -> Neighbourhood {
+        Self::map_bits(n, |row, col| (col, 2 - row))
+    }
+
+    /// Reflect a neighbourhood along the vertical axis.
+    fn reflect(n: Neighbourhood) -> Neighbourhood {
+        Self::map_bits(n, |row, col| (row, 2 - col))
+    }
+
+    fn map_bits<F: Fn(u16, u16) -> (u16, u16)>(n: Neighbourhood, f: F)
+                                               -> Neighbourhood {
+        let mut result = 0;
+        for bit in 0..9 {
+            if n & (1 << bit) != 0 {
+                let (row, col) = f(bit / 3, bit % 3);
+                result |= 1 << (row * 3 + col);
+            }
+        }
+        result
+    }
+
+    fn parse_part(&mut self, part: &str, alive: bool) -> Result<(), RuleError> {
+        let mut chars = part.chars().peekable();
+        while let Some(c) = chars.next() {
+            let count = c.to_digit(10).ok_or(RuleError::InvalidCharacter(c))?;
+            let count = count as u8;
+            let negate = chars.peek() == Some(&'-');
+            if negate {
+                let _ = chars.next();
+            }
+
+            let mut letters = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_lowercase() {
+                    break
+                }
+                letters.push(c);
+                let _ = chars.next();
+            }
+
+            if negate && letters.is_empty() {
+                return Err(RuleError::InvalidCharacter('-'))
+            } else if letters.is_empty() || negate {
+                self.set_count(count, None, alive)?;
+            }
+            // Validate all letters before removing anything, so that we can
+            // report the right error
+            let mut selected = Rule::empty();
+            for letter in letters.chars() {
+                selected.set_count(count, Some(letter), alive)?;
+            }
+            for (t, &s) in self.transitions.iter_mut()
+                .zip(selected.transitions.iter()) {
+                if s {
+                    *t = !negate;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a Larger than Life rulestring (`R5,C0,M1,S34..58,B34..45,NM`).
+    fn parse_ltl(s: &str) -> Result<Rule, RuleError> {
+        let format_error = || RuleError::Format(s.to_string());
+        let parts: Vec<&str> = s.split(',').collect();
+        let prefixes = ["R", "C", "M", "S", "B", "N"];
+        let has_prefix = |part: &str, prefix: &str| {
+            part.get(..1).map(|p| p.eq_ignore_ascii_case(prefix)) == Some(true)
+        };
+        if parts.len() != prefixes.len() || parts.iter().zip(&prefixes)
+            .any(|(part, prefix)| !has_prefix(part, prefix)) {
+            return Err(format_error())
+        }
+        let values: Vec<&str> = parts.iter().map(|part| &part[1..]).collect();
+        let counts = |range: &str| {
+            let mut bounds = range.splitn(2, "..").map(str::parse);
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(min)), Some(Ok(max))) if min <= max => Ok((min, max)),
+                _ => Err(format_error()),
+            }
+        };
+
+        let range = match values[0].parse() {
+            Ok(range) if range > 0 => range,
+            _ => return Err(format_error()),
+        };
+        let states = match values[1].parse::<State>() {
+            Ok(states) => states.max(2),
+            Err(_) => return Err(RuleError::InvalidStates(values[1].into())),
+        };
+        let middle = match values[2] {
+            "0" => false,
+            "1" => true,
+            _ => return Err(format_error()),
+        };
+        let shape = match values[5] {
+            "M" | "m" => Shape::Moore,
+            "N" | "n" => Shape::VonNeumann,
+            _ => return Err(format_error()),
+        };
+
+        let ltl = Ltl {
+            middle,
+            survival: counts(values[3])?,
+            birth: counts(values[4])?,
+        };
+        if ltl.birth.0 == 0 {
+            return Err(RuleError::BirthOnZero)
+        }
+        Ok(Rule {
+            states,
+            neighbours: Neighbours::new(shape, range),
+            ltl: Some(ltl),
+            ..Rule::empty()
+        })
+    }
+
+    /// Print the rule in Larger than Life notation.
+    fn write_ltl(&self, f: &mut fmt::Formatter, ltl: &Ltl) -> fmt::Result {
+        let states = if self.states > 2 { self.states } else { 0 };
+        let shape = match self.neighbours.shape() {
+            Shape::VonNeumann => 'N',
+            _ => 'M',
+        };
+        write!(f, "R{},C{},M{},S{}..{},B{}..{},N{}",
+               self.neighbours.range(), states, ltl.middle as u8,
+               ltl.survival.0, ltl.survival.1, ltl.birth.0, ltl.birth.1,
+               shape)
+    }
+
+    /// Split the neighbourhood suffix (e.g. `H` or `M2`) off a rulestring.
+    fn split_neighbours(s: &str) -> Result<(&str, Neighbours), RuleError> {
+        let rest = s.trim_end_matches(|c: char| c.is_ascii_digit());
+        let shape = match rest.chars().last() {
+            Some('M') => Shape::Moore,
+            Some('V') => Shape::VonNeumann,
+            Some('H') => Shape::Hexagonal,
+            Some('T') => Shape::Triangular,
+            _ => return Ok((s, Neighbours::default())),
+        };
+        let range = match &s[rest.len()..] {
+            "" => 1,
+            range => match range.parse() {
+                Ok(range) if range > 0 => range,
+                _ => return Err(RuleError::Format(s.to_string())),
+            },
+        };
+        Ok((&rest[..rest.len() - 1], Neighbours::new(shape, range)))
+    }
+
+    fn write_part(&self, f: &mut fmt::Formatter, alive: bool) -> fmt::Result {
+        let centre = if alive { CENTRE } else { 0 };
+        for count in 0..9 {
+            let enabled: Vec<bool> = Self::representatives(count).iter()
+                .map(|n| self.transitions[(n | centre) as usize])
+                .collect();
+            let included = enabled.iter().filter(|&&e| e).count();
+            if included == 0 {
+                continue
+            }
+            write!(f, "{}", count)?;
+            if included == enabled.len() {
+                continue
+            }
+
+            // Use whichever notation needs fewer letters
+            let negate = included > enabled.len() - included;
+            if negate {
+                write!(f, "-")?;
+            }
+            for (letter, &e) in Self::letters(count).chars().zip(&enabled) {
+                if e != negate {
+                    write!(f, "{}", letter)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule::conway()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    /// Parse a rulestring in B/S notation (`B36/S23`) or S/B notation
+    /// (`23/36`).
+    ///
+    /// The `B` and `S` prefixes are case-insensitive and may appear in either
+    /// order. Each neighbour count may be followed by Hensel letters to only
+    /// select some of its neighbourhoods (`B2n3`) or by a minus sign and
+    /// letters to select all but those neighbourhoods (`S23-q`).
+    ///
+    /// Generations rules have the number of states as a third part, either
+    /// with a `C` (or `G`) prefix (`B2/S345/C4`) or without (`345/2/4`).
+    ///
+    /// The rulestring may end with a suffix for the `Neighbours`.
+    ///
+    /// Larger than Life rules are given as a comma-separated list of the
+    /// range, the number of states (0 for two states), whether cells count
+    /// themselves, the survival and birth counts and the shape of the
+    /// neighbourhood (Moore or von Neumann): `R5,C0,M1,S34..58,B34..45,NM`.
+    fn from_str(s: &str) -> Result<Rule, RuleError> {
+        if s.trim().starts_with(['R', 'r']) {
+            return Self::parse_ltl(s.trim())
+        }
+        let format_error = || RuleError::Format(s.to_string());
+        let (rule, neighbours) = Self::split_neighbours(s.trim())?;
+        let parts: Vec<&str> = rule.split('/').collect();
+        if parts.len() != 2 && parts.len() != 3 {
+            return Err(format_error())
+        }
+
+        let prefix = |part: &str| {
+            part.chars().next().map(|c| c.to_ascii_uppercase())
+                .and_then(|c| match c {
+                    'B' | 'S' | 'C' => Some(c),
+                    'G' => Some('C'),
+                    _ => None,
+                })
+        };
+        let prefixes: Vec<Option<char>> = parts.iter()
+            .map(|part| prefix(part)).collect();
+        let (mut birth, mut survival, mut states) = (None, None, None);
+        if prefixes.iter().all(Option::is_some) {
+            for (part, prefix) in parts.iter().zip(prefixes) {
+                let target = match prefix {
+                    Some('B') => &mut birth,
+                    Some('S') => &mut survival,
+                    _ => &mut states,
+                };
+                if target.is_some() {
+                    return Err(format_error())
+                }
+                *target = Some(&part[1..]);
+            }
+        } else if prefixes.iter().all(Option::is_none) {
+            survival = Some(parts[0]);
+            birth = Some(parts[1]);
+            states = parts.get(2).cloned();
+        } else {
+            return Err(format_error())
+        }
+        let (birth, survival) = match (birth, survival) {
+            (Some(birth), Some(survival)) => (birth, survival),
+            _ => return Err(format_error()),
+        };
+
+        let mut rule = Rule::empty();
+        rule.parse_part(birth, false)?;
+        rule.parse_part(survival, true)?;
+        if let Some(states) = states {
+            let invalid = || RuleError::InvalidStates(states.to_string());
+            rule = rule.with_states(states.parse().map_err(|_| invalid())?)
+                .map_err(|_| invalid())?;
+        }
+        rule.check()?.with_neighbours(neighbours)
+    }
+}
+
+impl fmt::Display for Rule {
+    /// Print the rule in B/S notation, using Hensel notation if the rule is
+    /// not totalistic.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref ltl) = self.ltl {
+            return self.write_ltl(f, ltl)
+        }
+        write!(f, "B")?;
+        self.write_part(f, false)?;
+        write!(f, "/S")?;
+        self.write_part(f, true)?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        if self.neighbours != Neighbours::default() {
+            let shape = match self.neighbours.shape() {
+                Shape::Moore => 'M',
+                Shape::VonNeumann => 'V',
+                Shape::Hexagonal => 'H',
+                Shape::Triangular => 'T',
+            };
+            write!(f, "{}", shape)?;
+            if self.neighbours.range() > 1 {
+                write!(f, "{}", self.neighbours.range())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use backend::neighbours::{Neighbours, Shape};
+
+    fn parse(rule: &str) -> Result<Rule, RuleError> {
+        rule.parse()
+    }
+
+    /// Build a neighbourhood from a 3x3 picture (`O` is alive).
+    fn neighbourhood(picture: &str) -> Neighbourhood {
+        picture.chars().filter(|c| !c.is_whitespace()).enumerate()
+            .filter(|&(_, c)| c == 'O')
+            .fold(0, |n, (i, _)| n | 1 << i)
+    }
+
+    #[test]
+    fn conway() {
+        let rule = parse("B3/S23").unwrap();
+        assert_eq!(rule, Rule::conway());
+        assert!(rule.is_totalistic());
+        assert!(rule.next(neighbourhood("O.O ... .O.")));
+        assert!(!rule.next(neighbourhood("O.O ... ...")));
+        assert!(rule.next(neighbourhood("O.O .O. ...")));
+        assert!(rule.next(neighbourhood("O.O .O. O..")));
+        assert!(!rule.next(neighbourhood("O.O .O. O.O")));
+        assert!(!rule.next(neighbourhood("... .O. ..O")));
+    }
+
+    #[test]
+    fn notations() {
+        assert_eq!(parse("23/3").unwrap(), Rule::conway());
+        assert_eq!(parse("b3/s23").unwrap(), Rule::conway());
+        assert_eq!(parse("S23/B3").unwrap(), Rule::conway());
+        assert_eq!(parse("B36/S23").unwrap(), "23/36".parse().unwrap());
+        assert_eq!(parse("B2/S").unwrap(), parse("/2").unwrap());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Rule::conway().to_string(), "B3/S23");
+        assert_eq!(parse("23/36").unwrap().to_string(), "B36/S23");
+        assert_eq!(parse("34678/3678").unwrap().to_string(),
+                   "B3678/S34678");
+        assert_eq!(parse("/2").unwrap().to_string(), "B2/S");
+        assert_eq!(parse("B3/S012345678").unwrap().to_string(),
+                   "B3/S012345678");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("B3S23"), Err(RuleError::Format("B3S23".into())));
+        assert_eq!(parse("B3/S2/3"),
+                   Err(RuleError::Format("B3/S2/3".into())));
+        assert_eq!(parse("B3/B23"), Err(RuleError::Format("B3/B23".into())));
+        assert_eq!(parse("B3/23"), Err(RuleError::Format("B3/23".into())));
+        assert_eq!(parse("B39/S23"), Err(RuleError::InvalidCount(9)));
+        assert_eq!(parse("B3/S2x"), Err(RuleError::InvalidLetter(2, 'x')));
+        assert_eq!(parse("B3/S2X"), Err(RuleError::InvalidCharacter('X')));
+        assert_eq!(parse("B03/S23"), Err(RuleError::BirthOnZero));
+        assert_eq!(parse("B1e/S4x"), Err(RuleError::InvalidLetter(4, 'x')));
+        assert_eq!(parse("B1t/S"), Err(RuleError::InvalidLetter(1, 't')));
+        assert_eq!(parse("B3-/S23"), Err(RuleError::InvalidCharacter('-')));
+        assert_eq!(parse("B2/S/C3/4"),
+                   Err(RuleError::Format("B2/S/C3/4".into())));
+        assert_eq!(parse("B2/S/B3"), Err(RuleError::Format("B2/S/B3".into())));
+        assert_eq!(parse("B2/C3/4"), Err(RuleError::Format("B2/C3/4".into())));
+        assert_eq!(parse("B2/S/C1"), Err(RuleError::InvalidStates("1".into())));
+        assert_eq!(parse("/2/x"), Err(RuleError::InvalidStates("x".into())));
+        assert_eq!(parse("/2/256"),
+                   Err(RuleError::InvalidStates("256".into())));
+        assert_eq!(Rule::new(&[3], &[9]), Err(RuleError::InvalidCount(9)));
+    }
+
+    #[test]
+    fn letter_classes() {
+        // Every neighbourhood belongs to exactly one letter
+        for count in 0..9 {
+            let mut seen = BTreeSet::new();
+            let mut total = 0;
+            for rep in Rule::representatives(count) {
+                let class: BTreeSet<Neighbourhood> =
+                    Rule::symmetries(rep).iter().cloned().collect();
+                assert!(class.iter().all(|n| n.count_ones() == count as u32));
+                assert!(class.is_disjoint(&seen));
+                total += class.len();
+                seen = seen.union(&class).cloned().collect();
+            }
+            let expected = (0..256u16)
+                .filter(|n| n.count_ones() == count as u32).count();
+            assert_eq!(total, expected);
+        }
+    }
+
+    #[test]
+    fn hensel() {
+        let rule = parse("B2n3/S23-q").unwrap();
+        assert!(!rule.is_totalistic());
+        assert_eq!(rule.to_string(), "B2n3/S23-q");
+        assert!(rule.next(neighbourhood("O.. ... ..O")));
+        assert!(!rule.next(neighbourhood("O.O ... ...")));
+        assert!(rule.next(neighbourhood("O.. .O. ..O")));
+        assert!(rule.next(neighbourhood("O.O .O. .O.")));
+        assert!(!rule.next(neighbourhood(".OO .O. O..")));
+        assert!(rule.next(neighbourhood(".OO ... O..")));
+    }
+
+    #[test]
+    fn hensel_totalistic() {
+        assert_eq!(parse("B3cekainyqjr/S2cekain3").unwrap(), Rule::conway());
+        assert_eq!(parse("B3/S2-cekain3").unwrap(), parse("B3/S3").unwrap());
+        assert_eq!(parse("B2-c/S").unwrap(), parse("B2ekain/S").unwrap());
+        assert_eq!(parse("B2-c/S").unwrap().to_string(), "B2-c/S");
+        assert_eq!(parse("B2ce/S").unwrap().to_string(), "B2ce/S");
+        assert_eq!(parse("B4cekai/S").unwrap().to_string(), "B4ceaik/S");
+        assert_eq!(parse("B4-cekai/S").unwrap().to_string(),
+                   "B4-ceaik/S");
+    }
+
+    #[test]
+    fn neighbour_bit() {
+        assert_eq!(Rule::neighbour_bit((-1, 1)), 1);
+        assert_eq!(Rule::neighbour_bit((0, 0)), CENTRE);
+        assert_eq!(Rule::neighbour_bit((1, 0)), 32);
+        assert_eq!(Rule::neighbour_bit((1, -1)), 256);
+    }
+
+    #[test]
+    fn generations() {
+        let brians_brain = parse("/2/3").unwrap();
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!(parse("B2/S/C3").unwrap(), brians_brain);
+        assert_eq!(parse("b2/s/g3").unwrap(), brians_brain);
+        assert_eq!(parse("C3/B2/S").unwrap(), brians_brain);
+        assert_eq!(brians_brain.to_string(), "B2/S/C3");
+        assert_eq!(parse("345/2/4").unwrap().to_string(), "B2/S345/C4");
+        assert_eq!(parse("23/3/2").unwrap(), Rule::conway());
+        assert_eq!(Rule::conway().with_states(3).unwrap().to_string(),
+                   "B3/S23/C3");
+        assert_eq!(Rule::conway().with_states(1),
+                   Err(RuleError::InvalidStates("1".into())));
+    }
+
+    #[test]
+    fn next_state() {
+        let star_wars = parse("345/2/4").unwrap();
+        let two = neighbourhood("O.O ... ...");
+        let three = neighbourhood("O.O ... .O.");
+        assert_eq!(star_wars.next_state(0, two), ALIVE);
+        assert_eq!(star_wars.next_state(0, three), 0);
+        assert_eq!(star_wars.next_state(ALIVE, three | CENTRE), ALIVE);
+        assert_eq!(star_wars.next_state(ALIVE, two | CENTRE), 2);
+        assert_eq!(star_wars.next_state(2, two), 3);
+        assert_eq!(star_wars.next_state(3, three), 0);
+        assert_eq!(Rule::conway().next_state(ALIVE, CENTRE), 0);
+    }
+
+    #[test]
+    fn larger_than_life() {
+        let bosco = parse("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        assert_eq!(bosco.neighbours(), Neighbours::new(Shape::Moore, 5));
+        assert_eq!(bosco.states(), 2);
+        assert!(bosco.is_totalistic());
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        // The cell itself counts towards survival
+        assert!(bosco.next_count(true, 33));
+        assert!(!bosco.next_count(true, 32));
+        assert!(bosco.next_count(true, 57));
+        assert!(!bosco.next_count(true, 58));
+        assert!(!bosco.next_count(false, 33));
+        assert!(bosco.next_count(false, 34));
+        assert!(bosco.next_count(false, 45));
+        assert!(!bosco.next_count(false, 46));
+
+        let rule = parse("r2,c3,m0,s2..3,b3..3,nn").unwrap();
+        assert_eq!(rule.neighbours(), Neighbours::new(Shape::VonNeumann, 2));
+        assert_eq!(rule.states(), 3);
+        assert_eq!(rule.to_string(), "R2,C3,M0,S2..3,B3..3,NN");
+        assert_eq!(rule.next_state_count(ALIVE, 4), 2);
+
+        // Range 1 rules work on neighbourhoods, too
+        let rule = parse("R1,C0,M0,S2..3,B3..3,NM").unwrap();
+        assert!(rule.next(neighbourhood("O.O ... .O.")));
+        assert!(!rule.next(neighbourhood("O.O ... ...")));
+        assert!(rule.next(neighbourhood("O.O .O. ...")));
+
+        let error = |rule: &str| Err(RuleError::Format(rule.into()));
+        assert_eq!(parse("R5,C0,M1,S34..58,B34..45"),
+                   error("R5,C0,M1,S34..58,B34..45"));
+        assert_eq!(parse("R5,C0,M2,S34..58,B34..45,NM"),
+                   error("R5,C0,M2,S34..58,B34..45,NM"));
+        assert_eq!(parse("R5,C0,M1,S58..34,B34..45,NM"),
+                   error("R5,C0,M1,S58..34,B34..45,NM"));
+        assert_eq!(parse("R5,C0,M1,S34,B34..45,NM"),
+                   error("R5,C0,M1,S34,B34..45,NM"));
+        assert_eq!(parse("R0,C0,M1,S34..58,B34..45,NM"),
+                   error("R0,C0,M1,S34..58,B34..45,NM"));
+        assert_eq!(parse("R5,C0,M1,S34..58,B34..45,NX"),
+                   error("R5,C0,M1,S34..58,B34..45,NX"));
+        assert_eq!(parse("R5,Cx,M1,S34..58,B34..45,NM"),
+                   Err(RuleError::InvalidStates("x".into())));
+        assert_eq!(parse("R5,C0,M1,S34..58,B0..45,NM"),
+                   Err(RuleError::BirthOnZero));
+    }
+
+    #[test]
+    fn neighbours() {
+        let hex = parse("B2/S34H").unwrap();
+        assert_eq!(hex.neighbours(), Neighbours::new(Shape::Hexagonal, 1));
+        assert_eq!(hex.to_string(), "B2/S34H");
+        // The corners at the top right and bottom left don't count
+        assert!(hex.next(neighbourhood("O.. ... ..O")));
+        assert!(hex.next(neighbourhood("O.O ... ..O")));
+        assert!(!hex.next(neighbourhood("O.. ... .OO")));
+        assert!(!hex.next(neighbourhood("..O ... O..")));
+
+        let von_neumann = parse("B1/S1V").unwrap();
+        assert!(von_neumann.next(neighbourhood("OOO ... O.O")));
+        assert!(!von_neumann.next(neighbourhood(".O. O.. ...")));
+        assert_eq!(von_neumann.to_string(), "B1/S1V");
+
+        assert_eq!(parse("23/3M").unwrap(), Rule::conway());
+        assert_eq!(parse("B3/S23M").unwrap().to_string(), "B3/S23");
+        let range = parse("b5/s45/c3M2").unwrap();
+        assert_eq!(range.neighbours(), Neighbours::new(Shape::Moore, 2));
+        assert_eq!(range.states(), 3);
+        assert_eq!(range.to_string(), "B5/S45/C3M2");
+        assert_eq!(parse("B2/S34T").unwrap().to_string(), "B2/S34T");
+
+        assert_eq!(range.next_state_count(0, 5), ALIVE);
+        assert_eq!(range.next_state_count(0, 9), 0);
+        assert_eq!(range.next_state_count(ALIVE, 4), ALIVE);
+        assert_eq!(range.next_state_count(ALIVE, 12), 2);
+        assert_eq!(range.next_state_count(2, 5), 0);
+
+        assert_eq!(parse("B2e/S34H"), Err(RuleError::NotTotalistic));
+        assert_eq!(parse("B2/S34H0"),
+                   Err(RuleError::Format("B2/S34H0".into())));
+        assert_eq!(parse("B2/S34X"), Err(RuleError::InvalidCharacter('X')));
+    }
+}
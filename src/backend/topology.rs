@@ -0,0 +1,226 @@
+//! Topologies of the universe.
+
+use super::{Coord, Point};
+use super::data::AABB;
+use super::neighbours::Shape;
+
+/// The shape of the universe.
+///
+/// Bounded topologies are defined by an `AABB`. Just like Golly's bounded
+/// grids, the edges of a torus are joined directly, while a twisted edge is
+/// joined to the opposite edge reversed. All cells outside of the bounding
+/// box are dead.
+#[derive(Debug, Clone, Default)]
+pub enum Topology {
+    /// An infinite plane.
+    #[default]
+    Unbounded,
+    /// A finite plane, the cells beyond the edges are always dead.
+    Plane(AABB),
+    /// The left and right as well as the top and bottom edges are joined.
+    Torus(AABB),
+    /// Like a torus, but the top and bottom edges are joined with a twist.
+    KleinBottle(AABB),
+    /// Both pairs of edges are joined with a twist.
+    CrossSurface(AABB),
+}
+
+impl Topology {
+    /// Get the bounding box of a bounded topology.
+    pub fn area(&self) -> Option<&AABB> {
+        match *self {
+            Topology::Unbounded => None,
+            Topology::Plane(ref area) |
+            Topology::Torus(ref area) |
+            Topology::KleinBottle(ref area) |
+            Topology::CrossSurface(ref area) => Some(area),
+        }
+    }
+
+    /// Check if the edges of the topology are joined.
+    pub fn wraps(&self) -> bool {
+        !matches!(*self, Topology::Unbounded | Topology::Plane(_))
+    }
+
+    /// Check if neighbourhoods of a given `shape` can be used in the
+    /// universe.
+    ///
+    /// Twisted edges mirror the grid, which keeps square neighbourhoods
+    /// intact, but not the skewed hexagonal or the triangular ones.
+    pub fn supports(&self, shape: Shape) -> bool {
+        match *self {
+            Topology::KleinBottle(_) | Topology::CrossSurface(_) => {
+                matches!(shape, Shape::Moore | Shape::VonNeumann)
+            }
+            _ => true,
+        }
+    }
+
+    /// Check if a `point` is part of the universe.
+    pub fn contains(&self, point: Point) -> bool {
+        self.area().map(|area| area.contains(point)).unwrap_or(true)
+    }
+
+    /// Find the neighbour of `point` at `offset`.
+    ///
+    /// Returns the neighbour as well as the offset at which `point` lies from
+    /// the neighbour's point of view (which is only different from `-offset`
+    /// if a twisted edge was crossed), or `None` if the neighbour is beyond
+    /// the edge of a bounded plane. Across twisted edges, this is only
+    /// meaningful for the offsets of shapes the topology `supports`.
+    pub fn neighbour(&self, point: Point, offset: Point)
+                     -> Option<(Point, Point)> {
+        let neighbour = (point.0 + offset.0, point.1 + offset.1);
+        let back = (-offset.0, -offset.1);
+        let (area, twist_x, twist_y) = match *self {
+            Topology::Unbounded => return Some((neighbour, back)),
+            Topology::Plane(ref area) => {
+                return if area.contains(neighbour) {
+                    Some((neighbour, back))
+                } else {
+                    None
+                }
+            }
+            Topology::Torus(ref area) => (area, false, false),
+            Topology::KleinBottle(ref area) => (area, true, false),
+            Topology::CrossSurface(ref area) => (area, true, true),
+        };
+
+        let size = 2 * area.half_dim();
+        let min = (area.center().0 - area.half_dim(),
+                   area.center().1 - area.half_dim());
+        let (x, y) = (neighbour.0 - min.0, neighbour.1 - min.1);
+        let (mut wrapped_x, mut wrapped_y) = (wrap(x, size), wrap(y, size));
+        let (crossed_x, crossed_y) = (wrapped_x != x, wrapped_y != y);
+        let mut back = back;
+        // Crossing the top or bottom edge reverses the x axis if they are
+        // twisted (and vice versa)
+        if twist_x && crossed_y {
+            wrapped_x = size - 1 - wrapped_x;
+            back.0 = -back.0;
+        }
+        if twist_y && crossed_x {
+            wrapped_y = size - 1 - wrapped_y;
+            back.1 = -back.1;
+        }
+        Some(((wrapped_x + min.0, wrapped_y + min.1), back))
+    }
+}
+
+/// Wrap `coord` into `0..size`.
+fn wrap(coord: Coord, size: Coord) -> Coord {
+    ((coord % size) + size) % size
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+
+    const OFFSETS: [Point; 8] = [
+        (-1,  1), (0,  1), (1,  1),
+        (-1,  0),          (1,  0),
+        (-1, -1), (0, -1), (1, -1),
+    ];
+
+    fn area() -> AABB {
+        AABB::new((2, -1), 4)
+    }
+
+    #[test]
+    fn unbounded() {
+        let topology = Topology::default();
+        assert!(topology.area().is_none());
+        assert!(topology.contains((1000, -1000)));
+        assert_eq!(topology.neighbour((1000, 5), (1, -1)),
+                   Some(((1001, 4), (-1, 1))));
+    }
+
+    #[test]
+    fn plane() {
+        let topology = Topology::Plane(area());
+        assert!(topology.contains((-2, -5)));
+        assert!(!topology.contains((6, 0)));
+        assert_eq!(topology.neighbour((5, 2), (1, 0)), None);
+        assert_eq!(topology.neighbour((5, 2), (-1, 0)),
+                   Some(((4, 2), (1, 0))));
+    }
+
+    #[test]
+    fn torus() {
+        let topology = Topology::Torus(area());
+        assert_eq!(topology.neighbour((5, 2), (1, 1)),
+                   Some(((-2, -5), (-1, -1))));
+        assert_eq!(topology.neighbour((-2, 0), (-1, 0)),
+                   Some(((5, 0), (1, 0))));
+    }
+
+    #[test]
+    fn twisted() {
+        let topology = Topology::KleinBottle(area());
+        // Top and bottom are twisted
+        assert_eq!(topology.neighbour((4, 2), (1, 1)),
+                   Some(((-2, -5), (1, -1))));
+        // Left and right are not
+        assert_eq!(topology.neighbour((5, 0), (1, 1)),
+                   Some(((-2, 1), (-1, -1))));
+
+        let topology = Topology::CrossSurface(area());
+        assert_eq!(topology.neighbour((4, 2), (1, 1)),
+                   Some(((-2, -5), (1, -1))));
+        assert_eq!(topology.neighbour((5, 0), (1, 1)),
+                   Some(((-2, -4), (-1, 1))));
+    }
+
+    #[test]
+    fn shapes() {
+        let shapes = [Shape::Moore, Shape::VonNeumann, Shape::Hexagonal,
+                      Shape::Triangular];
+        for topology in &[Topology::Unbounded, Topology::Plane(area()),
+                          Topology::Torus(area())] {
+            assert!(shapes.iter().all(|&shape| topology.supports(shape)));
+        }
+        for topology in &[Topology::KleinBottle(area()),
+                          Topology::CrossSurface(area())] {
+            assert!(topology.supports(Shape::Moore));
+            assert!(topology.supports(Shape::VonNeumann));
+            assert!(!topology.supports(Shape::Hexagonal));
+            assert!(!topology.supports(Shape::Triangular));
+        }
+
+        // Across a twisted edge, a hexagonal neighbour would see the cell at
+        // an offset that isn't part of the hexagonal neighbourhood
+        let topology = Topology::KleinBottle(area());
+        assert_eq!(topology.neighbour((4, 2), (-1, 1)),
+                   Some(((0, -5), (-1, -1))));
+    }
+
+    #[test]
+    fn symmetric() {
+        // If b is a's neighbour, then a must be b's neighbour
+        let topologies = [
+            Topology::Plane(area()),
+            Topology::Torus(area()),
+            Topology::KleinBottle(area()),
+            Topology::CrossSurface(area()),
+        ];
+        for topology in &topologies {
+            for x in -2..6 {
+                for y in -5..3 {
+                    for &offset in OFFSETS.iter() {
+                        let (neighbour, back) =
+                            match topology.neighbour((x, y), offset) {
+                                Some(n) => n,
+                                None => continue,
+                            };
+                        assert!(topology.contains(neighbour));
+                        assert_eq!(topology.neighbour(neighbour, back),
+                                   Some(((x, y), offset)),
+                                   "{:?}: ({}, {}) + {:?}",
+                                   topology, x, y, offset);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,35 +1,1415 @@
-use std::sync::Arc;
-use std::sync::mpsc::Sender;
+//! The updater computes new generations in the background.
 
-use backend::data::QTree;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
 
+use backend::{Control, Coord, Point};
+use backend::automaton::{Automaton, NEIGHBOURS};
+use backend::data::{AABB, ALIVE, CellMap, QTree, State};
+use backend::hashlife::{HashLife, HashLifeError, MAX_STEP_LOG2};
+use backend::neighbours::Shape;
+use backend::rule::{CENTRE, Neighbourhood, Rule};
+use backend::topology::Topology;
+
+/// The default number of threads used by the naive engine.
+pub const DEFAULT_THREADS: usize = 4;
+
+/// The algorithm used to compute new generations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Compute one generation at a time from the live cells of a `QTree`.
+    Naive,
+    /// Compute `2^step_log2` generations at a time with Hashlife, using at
+    /// most about `memory_limit` bytes.
+    HashLife {
+        /// Logarithm of the number of generations per step
+        step_log2: u8,
+        /// Memory limit in bytes
+        memory_limit: usize,
+    },
+}
+
+/// Errors that can occur when changing the settings of an `Updater`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdaterError {
+    /// The current engine doesn't support the new settings.
+    HashLife(HashLifeError),
+    /// The topology has twisted edges, which the rule's neighbourhood of the
+    /// given shape can't cross.
+    Twisted(Shape),
+}
+
+impl fmt::Display for UpdaterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UpdaterError::HashLife(ref error) => write!(f, "{}", error),
+            UpdaterError::Twisted(shape) => {
+                write!(f, "{:?} neighbourhoods can't cross twisted edges",
+                       shape)
+            }
+        }
+    }
+}
+
+impl Error for UpdaterError {}
+
+/// Computes new generations and sends them to the GUI.
 pub struct Updater {
-    current: Arc<QTree>,
-    data_send: Sender<Arc<QTree>>,
+    current: Arc<QTree<State>>,
+    rule: Rule,
+    automaton: Option<Box<dyn Automaton>>,
+    engine: Engine,
+    hashlife: Option<HashLife>,
+    topology: Topology,
+    pool: Pool,
+    data_send: Sender<Arc<QTree<State>>>,
 }
 
 impl Updater {
-    pub fn new(data: Arc<QTree>, data_send: Sender<Arc<QTree>>) -> Updater {
+    /// Create a new updater for the pattern `data`, using the naive engine.
+    pub fn new(data: Arc<QTree<State>>, rule: Rule,
+               data_send: Sender<Arc<QTree<State>>>) -> Updater {
         Updater {
             current: data,
+            rule,
+            automaton: None,
+            engine: Engine::Naive,
+            hashlife: None,
+            topology: Topology::Unbounded,
+            pool: Pool::new(DEFAULT_THREADS),
             data_send,
         }
     }
 
-    pub fn run(mut self) {
+    /// Get the engine that is currently used.
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Switch to another engine, keeping the current pattern.
+    ///
+    /// # Errors
+    /// Fails (and keeps the old engine) if the new engine doesn't support
+    /// the rule, the automaton or the topology, or if Hashlife should
+    /// advance by more than `2^MAX_STEP_LOG2` generations per step.
+    pub fn set_engine(&mut self, engine: Engine) -> Result<(), HashLifeError> {
+        self.hashlife = match engine {
+            Engine::Naive => None,
+            Engine::HashLife { step_log2, .. } if step_log2 > MAX_STEP_LOG2 => {
+                return Err(HashLifeError::Step(step_log2))
+            }
+            Engine::HashLife { .. } if self.automaton.is_some() => {
+                let states = self.automaton.as_ref().unwrap().states();
+                return Err(HashLifeError::MultiState(states))
+            }
+            Engine::HashLife { .. } if self.topology.area().is_some() => {
+                return Err(HashLifeError::Bounded)
+            }
+            Engine::HashLife { memory_limit, .. } => {
+                Some(HashLife::from_qtree(self.rule.clone(), memory_limit,
+                                          &self.current)?)
+            }
+        };
+        self.engine = engine;
+        Ok(())
+    }
+
+    /// Get the automaton that is simulated instead of the rule, if any.
+    pub fn automaton(&self) -> Option<&dyn Automaton> {
+        self.automaton.as_deref()
+    }
+
+    /// Simulate `automaton` instead of the rule (or the rule again if it is
+    /// `None`), keeping the current pattern.
+    ///
+    /// Since Hashlife only supports Life-like rules, this switches back to
+    /// the naive engine.
+    pub fn set_automaton(&mut self, automaton: Option<Box<dyn Automaton>>) {
+        if automaton.is_some() {
+            self.engine = Engine::Naive;
+            self.hashlife = None;
+        }
+        self.automaton = automaton;
+    }
+
+    /// Get the topology of the universe.
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Change the topology of the universe.
+    ///
+    /// All cells outside of a bounded topology are removed.
+    ///
+    /// # Errors
+    /// Fails (and keeps the old topology) if the topology is bounded, but
+    /// the current engine only supports unbounded ones, or if its edges are
+    /// twisted, but the neighbourhood of the rule isn't square (see
+    /// `Topology::supports`).
+    pub fn set_topology(&mut self, topology: Topology)
+                        -> Result<(), UpdaterError> {
+        let shape = self.rule.neighbours().shape();
+        if !topology.supports(shape) {
+            return Err(UpdaterError::Twisted(shape))
+        }
+        if let Some(area) = topology.area() {
+            if self.hashlife.is_some() {
+                return Err(UpdaterError::HashLife(HashLifeError::Bounded))
+            }
+            let mut current = QTree::<State>::empty(area.clone());
+            for (point, &state) in self.current.iter() {
+                if area.contains(point) {
+                    current.set(point, state);
+                }
+            }
+            self.current = Arc::new(current);
+        }
+        self.topology = topology;
+        Ok(())
+    }
+
+    /// Get the number of threads the naive engine computes a generation
+    /// with.
+    pub fn threads(&self) -> usize {
+        self.pool.size()
+    }
+
+    /// Set the number of threads the naive engine computes a generation
+    /// with.
+    ///
+    /// The threads are started right away and kept until the updater is
+    /// dropped (or the number changes again).
+    ///
+    /// # Panics
+    /// Panics if `threads` is 0.
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads > 0, "Need at least one thread");
+        if threads != self.pool.size() {
+            self.pool = Pool::new(threads);
+        }
+    }
+
+    /// Get the current pattern.
+    pub fn current(&self) -> Arc<QTree<State>> {
+        Arc::clone(&self.current)
+    }
+
+    /// Compute the next step with the current engine.
+    ///
+    /// # Errors
+    /// Fails (and keeps the current pattern) if Hashlife can't advance the
+    /// pattern any further (see `HashLife::step`).
+    pub fn step(&mut self) -> Result<(), HashLifeError> {
+        self.current = if let Some(ref mut automaton) = self.automaton {
+            Arc::new(automaton.next(&self.topology, &self.current))
+        } else {
+            match (self.engine, self.hashlife.as_mut()) {
+                (Engine::HashLife { step_log2, .. }, Some(hashlife)) => {
+                    hashlife.step(step_log2)?;
+                    Arc::new(hashlife.to_qtree().map(|_, _| ALIVE))
+                }
+                _ if Self::summed(&self.rule, &self.topology) => {
+                    Self::build_next_summed(&self.rule, &self.current,
+                                            &self.pool)
+                }
+                _ if self.pool.size() > 1 => {
+                    Self::build_next_parallel(&self.rule, &self.topology,
+                                              &self.current, &self.pool)
+                }
+                _ => Self::build_next(&self.rule, &self.topology,
+                                      &self.current),
+            }
+        };
+        // Nobody else has the new generation yet, so it can be compacted to
+        // keep the boundary from growing as the pattern moves
+        if let Some(current) = Arc::get_mut(&mut self.current) {
+            current.compact();
+        }
+        Ok(())
+    }
+
+    /// Compute the previous step, if the automaton is reversible.
+    ///
+    /// Returns whether the pattern was changed.
+    pub fn step_back(&mut self) -> bool {
+        let previous = match self.automaton {
+            Some(ref mut automaton) => {
+                automaton.previous(&self.topology, &self.current)
+            }
+            None => None,
+        };
+        match previous {
+            Some(previous) => {
+                self.current = Arc::new(previous);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply a `control` message.
+    ///
+    /// Controls that only change the view (i.e. `Control::Origin`) are
+    /// ignored.
+    ///
+    /// # Errors
+    /// Fails if switching the engine or the topology fails.
+    pub fn control(&mut self, control: Control) -> Result<(), UpdaterError> {
+        match control {
+            Control::Engine(engine) => {
+                self.set_engine(engine).map_err(UpdaterError::HashLife)
+            }
+            Control::Topology(topology) => self.set_topology(topology),
+            Control::Threads(threads) => {
+                self.set_threads(threads.max(1));
+                Ok(())
+            }
+            Control::Automaton(automaton) => {
+                self.set_automaton(automaton);
+                Ok(())
+            }
+            Control::Origin(_) => Ok(()),
+        }
+    }
+
+    /// Send the current pattern and compute the next step until the
+    /// receiving end hangs up.
+    ///
+    /// The messages received through `controls` are applied before each
+    /// step (see `control`); the ones that fail are reported on stderr. If
+    /// Hashlife can't advance the pattern any further, the error is reported
+    /// the same way and the naive engine takes over. Whenever the engine
+    /// changes, the new one is sent through `engines`.
+    pub fn run(mut self, controls: Receiver<Control>,
+               engines: Sender<Engine>) {
+        let mut engine = self.engine;
         while self.data_send.send(Arc::clone(&self.current)).is_ok() {
-            self.current = Self::build_next(&*self.current);
+            for control in controls.try_iter() {
+                if let Err(error) = self.control(control) {
+                    eprintln!("{}", error);
+                }
+            }
+            if let Err(error) = self.step() {
+                eprintln!("{}", error);
+                self.engine = Engine::Naive;
+                self.hashlife = None;
+            }
+            if self.engine != engine {
+                engine = self.engine;
+                let _ = engines.send(engine);
+            }
+        }
+    }
+
+    /// Compute the next generation according to `rule`.
+    fn build_next(rule: &Rule, topology: &Topology, current: &QTree<State>)
+                  -> Arc<QTree<State>> {
+        let cells = current.iter().map(|(point, &state)| (point, state));
+        let mut next = QTree::<State>::empty(current.boundary());
+        let cells = Self::next_cells(rule, topology, current, cells, None);
+        for (point, state) in cells {
+            next.set(point, state);
+        }
+        Arc::new(next)
+    }
+
+    /// Compute the next generation according to `rule` on the threads of
+    /// `pool`.
+    ///
+    /// The area around the quadtree is split into regions, which are
+    /// distributed among the threads. Each thread looks up the cells in (and
+    /// right around) a region and computes their next states, which are then
+    /// merged into the next quadtree.
+    ///
+    /// If the edges of the topology are joined, the cells along the edges
+    /// are passed to every thread as well, since they might be neighbours of
+    /// cells on the other side.
+    fn build_next_parallel(rule: &Rule, topology: &Topology,
+                           current: &Arc<QTree<State>>, pool: &Pool)
+                           -> Arc<QTree<State>> {
+        let reach = rule.neighbours().reach();
+        let boundary = current.boundary();
+        let area = match topology.area() {
+            Some(area) => area.clone(),
+            None => {
+                let mut by = boundary.half_dim();
+                while by < reach {
+                    by *= 2;
+                }
+                boundary.grow(by)
+            }
+        };
+        let regions = Self::regions(area.clone(), pool.size());
+        let edges: Vec<(Point, State)> = if topology.wraps() {
+            let inner = area.grow(-reach);
+            current.iter()
+                .filter(|&(point, _)| !inner.contains(point))
+                .map(|(point, &state)| (point, state))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let (rule, topology) = (rule.clone(), topology.clone());
+        let current = Arc::clone(current);
+        let count = regions.len();
+        let results = pool.map(count, move |i| {
+            let region = &regions[i];
+            let around = region.grow(reach);
+            let cells = current.query(&around).into_iter()
+                .map(|point| (point, *current.get(point).unwrap()))
+                .chain(edges.iter().cloned()
+                       .filter(|&(point, _)| !around.contains(point)));
+            Self::next_cells(&rule, &topology, &current, cells, Some(region))
+        });
+
+        let mut next = QTree::<State>::empty(boundary);
+        for (point, state) in results.into_iter().flatten() {
+            next.set(point, state);
+        }
+        Arc::new(next)
+    }
+
+    /// Check if the next generation should be computed with
+    /// `build_next_summed`.
+    fn summed(rule: &Rule, topology: &Topology) -> bool {
+        let neighbours = rule.neighbours();
+        topology.area().is_none() && neighbours.range() > 1 &&
+            matches!(neighbours.shape(), Shape::Moore | Shape::VonNeumann)
+    }
+
+    /// Compute the next generation of a rule with a large Moore or von
+    /// Neumann neighbourhood on the threads of `pool`.
+    ///
+    /// Pushing every live cell to each of its neighbours would be slow for
+    /// large ranges, so we build summed-area tables around the clusters of
+    /// the pattern instead (see `grids`), which count the live cells in any
+    /// rectangle in constant time. Von Neumann neighbourhoods are summed up
+    /// row by row. The rows of the tables are distributed among the threads.
+    ///
+    /// If the clusters are too sparse for that to pay off, the cells are
+    /// pushed to their neighbours after all.
+    fn build_next_summed(rule: &Rule, current: &Arc<QTree<State>>,
+                         pool: &Pool) -> Arc<QTree<State>> {
+        let range = rule.neighbours().range() as Coord;
+        let grids = match Self::grids(current, range) {
+            Some(grids) => grids,
+            None => {
+                return Self::build_next_parallel(rule, &Topology::Unbounded,
+                                                 current, pool)
+            }
+        };
+
+        let rule = rule.clone();
+        let threads = pool.size();
+        let results = pool.map(threads, move |i| {
+            let von_neumann = rule.neighbours().shape() == Shape::VonNeumann;
+            let mut next = vec![];
+            let rows = grids.iter()
+                .flat_map(|grid| (0..grid.height).map(move |y| (grid, y)));
+            for (grid, y) in rows.skip(i).step_by(threads) {
+                for x in 0..grid.width {
+                    let state = grid.state(x, y);
+                    let mut count = if von_neumann {
+                        (-range..range + 1).map(|dy| {
+                            let r = range - dy.abs();
+                            grid.sum(x - r, y + dy, x + r + 1, y + dy + 1)
+                        }).sum()
+                    } else {
+                        grid.sum(x - range, y - range,
+                                 x + range + 1, y + range + 1)
+                    };
+                    if state == ALIVE {
+                        count -= 1;
+                    }
+                    match rule.next_state_count(state, count) {
+                        0 => {}
+                        state => next.push(((grid.origin.0 + x,
+                                             grid.origin.1 + y), state)),
+                    }
+                }
+            }
+            next
+        });
+
+        let mut next = QTree::<State>::empty(current.boundary());
+        for (point, state) in results.into_iter().flatten() {
+            next.set(point, state);
+        }
+        Arc::new(next)
+    }
+
+    /// Create a grid for each cluster of cells that aren't dead, extended by
+    /// `range` in every direction.
+    ///
+    /// The cells are sorted into squares that are `2 * range + 1` cells
+    /// wide, and neighbouring squares form a cluster. Cells in different
+    /// clusters are too far apart to both be in the neighbourhood of a cell,
+    /// so each cluster can be computed on its own. Where grids overlap, the
+    /// cells of other clusters are dead.
+    ///
+    /// Returns `None` if the grids would cover more cells than there are in
+    /// the neighbourhoods of all cells together.
+    fn grids(current: &QTree<State>, range: Coord) -> Option<Vec<Grid>> {
+        let size = 2 * range + 1;
+        let mut squares = HashMap::<Point, Vec<(Point, State)>>::new();
+        let mut population = 0u64;
+        for (point, &state) in current.iter() {
+            let square = (point.0.div_euclid(size), point.1.div_euclid(size));
+            squares.entry(square).or_default().push((point, state));
+            population += 1;
+        }
+
+        let budget = population * (size as u64).pow(2);
+        let mut area = 0u64;
+        let mut grids = vec![];
+        let keys: Vec<Point> = squares.keys().cloned().collect();
+        for key in keys {
+            let mut cells = vec![];
+            let mut stack = vec![key];
+            while let Some(square) = stack.pop() {
+                if let Some(square_cells) = squares.remove(&square) {
+                    cells.extend(square_cells);
+                    for dx in -1..2 {
+                        for dy in -1..2 {
+                            stack.push((square.0 + dx, square.1 + dy));
+                        }
+                    }
+                }
+            }
+            if cells.is_empty() {
+                continue
+            }
+            let min = cells.iter().fold(cells[0].0, |min, &(p, _)| {
+                (min.0.min(p.0), min.1.min(p.1))
+            });
+            let max = cells.iter().fold(cells[0].0, |max, &(p, _)| {
+                (max.0.max(p.0), max.1.max(p.1))
+            });
+            let side = |min: Coord, max: Coord| {
+                (i64::from(max) - i64::from(min)) as u64 + 1 + 2 * range as u64
+            };
+            area = area.saturating_add(
+                side(min.0, max.0).saturating_mul(side(min.1, max.1))
+            );
+            if area > budget {
+                return None
+            }
+            grids.push(Grid::new(&cells, (min, max), range));
+        }
+        Some(grids)
+    }
+
+    /// Split the area in which cells can be alive in the next generation
+    /// into (at least) `count` regions, if possible.
+    ///
+    /// In an unbounded universe, new cells can only be born near existing
+    /// ones, so doubling the boundary (or more for large neighbourhoods) is
+    /// enough for `area`.
+    fn regions(area: AABB, count: usize) -> Vec<AABB> {
+        let mut regions = vec![area];
+        while regions.len() < count &&
+            regions.iter().all(|r| r.half_dim() % 2 == 0) {
+            regions = regions.iter()
+                .flat_map(|r| r.quadrants().to_vec())
+                .collect();
+        }
+        regions
+    }
+
+    /// Compute the next states of the cells in `area` (or everywhere if it is
+    /// `None`) that won't be dead.
+    ///
+    /// `cells` must contain all cells in and around `area` (within the reach
+    /// of the neighbourhood). Only cells that are not dead and the neighbours
+    /// of live cells can be alive in the next generation, so we collect the
+    /// neighbourhood of each of those cells and then let the rule decide on
+    /// their next state.
+    ///
+    /// If the neighbours of the rule fit into a `Neighbourhood`, we collect
+    /// that, otherwise only the number of live neighbours.
+    fn next_cells<I>(rule: &Rule, topology: &Topology, current: &QTree<State>,
+                     cells: I, area: Option<&AABB>) -> Vec<(Point, State)>
+        where I: Iterator<Item = (Point, State)> {
+        let in_area = |point| match area {
+            Some(area) => area.contains(point),
+            None => true,
+        };
+        let neighbours = rule.neighbours();
+        let masked = neighbours.mask().is_some();
+        // Triangles pointing up and down have different neighbours
+        let offsets = if masked {
+            [NEIGHBOURS.to_vec(), vec![]]
+        } else {
+            [neighbours.offsets((0, 0)), neighbours.offsets((1, 0))]
+        };
+
+        let mut neighbourhoods = HashMap::<Point, (Neighbourhood, u32)>::new();
+        for (point, state) in cells {
+            if in_area(point) {
+                let neighbourhood = neighbourhoods.entry(point)
+                    .or_insert((0, 0));
+                if state == ALIVE {
+                    neighbourhood.0 |= CENTRE;
+                }
+            }
+            if state != ALIVE {
+                // Dying cells don't influence their neighbours
+                continue
+            }
+            let parity = if masked { 0 } else { (point.0 + point.1) & 1 };
+            for &offset in offsets[parity as usize].iter() {
+                // From the neighbour's point of view, we're at `back`
+                let neighbour = topology.neighbour(point, offset);
+                let (neighbour, back) = match neighbour {
+                    Some(neighbour) => neighbour,
+                    None => continue,
+                };
+                if !in_area(neighbour) {
+                    continue
+                }
+                let neighbourhood = neighbourhoods.entry(neighbour)
+                    .or_insert((0, 0));
+                if masked {
+                    neighbourhood.0 |= Rule::neighbour_bit(back);
+                } else {
+                    neighbourhood.1 += 1;
+                }
+            }
+        }
+
+        neighbourhoods.into_iter().filter_map(|(point, neighbourhood)| {
+            let (neighbourhood, count) = neighbourhood;
+            let state = current.get(point).cloned().unwrap_or(0);
+            let next_state = if masked {
+                rule.next_state(state, neighbourhood)
+            } else {
+                rule.next_state_count(state, count)
+            };
+            match next_state {
+                0 => None,
+                next_state => Some((point, next_state)),
+            }
+        }).collect()
+    }
+}
+
+/// A job for the threads of a `Pool`.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads that the naive engine distributes its work among.
+///
+/// The threads are kept around between generations and receive their jobs
+/// through a channel, so that a generation only costs a message per job
+/// instead of starting new threads. They are stopped and joined when the
+/// pool is dropped.
+struct Pool {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    /// Start `threads` threads that wait for jobs.
+    fn new(threads: usize) -> Pool {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads).map(|_| {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                // The lock has to be released before running the job, so
+                // that the other threads can take the next ones
+                let job = receiver.lock().expect("A worker panicked").recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            })
+        }).collect();
+        Pool {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Get the number of threads.
+    fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Compute `job(i)` for every `i` in `0..count` on the threads and
+    /// collect the results (in no particular order).
+    ///
+    /// # Panics
+    /// Panics if one of the jobs panicked.
+    fn map<T, F>(&self, count: usize, job: F) -> Vec<T>
+        where T: Send + 'static, F: Fn(usize) -> T + Send + Sync + 'static {
+        let job = Arc::new(job);
+        let (results_send, results) = mpsc::channel();
+        for i in 0..count {
+            let job = Arc::clone(&job);
+            let results_send = results_send.clone();
+            let sent = self.jobs.as_ref().map(|jobs| {
+                jobs.send(Box::new(move || {
+                    let _ = results_send.send(job(i));
+                }))
+            });
+            assert!(matches!(sent, Some(Ok(()))), "The workers are gone");
+        }
+        drop(results_send);
+        let results: Vec<T> = results.iter().collect();
+        assert_eq!(results.len(), count, "A job panicked");
+        results
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // Without a sender, the threads stop once the remaining jobs are done
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The states of all cells in a rectangle, with a summed-area table of the
+/// live cells.
+struct Grid {
+    /// The bottom left corner
+    origin: Point,
+    width: Coord,
+    height: Coord,
+    states: Vec<State>,
+    /// The number of live cells below and to the left of each corner
+    sums: Vec<u32>,
+}
+
+impl Grid {
+    /// Create a grid for the `cells`, whose bounding box goes from `min` to
+    /// `max`, extended by `margin` in every direction.
+    fn new(cells: &[(Point, State)], (min, max): (Point, Point),
+           margin: Coord) -> Grid {
+        let origin = (min.0 - margin, min.1 - margin);
+        let width = max.0 - min.0 + 1 + 2 * margin;
+        let height = max.1 - min.1 + 1 + 2 * margin;
+
+        let mut states = vec![0; width as usize * height as usize];
+        for &(point, state) in cells {
+            let (x, y) = (point.0 - origin.0, point.1 - origin.1);
+            states[y as usize * width as usize + x as usize] = state;
+        }
+        let stride = width as usize + 1;
+        let mut sums = vec![0; stride * (height as usize + 1)];
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let alive = states[y * width as usize + x] == ALIVE;
+                sums[(y + 1) * stride + x + 1] = alive as u32 +
+                    sums[y * stride + x + 1] + sums[(y + 1) * stride + x] -
+                    sums[y * stride + x];
+            }
+        }
+
+        Grid {
+            origin,
+            width,
+            height,
+            states,
+            sums,
+        }
+    }
+
+    fn state(&self, x: Coord, y: Coord) -> State {
+        self.states[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Count the live cells in `[x0, x1) x [y0, y1)`.
+    fn sum(&self, x0: Coord, y0: Coord, x1: Coord, y1: Coord) -> u32 {
+        let clamp = |v: Coord, max: Coord| v.max(0).min(max) as usize;
+        let (x0, x1) = (clamp(x0, self.width), clamp(x1, self.width));
+        let (y0, y1) = (clamp(y0, self.height), clamp(y1, self.height));
+        if x0 >= x1 || y0 >= y1 {
+            return 0
+        }
+        let stride = self.width as usize + 1;
+        self.sums[y1 * stride + x1] + self.sums[y0 * stride + x0] -
+            self.sums[y0 * stride + x1] - self.sums[y1 * stride + x0]
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashSet};
+    use backend::BigInt;
+    use backend::data::AABB;
+    use backend::automaton::Wireworld;
+    use backend::hashlife::DEFAULT_MEMORY_LIMIT;
+    use backend::margolus::Margolus;
+
+    fn tree(points: &[Point]) -> QTree<State> {
+        QTree::new(AABB::new((0, 0), 512), &points.to_vec())
+            .map(|_, _| ALIVE)
+    }
+
+    fn cells(tree: &QTree<State>) -> BTreeSet<Point> {
+        tree.points().collect()
+    }
+
+    fn set(points: &[Point]) -> BTreeSet<Point> {
+        points.iter().cloned().collect()
+    }
+
+    fn translated(points: &[Point], by: Point) -> BTreeSet<Point> {
+        points.iter().map(|p| (p.0 + by.0, p.1 + by.1)).collect()
+    }
+
+    fn run(tree: QTree<State>, generations: usize) -> Arc<QTree<State>> {
+        run_rule(&Rule::conway(), tree, generations)
+    }
+
+    fn run_rule(rule: &Rule, tree: QTree<State>, generations: usize)
+                -> Arc<QTree<State>> {
+        run_topology(rule, &Topology::Unbounded, tree, generations)
+    }
+
+    fn run_topology(rule: &Rule, topology: &Topology, tree: QTree<State>,
+                    generations: usize) -> Arc<QTree<State>> {
+        let mut current = Arc::new(tree);
+        for _ in 0..generations {
+            current = Updater::build_next(rule, topology, &current);
+        }
+        current
+    }
+
+    #[test]
+    fn empty() {
+        let next = run(tree(&[]), 1);
+        assert!(cells(&next).is_empty());
+    }
+
+    #[test]
+    fn block() {
+        let block = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        let next = run(tree(&block), 1);
+        assert_eq!(cells(&next), set(&block));
+        let next = run(tree(&block), 10);
+        assert_eq!(cells(&next), set(&block));
+    }
+
+    #[test]
+    fn blinker() {
+        let horizontal = [(-1, 0), (0, 0), (1, 0)];
+        let vertical = [(0, -1), (0, 0), (0, 1)];
+        let next = run(tree(&horizontal), 1);
+        assert_eq!(cells(&next), set(&vertical));
+        let next = run(tree(&horizontal), 2);
+        assert_eq!(cells(&next), set(&horizontal));
+    }
+
+    #[test]
+    fn glider() {
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let next = run(tree(&glider), 4);
+        assert_eq!(cells(&next), translated(&glider, (-1, -1)));
+        let next = run(tree(&glider), 40);
+        assert_eq!(cells(&next), translated(&glider, (-10, -10)));
+    }
+
+    #[test]
+    fn r_pentomino() {
+        let r_pentomino = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+        let population = |generations| {
+            run(tree(&r_pentomino), generations).points().count()
+        };
+        assert_eq!(population(0), 5);
+        assert_eq!(population(1), 6);
+        assert_eq!(population(2), 7);
+        assert_eq!(population(3), 9);
+        // The R-pentomino stabilizes after 1103 generations with 116 cells
+        // (including six escaped gliders).
+        assert_eq!(population(1103), 116);
+    }
+
+    #[test]
+    fn highlife_replicator() {
+        let rule = "B36/S23".parse().unwrap();
+        let replicator = [
+            (0, 2), (1, 2), (2, 2),
+            (-1, 1), (2, 1),
+            (-2, 0), (2, 0),
+            (-2, -1), (1, -1),
+            (-2, -2), (-1, -2), (0, -2),
+        ];
+        let next = run_rule(&rule, tree(&replicator), 12);
+        let expected: BTreeSet<Point> = translated(&replicator, (-2, 2))
+            .union(&translated(&replicator, (2, -2)))
+            .cloned().collect();
+        assert_eq!(cells(&next), expected);
+    }
+
+    #[test]
+    fn seeds() {
+        let rule = "B2/S".parse().unwrap();
+        let domino = [(0, 0), (1, 0)];
+        let next = run_rule(&rule, tree(&domino), 1);
+        assert_eq!(cells(&next), set(&[(0, 1), (1, 1), (0, -1), (1, -1)]));
+    }
+
+    #[test]
+    fn survival_without_neighbours() {
+        // Life without Death, in which isolated cells survive as well
+        let rule = "B3/S012345678".parse().unwrap();
+        let isolated = [(0, 0), (10, 10), (-20, 5)];
+        let next = run_rule(&rule, tree(&isolated), 5);
+        assert_eq!(cells(&next), set(&isolated));
+        let next = Updater::build_next_parallel(
+            &rule, &Topology::Unbounded, &Arc::new(tree(&isolated)),
+            &Pool::new(4)
+        );
+        assert_eq!(cells(&next), set(&isolated));
+    }
+
+    #[test]
+    fn isotropic_spaceships() {
+        // The glider still works in B2n3/S23-q
+        let rule = "B2n3/S23-q".parse().unwrap();
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let next = run_rule(&rule, tree(&glider), 4);
+        assert_eq!(cells(&next), translated(&glider, (-1, -1)));
+
+        // The c/5 orthogonal T-tetromino spaceship of tlife
+        let rule = "B3/S2-i34q".parse().unwrap();
+        let ship = [(0, 1), (1, 0), (1, 1), (1, 2)];
+        let next = run_rule(&rule, tree(&ship), 5);
+        assert_eq!(cells(&next), translated(&ship, (-1, 0)));
+        let next = run_rule(&rule, tree(&ship), 50);
+        assert_eq!(cells(&next), translated(&ship, (-10, 0)));
+
+        // A c/2 orthogonal spaceship in B2e3/S23
+        let rule = "B2e3/S23".parse().unwrap();
+        let ship = [(0, 0), (0, 1), (1, 0), (2, 0), (2, 1)];
+        let next = run_rule(&rule, tree(&ship), 2);
+        assert_eq!(cells(&next), translated(&ship, (0, -1)));
+
+        // ... which relies on the 2e births
+        let rule = "B2-e3/S23".parse().unwrap();
+        let next = run_rule(&rule, tree(&ship), 2);
+        assert_ne!(cells(&next), translated(&ship, (0, -1)));
+    }
+
+    #[test]
+    fn generations() {
+        fn states(tree: &QTree<State>) -> BTreeSet<(Point, State)> {
+            tree.iter().map(|(p, &state)| (p, state)).collect()
+        }
+
+        // A c/1 spaceship in Brian's Brain
+        let rule = "/2/3".parse().unwrap();
+        let mut ship = tree(&[(0, 0), (0, 1)]);
+        ship.set((-1, 0), 2);
+        ship.set((-1, 1), 2);
+        let next = run_rule(&rule, ship, 1);
+        let expected: BTreeSet<(Point, State)> = [
+            ((1, 0), ALIVE), ((1, 1), ALIVE), ((0, 0), 2), ((0, 1), 2),
+        ].iter().cloned().collect();
+        assert_eq!(states(&next), expected);
+
+        // A dying cell decays even without live neighbours
+        let rule = "345/2/4".parse().unwrap();
+        let mut cell = tree(&[]);
+        cell.set((0, 0), 2);
+        let next = run_rule(&rule, cell, 1);
+        assert_eq!(states(&next), [((0, 0), 3)].iter().cloned().collect());
+        let next = run_rule(&rule, Arc::try_unwrap(next).unwrap(), 1);
+        assert!(states(&next).is_empty());
+    }
+
+    #[test]
+    fn parallel() {
+        fn states(tree: &QTree<State>) -> Vec<(Point, State)> {
+            let mut states: Vec<_> = tree.iter()
+                .map(|(p, &state)| (p, state))
+                .collect();
+            states.sort();
+            states
+        }
+
+        let r_pentomino = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+        // Brian's Brain, so that there are dying cells as well
+        for rule in &[Rule::conway(), "/2/3".parse().unwrap()] {
+            for &threads in &[2, 3, 4, 16] {
+                let pool = Pool::new(threads);
+                let mut single = Arc::new(tree(&r_pentomino));
+                let mut parallel = Arc::clone(&single);
+                for _ in 0..100 {
+                    single = Updater::build_next(rule, &Topology::Unbounded,
+                                                 &single);
+                    parallel = Updater::build_next_parallel(
+                        rule, &Topology::Unbounded, &parallel, &pool
+                    );
+                    assert_eq!(states(&parallel), states(&single));
+                }
+            }
+        }
+
+        // Births right outside of the boundary
+        let blinker = QTree::new(AABB::new((0, 0), 2),
+                                 &vec![(-2, 1), (-1, 1), (0, 1)])
+            .map(|_, _| ALIVE);
+        let next = Updater::build_next_parallel(
+            &Rule::conway(), &Topology::Unbounded, &Arc::new(blinker),
+            &Pool::new(4)
+        );
+        assert_eq!(cells(&next), set(&[(-1, 0), (-1, 1), (-1, 2)]));
+    }
+
+    #[test]
+    fn pool() {
+        let pool = Pool::new(3);
+        let mut squares = pool.map(10, |i| i * i);
+        squares.sort();
+        assert_eq!(squares, (0..10).map(|i| i * i).collect::<Vec<_>>());
+
+        // The same threads do the work every time
+        let mut ids = HashSet::new();
+        for _ in 0..20 {
+            ids.extend(pool.map(6, |_| thread::current().id()));
+        }
+        assert!(ids.len() <= 3);
+        assert!(!ids.contains(&thread::current().id()));
+
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&[])), Rule::conway(),
+                                       data_send);
+        assert_eq!(updater.threads(), DEFAULT_THREADS);
+        updater.set_threads(2);
+        assert_eq!(updater.threads(), 2);
+    }
+
+    #[test]
+    fn switch_engine() {
+        let (data_send, _data_recv) = mpsc::channel();
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let mut updater = Updater::new(Arc::new(tree(&glider)),
+                                       Rule::conway(), data_send);
+        for _ in 0..4 {
+            updater.step().unwrap();
         }
+        let hashlife = Engine::HashLife {
+            step_log2: 3,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        };
+        updater.set_engine(hashlife).unwrap();
+        assert_eq!(updater.engine(), hashlife);
+        updater.step().unwrap();
+        assert_eq!(cells(&updater.current()), translated(&glider, (-3, -3)));
+        let expected = Updater::build_next(&Rule::conway(),
+                                           &Topology::Unbounded,
+                                           &updater.current());
+        updater.set_engine(Engine::Naive).unwrap();
+        updater.step().unwrap();
+        assert_eq!(cells(&updater.current()), cells(&expected));
+
+        // Hashlife only supports two states
+        let rule = "/2/3".parse().unwrap();
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&glider)), rule,
+                                       data_send);
+        assert!(updater.set_engine(hashlife).is_err());
+        assert_eq!(updater.engine(), Engine::Naive);
+
+        // The generation counter only has 64 bits
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&glider)),
+                                       Rule::conway(), data_send);
+        assert_eq!(updater.set_engine(Engine::HashLife {
+            step_log2: MAX_STEP_LOG2 + 1,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }), Err(HashLifeError::Step(MAX_STEP_LOG2 + 1)));
+        assert_eq!(updater.engine(), Engine::Naive);
+    }
+
+    #[test]
+    fn controls() {
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let hashlife = Engine::HashLife {
+            step_log2: 2,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        };
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&glider)),
+                                       Rule::conway(), data_send);
+        updater.control(Control::Engine(hashlife)).unwrap();
+        assert_eq!(updater.engine(), hashlife);
+        let torus = Topology::Torus(AABB::new((0, 0), 4));
+        assert!(updater.control(Control::Topology(torus)).is_err());
+        assert!(updater.topology().area().is_none());
+        updater.control(Control::Threads(0)).unwrap();
+        assert_eq!(updater.threads(), 1);
+        updater.control(Control::Automaton(Some(Box::new(Wireworld))))
+            .unwrap();
+        assert_eq!(updater.engine(), Engine::Naive);
+        let origin = (BigInt::from(3), BigInt::from(-2));
+        updater.control(Control::Origin(origin)).unwrap();
+        assert_eq!(updater.automaton().unwrap().name(), "Wireworld");
+
+        // A running updater applies the controls before the next step
+        let (data_send, data_recv) = mpsc::channel();
+        let (controls, control_recv) = mpsc::channel();
+        let (engine_send, engines) = mpsc::channel();
+        let updater = Updater::new(Arc::new(tree(&glider)), Rule::conway(),
+                                   data_send);
+        controls.send(Control::Engine(hashlife)).unwrap();
+        let runner = thread::spawn(move || {
+            updater.run(control_recv, engine_send)
+        });
+        assert_eq!(cells(&data_recv.recv().unwrap()), set(&glider));
+        let expected = run_topology(&Rule::conway(), &Topology::Unbounded,
+                                    tree(&glider), 4);
+        assert_eq!(cells(&data_recv.recv().unwrap()), cells(&expected));
+        assert_eq!(engines.recv().unwrap(), hashlife);
+
+        // ... and reports when the engine changes because of an automaton
+        controls.send(Control::Automaton(Some(Box::new(Wireworld))))
+            .unwrap();
+        assert_eq!(engines.recv().unwrap(), Engine::Naive);
+        drop(data_recv);
+        runner.join().unwrap();
+    }
+
+    #[test]
+    fn topologies() {
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let area = AABB::new((0, 0), 4);
+
+        // The glider comes back after travelling around the torus
+        let topology = Topology::Torus(area.clone());
+        let next = run_topology(&Rule::conway(), &topology, tree(&glider), 32);
+        assert_eq!(cells(&next), set(&glider));
+        let next = run_topology(&Rule::conway(), &topology, tree(&glider), 20);
+        assert_eq!(cells(&next), set(&[
+            (3, 3), (-4, 3), (-3, 3), (3, -4), (-4, -3),
+        ]));
+
+        // ... but crashes into the edge of a plane and turns into a block
+        let topology = Topology::Plane(area.clone());
+        let next = run_topology(&Rule::conway(), &topology, tree(&glider), 32);
+        let block = [(-4, -4), (-3, -4), (-4, -3), (-3, -3)];
+        assert_eq!(cells(&next), set(&block));
+
+        // After crossing the twisted edge of a Klein bottle, it is mirrored
+        let topology = Topology::KleinBottle(area.clone());
+        let next = run_topology(&Rule::conway(), &topology, tree(&glider), 32);
+        let mirrored: Vec<Point> = glider.iter()
+            .map(|&(x, y)| (-1 - x, y))
+            .collect();
+        assert_eq!(cells(&next), set(&mirrored));
     }
 
-    fn build_next(current: &QTree) -> Arc<QTree> {
-        let mut next = Arc::new(QTree::new(current.boundary(), &vec![]));
-        {
-            let data = Arc::get_mut(&mut next).unwrap();
-            for point in current {
-                data.set(point);
+    #[test]
+    fn parallel_topologies() {
+        let r_pentomino = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+        let area = AABB::new((0, 0), 8);
+        let topologies = [
+            Topology::Plane(area.clone()),
+            Topology::Torus(area.clone()),
+            Topology::KleinBottle(area.clone()),
+            Topology::CrossSurface(area.clone()),
+        ];
+        let pool = Pool::new(4);
+        for topology in &topologies {
+            let mut single = Arc::new(tree(&r_pentomino));
+            let mut parallel = Arc::clone(&single);
+            for _ in 0..100 {
+                single = Updater::build_next(&Rule::conway(), topology,
+                                             &single);
+                parallel = Updater::build_next_parallel(
+                    &Rule::conway(), topology, &parallel, &pool
+                );
+                assert_eq!(cells(&parallel), cells(&single));
+                assert!(cells(&single).iter().all(|&p| area.contains(p)));
             }
         }
-        next
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        use backend::neighbours::{Neighbours, Shape};
+
+        // A single cell gives birth to all of its neighbours
+        for &(rule, shape, range) in &[
+            ("B1/SV", Shape::VonNeumann, 1),
+            ("B1/SH", Shape::Hexagonal, 1),
+            ("B1/ST", Shape::Triangular, 1),
+            ("B1/SM2", Shape::Moore, 2),
+            ("B1/SH2", Shape::Hexagonal, 2),
+        ] {
+            let rule = rule.parse().unwrap();
+            for &cell in &[(0, 0), (3, 0)] {
+                let next = run_rule(&rule, tree(&[cell]), 1);
+                let offsets = Neighbours::new(shape, range).offsets(cell);
+                assert_eq!(cells(&next), translated(&offsets, cell));
+            }
+        }
+
+        // Hashlife gives the same results for hexagonal rules
+        let rule: Rule = "B2/S34H".parse().unwrap();
+        let r_pentomino = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+        let mut hashlife = HashLife::new(rule.clone(), DEFAULT_MEMORY_LIMIT)
+            .unwrap();
+        for &point in r_pentomino.iter() {
+            hashlife.set(point).unwrap();
+        }
+        hashlife.step(4).unwrap();
+        let next = run_rule(&rule, tree(&r_pentomino), 16);
+        assert_eq!(cells(&next), hashlife.to_qtree().into_iter().collect());
+    }
+
+    #[test]
+    fn parallel_neighbourhoods() {
+        let r_pentomino = [(1, 1), (2, 1), (0, 0), (1, 0), (1, -1)];
+        let topologies = [
+            Topology::Unbounded,
+            Topology::Torus(AABB::new((0, 0), 8)),
+        ];
+        let pool = Pool::new(4);
+        for rule in &["B2/S34H", "B4/S3456T", "B4/S3456/C4T", "B5/S45M2"] {
+            let rule: Rule = rule.parse().unwrap();
+            for topology in &topologies {
+                let mut single = Arc::new(tree(&r_pentomino));
+                let mut parallel = Arc::clone(&single);
+                for _ in 0..30 {
+                    single = Updater::build_next(&rule, topology, &single);
+                    parallel = Updater::build_next_parallel(
+                        &rule, topology, &parallel, &pool
+                    );
+                    assert_eq!(cells(&parallel), cells(&single));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn larger_than_life() {
+        let bosco: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        let square: Vec<Point> = (-5..6)
+            .flat_map(|x| (-5..6).map(move |y| (x, y)))
+            .collect();
+        let next = Updater::build_next_summed(&bosco,
+                                              &Arc::new(tree(&square)),
+                                              &Pool::new(2));
+        let next = cells(&next);
+        // Cells near the centre have too many neighbours, those in the
+        // corners survive
+        assert!(!next.contains(&(0, 0)));
+        assert!(next.contains(&(5, 5)));
+        assert!(next.contains(&(-5, -5)));
+        // Cells at a distance of 2 from the middle of a side are born with
+        // 44 - 4 * |offset| live neighbours
+        for &(x, y) in &[(7, 0), (-7, 2), (-2, 7), (1, -7)] {
+            assert!(next.contains(&(x, y)));
+        }
+        assert!(!next.contains(&(6, 0)));
+        assert!(!next.contains(&(8, 0)));
+        assert!(!next.contains(&(7, 3)));
+
+        // A single cell dies
+        let next = Updater::build_next_summed(&bosco,
+                                              &Arc::new(tree(&[(0, 0)])),
+                                              &Pool::new(1));
+        assert!(cells(&next).is_empty());
+    }
+
+    #[test]
+    fn summed() {
+        // Some pseudo-random soup
+        let mut seed = 12345u32;
+        let mut soup = vec![];
+        for x in 0..20 {
+            for y in 0..20 {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                if seed >> 16 & 1 == 1 {
+                    soup.push((x, y));
+                }
+            }
+        }
+
+        let pools = [Pool::new(1), Pool::new(2), Pool::new(3)];
+        for rule in &["R5,C0,M1,S34..58,B34..45,NM", "R3,C4,M0,S5..9,B6..8,NN",
+                      "B5/S45M2"] {
+            let rule: Rule = rule.parse().unwrap();
+            assert!(Updater::summed(&rule, &Topology::Unbounded));
+            let mut generic = Arc::new(tree(&soup));
+            let mut summed = Arc::clone(&generic);
+            for generation in 0..20 {
+                generic = Updater::build_next(&rule, &Topology::Unbounded,
+                                              &generic);
+                summed = Updater::build_next_summed(&rule, &summed,
+                                                    &pools[generation % 3]);
+                assert_eq!(summed.iter().collect::<BTreeSet<_>>(),
+                           generic.iter().collect::<BTreeSet<_>>());
+            }
+        }
+
+        let torus = Topology::Torus(AABB::new((0, 0), 16));
+        let rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        assert!(!Updater::summed(&rule, &torus));
+        assert!(!Updater::summed(&Rule::conway(), &Topology::Unbounded));
+    }
+
+    #[test]
+    fn summed_clusters() {
+        let bosco: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        let pool = Pool::new(2);
+        let square: Vec<Point> = (-5..6)
+            .flat_map(|x| (-5..6).map(move |y| (x, y)))
+            .collect();
+
+        // Two objects that are far apart only need small tables
+        let far = (100_000, -100_000);
+        let points: Vec<Point> = square.iter()
+            .chain(translated(&square, far).iter())
+            .cloned().collect();
+        let grids = Updater::grids(&tree(&points), 5).unwrap();
+        assert_eq!(grids.len(), 2);
+        assert!(grids.iter().all(|grid| grid.width * grid.height == 21 * 21));
+        let mut single = Arc::new(tree(&square));
+        let mut both = Arc::new(tree(&points));
+        for _ in 0..10 {
+            single = Updater::build_next(&bosco, &Topology::Unbounded,
+                                         &single);
+            both = Updater::build_next_summed(&bosco, &both, &pool);
+            let single: Vec<Point> = cells(&single).into_iter().collect();
+            let expected: BTreeSet<Point> = single.iter().cloned()
+                .chain(translated(&single, far)).collect();
+            assert_eq!(cells(&both), expected);
+        }
+
+        // A chain of cells whose tables would be mostly empty
+        let diagonal: Vec<Point> = (0..20).map(|i| (12 * i, 12 * i))
+            .collect();
+        assert!(Updater::grids(&tree(&diagonal), 5).is_none());
+        let points: Vec<Point> = diagonal.iter()
+            .flat_map(|&(x, y)| vec![(x, y), (x + 1, y), (x, y + 1)])
+            .collect();
+        let mut generic = Arc::new(tree(&points));
+        let mut summed = Arc::clone(&generic);
+        for _ in 0..3 {
+            generic = Updater::build_next(&bosco, &Topology::Unbounded,
+                                          &generic);
+            summed = Updater::build_next_summed(&bosco, &summed, &pool);
+            assert_eq!(cells(&summed), cells(&generic));
+        }
+    }
+
+    #[test]
+    fn automata() {
+        let (data_send, _) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&[])), Rule::conway(),
+                                       data_send);
+        updater.set_engine(Engine::HashLife {
+            step_log2: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }).unwrap();
+        updater.set_automaton(Some(Box::new(Wireworld)));
+        assert_eq!(updater.engine(), Engine::Naive);
+        assert_eq!(updater.automaton().unwrap().name(), "Wireworld");
+        assert_eq!(updater.set_engine(Engine::HashLife {
+            step_log2: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }), Err(HashLifeError::MultiState(4)));
+
+        // An electron travels around a loop on a torus
+        updater.set_topology(Topology::Torus(AABB::new((0, 0), 4))).unwrap();
+        let mut wire = QTree::<State>::empty(AABB::new((0, 0), 512));
+        for x in -4..4 {
+            wire.set((x, 0), Wireworld::CONDUCTOR);
+        }
+        wire.set((0, 0), Wireworld::HEAD);
+        wire.set((-1, 0), Wireworld::TAIL);
+        updater.current = Arc::new(wire);
+        for _ in 0..8 {
+            updater.step().unwrap();
+        }
+        assert_eq!(updater.current().get((0, 0)), Some(&Wireworld::HEAD));
+        assert_eq!(updater.current().get((-1, 0)), Some(&Wireworld::TAIL));
+        assert_eq!(updater.current().iter().count(), 8);
+
+        updater.set_automaton(None);
+        updater.step().unwrap();
+        assert!(updater.current().iter().all(|(_, &state)| state == ALIVE));
+    }
+
+    #[test]
+    fn step_back() {
+        let (data_send, _) = mpsc::channel();
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let mut updater = Updater::new(Arc::new(tree(&glider)),
+                                       Rule::conway(), data_send);
+        assert!(!updater.step_back());
+
+        updater.set_automaton(Some(Box::new(Margolus::critters())));
+        for _ in 0..7 {
+            updater.step().unwrap();
+        }
+        assert_ne!(cells(&updater.current()), set(&glider));
+        for _ in 0..7 {
+            assert!(updater.step_back());
+        }
+        assert_eq!(cells(&updater.current()), set(&glider));
+    }
+
+    #[test]
+    fn compact_spaceship() {
+        let (data_send, _data_recv) = mpsc::channel();
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let mut updater = Updater::new(Arc::new(tree(&glider)),
+                                       Rule::conway(), data_send);
+        for _ in 0..400 {
+            updater.step().unwrap();
+        }
+        let current = updater.current();
+        assert_eq!(cells(&current), translated(&glider, (-100, -100)));
+        // The boundary follows the glider instead of growing
+        let boundary = current.boundary();
+        assert!(boundary.half_dim() <= 8);
+        assert!(current.points().all(|point| boundary.contains(point)));
+    }
+
+    #[test]
+    fn set_topology() {
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&[(0, 0), (10, 0)])),
+                                       Rule::conway(), data_send);
+        let torus = Topology::Torus(AABB::new((0, 0), 4));
+        updater.set_topology(torus.clone()).unwrap();
+        assert_eq!(cells(&updater.current()), set(&[(0, 0)]));
+
+        // Hashlife only supports unbounded universes
+        let hashlife = Engine::HashLife {
+            step_log2: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        };
+        assert_eq!(updater.set_engine(hashlife).err(),
+                   Some(HashLifeError::Bounded));
+        updater.set_topology(Topology::Unbounded).unwrap();
+        updater.set_engine(hashlife).unwrap();
+        assert_eq!(updater.set_topology(torus).err(),
+                   Some(UpdaterError::HashLife(HashLifeError::Bounded)));
+
+        // Hexagonal neighbourhoods can't cross twisted edges
+        let (data_send, _data_recv) = mpsc::channel();
+        let mut updater = Updater::new(Arc::new(tree(&[(0, 0)])),
+                                       "B2/S34H".parse().unwrap(), data_send);
+        let area = AABB::new((0, 0), 4);
+        assert_eq!(updater.set_topology(Topology::KleinBottle(area.clone()))
+                   .err(), Some(UpdaterError::Twisted(Shape::Hexagonal)));
+        assert!(updater.topology().area().is_none());
+        updater.set_topology(Topology::Torus(area)).unwrap();
     }
 }
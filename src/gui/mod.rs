@@ -6,9 +6,15 @@ use self::glfw::Context;
 extern crate gl;
 
 use std::sync::Arc;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 
-use backend::data::QTree;
+use backend::{BigInt, BigPoint, Control};
+use backend::automaton::Automaton;
+use backend::data::{QTree, State};
+use backend::hashlife::DEFAULT_MEMORY_LIMIT;
+use backend::neighbours::Shape;
+use backend::rule::Rule;
+use backend::updater::Engine;
 
 #[derive(Clone, Debug)]
 /// Indicates what modifiers are held down
@@ -193,32 +199,102 @@ impl Window {
 pub struct GUI {
     window: Window,
     renderer: Renderer,
-    data_recv: Receiver<Arc<QTree>>,
+    data_recv: Receiver<Arc<QTree<State>>>,
+    rule: Rule,
+    engine: Engine,
+    controls: Receiver<Control>,
+    updater: Sender<Control>,
+    engines: Receiver<Engine>,
 }
 
 impl GUI {
     /// Constructs a new GUI.
     ///
+    /// `rule` is the rule that is simulated, which decides how the cells are
+    /// drawn. The messages received through `controls` are applied like
+    /// those from key presses (see `control`), and the ones for the updater
+    /// are passed on through the first channel of `updater`. The updater
+    /// reports the engine it actually uses through the second one (see
+    /// `Updater::run`).
+    ///
     /// **Note:** Since we initialize GLFW in here, this _must_ be called from
     /// the main thread.
-    pub fn new(data_recv: Receiver<Arc<QTree>>) -> GUI {
+    pub fn new(data_recv: Receiver<Arc<QTree<State>>>, rule: &Rule,
+               controls: Receiver<Control>,
+               (updater, engines): (Sender<Control>, Receiver<Engine>))
+               -> GUI {
         let mut window = Window::new((600, 600), "Conway's Game of Life");
         window.init_gl();
-        let renderer = Renderer::new();
+        let mut renderer = Renderer::new();
+        renderer.set_states(rule.states());
+        renderer.set_shape(rule.neighbours().shape());
         GUI {
             window,
             renderer,
             data_recv,
+            rule: rule.clone(),
+            engine: Engine::Naive,
+            controls,
+            updater,
+            engines,
+        }
+    }
+
+    /// Apply a `control` message.
+    ///
+    /// Changes to the view are made right away, everything else is passed
+    /// on to the updater (which reports failures on stderr).
+    pub fn control(&mut self, control: Control) {
+        match control {
+            Control::Origin(origin) => return self.set_origin(origin),
+            Control::Automaton(ref automaton) => {
+                let rule = self.rule.clone();
+                self.set_automaton(automaton.as_deref(), &rule);
+            }
+            _ => {}
+        }
+        // The updater only hangs up once the GUI is gone
+        let _ = self.updater.send(control);
+    }
+
+    /// Draw the cells in the colours of `automaton` (or those of the rule
+    /// again if it is `None`).
+    pub fn set_automaton(&mut self, automaton: Option<&dyn Automaton>,
+                         rule: &Rule) {
+        match automaton {
+            Some(automaton) => {
+                self.renderer.set_states(automaton.states());
+                self.renderer.set_palette(&automaton.palette());
+                self.renderer.set_shape(Shape::Moore);
+            }
+            None => {
+                self.renderer.set_states(rule.states());
+                self.renderer.set_palette(&[]);
+                self.renderer.set_shape(rule.neighbours().shape());
+            }
         }
     }
 
+    /// Centre the view on the cell at `origin` (see `Renderer::set_origin`).
+    pub fn set_origin(&mut self, origin: BigPoint) {
+        self.renderer.set_origin(origin);
+    }
+
     /// Runs the main GUI loop.
     ///
+    /// Escape closes the window, H switches between the naive engine and
+    /// Hashlife, 1 to 9 set the number of threads and Home centres the view
+    /// on (0, 0).
+    ///
     /// **Note:** Since we poll GLFW events in here, this _must_ be called from
     /// the main thread.
     pub fn run(mut self) {
         while !self.window.window.should_close() {
             let mut should_close = false;
+            if let Some(engine) = self.engines.try_iter().last() {
+                self.engine = engine;
+            }
+            let mut controls: Vec<Control> = self.controls.try_iter().collect();
             for ev in self.window.get_events() {
                 println!("{:?}", ev);
                 match ev {
@@ -230,6 +306,10 @@ impl GUI {
                             should_close = true;
                         }
                     }
+                    Event::Key(key, _, glfw::Action::Press, mods)
+                        if mods.is_empty() => {
+                        controls.extend(Self::key_control(key, self.engine));
+                    }
                     _ => {}
                 }
             }
@@ -237,15 +317,41 @@ impl GUI {
                 self.window.window.set_should_close(true);
                 break;
             }
+            for control in controls {
+                self.control(control);
+            }
             if let Ok(data) = self.data_recv.recv() {
                 self.renderer.draw(&*data);
             }
             self.window.window.swap_buffers();
         }
     }
+
+    /// Get the control bound to `key`, if any (see `run`).
+    fn key_control(key: glfw::Key, engine: Engine) -> Option<Control> {
+        let digit = key as i32 - glfw::Key::Num0 as i32;
+        match key {
+            glfw::Key::H => Some(Control::Engine(match engine {
+                Engine::Naive => Engine::HashLife {
+                    step_log2: 0,
+                    memory_limit: DEFAULT_MEMORY_LIMIT,
+                },
+                Engine::HashLife { .. } => Engine::Naive,
+            })),
+            _ if (1..=9).contains(&digit) => {
+                Some(Control::Threads(digit as usize))
+            }
+            glfw::Key::Home => {
+                Some(Control::Origin((BigInt::from(0), BigInt::from(0))))
+            }
+            _ => None,
+        }
+    }
 }
 
 mod shader;
 pub use self::shader::Shader;
 mod renderer;
 pub use self::renderer::Renderer;
+pub mod snapshot;
+pub mod recording;
@@ -0,0 +1,287 @@
+//! Recording runs into animated GIFs without a window.
+
+extern crate gif;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use self::gif::{Encoder, Frame, Repeat, SetParameter};
+use gui::snapshot::{ColourScheme, Snapshot};
+use backend::Coord;
+use backend::data::{AABB, QTree, State};
+use backend::updater::Updater;
+
+/// The default delay between frames (in hundredths of a second).
+pub const DEFAULT_DELAY: u16 = 10;
+
+/// The part of the universe a recording shows.
+#[derive(Debug, Clone)]
+pub enum Framing {
+    /// Always the same area.
+    Fixed(AABB),
+    /// An area that follows the pattern. It is centred on the cells of each
+    /// generation and large enough for all of them, with a margin of the
+    /// given number of cells.
+    Tracking(Coord),
+}
+
+/// Records generations into animated GIFs.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    framing: Framing,
+    cell_size: u32,
+    grid: bool,
+    colours: ColourScheme,
+    delay: u16,
+    repeat: bool,
+}
+
+impl Recording {
+    /// Create a recording of the area given by `framing`, with cells that
+    /// are `cell_size` pixels wide and high.
+    ///
+    /// By default, the recording has no grid, shows each generation for a
+    /// tenth of a second and loops forever.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is 0.
+    pub fn new(framing: Framing, cell_size: u32) -> Recording {
+        assert!(cell_size > 0, "Cells have to be at least one pixel wide");
+        Recording {
+            framing,
+            cell_size,
+            grid: false,
+            colours: ColourScheme::default(),
+            delay: DEFAULT_DELAY,
+            repeat: true,
+        }
+    }
+
+    /// Draw lines between the cells (or not, see `Snapshot::with_grid`).
+    pub fn with_grid(self, grid: bool) -> Recording {
+        Recording {
+            grid,
+            ..self
+        }
+    }
+
+    /// Draw the cells in `colours`.
+    pub fn with_colours(self, colours: ColourScheme) -> Recording {
+        Recording {
+            colours,
+            ..self
+        }
+    }
+
+    /// Show each generation for `delay` hundredths of a second.
+    pub fn with_delay(self, delay: u16) -> Recording {
+        Recording {
+            delay,
+            ..self
+        }
+    }
+
+    /// Loop the animation forever (or play it only once).
+    pub fn with_repeat(self, repeat: bool) -> Recording {
+        Recording {
+            repeat,
+            ..self
+        }
+    }
+
+    /// Record `generations` generations of `updater` (starting with the
+    /// current one) and write them to `writer` as an animated GIF.
+    ///
+    /// The updater is left at the generation after the last recorded one.
+    ///
+    /// # Errors
+    /// Fails if the frames are too large for a GIF, if writing fails, or if
+    /// the updater can't compute the next generation (see `Updater::step`).
+    pub fn record<W: Write>(&self, updater: &mut Updater, generations: usize,
+                            writer: W) -> io::Result<()> {
+        let mut recorded = Vec::with_capacity(generations);
+        for _ in 0..generations {
+            recorded.push(updater.current());
+            updater.step().map_err(io::Error::other)?;
+        }
+        self.write_gif(&recorded, writer)
+    }
+
+    /// Write `generations` to `writer` as an animated GIF, e.g. ones that
+    /// were received from a running `Updater`.
+    ///
+    /// # Errors
+    /// Fails if the frames are too large for a GIF, or if writing fails.
+    pub fn write_gif<W: Write>(&self, generations: &[Arc<QTree<State>>],
+                               writer: W) -> io::Result<()> {
+        let snapshots: Vec<Snapshot> = self.areas(generations).into_iter()
+            .map(|area| {
+                Snapshot::new(area, self.cell_size)
+                    .with_grid(self.grid)
+                    .with_colours(self.colours.clone())
+            })
+            .collect();
+        let size = snapshots.first().map_or(Ok(0), Snapshot::size)?;
+        if size > u16::MAX as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "frames are too large for a GIF"))
+        }
+
+        let size = size as u16;
+        let mut encoder = Encoder::new(writer, size, size, &[])?;
+        if self.repeat {
+            encoder.set(Repeat::Infinite)?;
+        }
+        for (generation, snapshot) in generations.iter().zip(&snapshots) {
+            let image = snapshot.render(generation)?;
+            let mut palette = vec![];
+            let mut indices = HashMap::new();
+            let mut buffer = Vec::with_capacity(image.len() / 3);
+            for pixel in image.pixels() {
+                let colours = indices.len();
+                let index = *indices.entry(pixel.data).or_insert(colours);
+                if index == colours {
+                    palette.extend_from_slice(&pixel.data);
+                }
+                if index > u8::MAX as usize {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "more than 256 colours"))
+                }
+                buffer.push(index as u8);
+            }
+            encoder.write_frame(&Frame {
+                delay: self.delay,
+                width: size,
+                height: size,
+                palette: Some(palette),
+                buffer: Cow::Owned(buffer),
+                ..Frame::default()
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Get the area to show for each of the `generations`.
+    fn areas(&self, generations: &[Arc<QTree<State>>]) -> Vec<AABB> {
+        let margin = match self.framing {
+            Framing::Fixed(ref area) => {
+                return vec![area.clone(); generations.len()]
+            }
+            Framing::Tracking(margin) => margin,
+        };
+        // The centre of each generation, and the half-dimension that is
+        // needed to show it
+        let bounds: Vec<(Coord, Coord, Coord)> = generations.iter()
+            .map(|generation| match generation.bounding_box() {
+                Some((min, max)) => {
+                    let half_width = (max.0 - min.0 + 2) / 2;
+                    let half_height = (max.1 - min.1 + 2) / 2;
+                    (min.0 + half_width, min.1 + half_height,
+                     half_width.max(half_height))
+                }
+                None => (0, 0, 0),
+            })
+            .collect();
+        let half_dim = bounds.iter().map(|&(_, _, half_dim)| half_dim)
+            .max().unwrap_or(0).saturating_add(margin).max(1);
+        bounds.iter()
+            .map(|&(x, y, _)| AABB::new((x, y), half_dim))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use self::gif::{ColorOutput, Decoder};
+    use backend::Point;
+    use backend::data::{ALIVE, CellMap};
+    use backend::rule::Rule;
+
+    fn start(points: &[Point]) -> Updater {
+        let data = QTree::new(AABB::new((0, 0), 8), &points.to_vec())
+            .map(|_, _| ALIVE);
+        let (data_send, _) = mpsc::channel();
+        Updater::new(Arc::new(data), Rule::conway(), data_send)
+    }
+
+    /// Decode a GIF into its frames (as RGBA pixels) and their delays.
+    fn decode(gif: &[u8]) -> (u16, Vec<(Vec<u8>, u16)>) {
+        let mut decoder = Decoder::new(gif);
+        decoder.set(ColorOutput::RGBA);
+        let mut reader = decoder.read_info().unwrap();
+        let width = reader.width();
+        let mut frames = vec![];
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (width, width));
+            frames.push((frame.buffer.to_vec(), frame.delay));
+        }
+        (width, frames)
+    }
+
+    fn rgba(snapshot: &Snapshot, cells: &QTree<State>) -> Vec<u8> {
+        snapshot.render(cells).unwrap().pixels()
+            .flat_map(|pixel| vec![pixel.data[0], pixel.data[1],
+                                   pixel.data[2], 255])
+            .collect()
+    }
+
+    #[test]
+    fn fixed() {
+        let area = AABB::new((0, 0), 2);
+        let recording = Recording::new(Framing::Fixed(area.clone()), 3)
+            .with_grid(true)
+            .with_delay(25);
+        let mut updater = start(&[(-1, 0), (0, 0), (1, 0)]);
+        let mut gif = vec![];
+        recording.record(&mut updater, 3, &mut gif).unwrap();
+        assert!(gif.windows(11).any(|w| w == b"NETSCAPE2.0"));
+
+        let (size, frames) = decode(&gif);
+        assert_eq!(size, 13);
+        assert_eq!(frames.len(), 3);
+        let snapshot = Snapshot::new(area, 3).with_grid(true);
+        let horizontal = start(&[(-1, 0), (0, 0), (1, 0)]).current();
+        let vertical = start(&[(0, -1), (0, 0), (0, 1)]).current();
+        assert_eq!(frames[0], (rgba(&snapshot, &horizontal), 25));
+        assert_eq!(frames[1], (rgba(&snapshot, &vertical), 25));
+        assert_eq!(frames[2], frames[0]);
+        // The updater continues after the recorded generations
+        assert_eq!(updater.current().points().count(), 3);
+        assert!(updater.current().get((0, 1)).is_some());
+    }
+
+    #[test]
+    fn tracking() {
+        let glider = [(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)];
+        let recording = Recording::new(Framing::Tracking(2), 1)
+            .with_repeat(false);
+        let mut gif = vec![];
+        recording.record(&mut start(&glider), 9, &mut gif).unwrap();
+        assert!(!gif.windows(11).any(|w| w == b"NETSCAPE2.0"));
+
+        // The glider is 3x3, plus the margin
+        let (size, frames) = decode(&gif);
+        assert_eq!(size, 2 * (2 + 2));
+        assert_eq!(frames.len(), 9);
+        // Every four generations, the glider is back in its original shape,
+        // which the view follows
+        assert_eq!(frames[4], frames[0]);
+        assert_eq!(frames[8], frames[0]);
+        assert!(frames[1] != frames[0]);
+    }
+
+    #[test]
+    fn too_large() {
+        let recording = Recording::new(Framing::Fixed(AABB::new((0, 0), 1024)),
+                                       64);
+        let mut gif = vec![];
+        let error = recording.record(&mut start(&[]), 1, &mut gif)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -1,18 +1,30 @@
 extern crate gl;
+extern crate num_traits;
 
-use std::ptr;
 use std::mem::size_of;
 use std::os::raw::c_void;
 use std::ffi::CString;
 
+use self::num_traits::{NumCast, ToPrimitive};
 use gui::shader::Shader;
-use backend::Point;
+use backend::{BigInt, BigPoint, Coord, Point};
+use backend::automaton::Colour;
+use backend::data::{AABB, CellMap, QTree, State};
+use backend::neighbours::Shape;
 
 const DEFAULT_WIDTH: f32 = 600.0;
 const DEFAULT_HEIGHT: f32 = 600.0;
 const DEFAULT_WIDTH_IN_CELLS: f32 = 20.0;
 const DEFAULT_HEIGHT_IN_CELLS: f32 = 20.0;
 
+/// Maximum number of colours in a palette (see `game.vert`)
+const MAX_PALETTE: usize = 16;
+
+/// Attributes of a vertex of the game shader (position and cell state)
+const GAME_ATTRIBUTES: [(&str, i32); 2] = [("pos", 2), ("state", 1)];
+/// Attributes of a vertex of the grid shader (position)
+const GRID_ATTRIBUTES: [(&str, i32); 1] = [("pos", 2)];
+
 /// Renderer that handles the actual rendering
 pub struct Renderer {
     game_shader: Shader,
@@ -22,6 +34,7 @@ pub struct Renderer {
     grid_vao: u32, grid_vbo: u32,
     grid_vertices: Vec<f32>,
     viewport: Viewport,
+    shape: Shape,
 }
 
 impl Renderer {
@@ -51,9 +64,12 @@ impl Renderer {
             grid_vao, grid_vbo,
             grid_vertices: vec![],
             viewport: Viewport::new(),
+            shape: Shape::Moore,
         };
 
         renderer.set_zoom(1.0);
+        renderer.set_states(2);
+        renderer.set_palette(&[]);
 
         renderer.game_shader.use_program();
         renderer.game_shader.bind_vbo(gl::ARRAY_BUFFER, 0);
@@ -65,28 +81,39 @@ impl Renderer {
         renderer
     }
 
-    fn setup_vao(shader: &mut Shader, location: &str) {
-        let location = shader.get_attrib_location(
-            &CString::new(location).unwrap()
-        ) as u32;
-        unsafe {
-            gl::VertexAttribPointer(
-                location,
-                2, gl::FLOAT, gl::FALSE, 2 * size_of::<f32>() as i32,
-                ptr::null()
-            );
-            gl::EnableVertexAttribArray(location);
+    /// Set up the vertex attributes for interleaved vertices consisting of
+    /// the given `attributes` (name and number of floats).
+    fn setup_vao(shader: &mut Shader, attributes: &[(&str, i32)]) {
+        let stride: i32 = attributes.iter().map(|&(_, size)| size).sum();
+        let mut offset = 0;
+        for &(name, size) in attributes {
+            let location = shader.get_attrib_location(
+                &CString::new(name).unwrap()
+            ) as u32;
+            unsafe {
+                gl::VertexAttribPointer(
+                    location,
+                    size, gl::FLOAT, gl::FALSE,
+                    stride * size_of::<f32>() as i32,
+                    (offset * size_of::<f32>()) as *const c_void
+                );
+                gl::EnableVertexAttribArray(location);
+            }
+            offset += size as usize;
         }
     }
 
+    fn vertex_size(attributes: &[(&str, i32)]) -> usize {
+        attributes.iter().map(|&(_, size)| size as usize).sum()
+    }
+
     fn update_grid(&mut self) {
         self.grid_shader.use_program();
 
-        let viewport = &self.viewport.viewport;
-        let min_x = (viewport.0).0;
-        let max_x = (viewport.1).0;
-        let min_y = (viewport.0).1;
-        let max_y = (viewport.1).1;
+        // The origin is always on a grid line, so the lines don't depend on
+        // where it is
+        let (max_x, max_y) = self.viewport.dim;
+        let (min_x, min_y) = (-max_x, -max_y);
         let x_dim = (max_x - min_x + 1) as usize;
         let y_dim = (max_y - min_y + 1) as usize;
         // 2 floats/vertex, 2 vertices per line, xy_dim lines per dimension
@@ -109,11 +136,11 @@ impl Renderer {
             gl::BufferData(
                 gl::ARRAY_BUFFER,
                 (self.grid_vertices.len() * size_of::<f32>()) as isize,
-                &self.grid_vertices[0] as *const f32 as *const c_void,
+                self.grid_vertices.as_ptr() as *const c_void,
                 gl::STATIC_DRAW
             );
         }
-        Self::setup_vao(&mut self.grid_shader, "pos");
+        Self::setup_vao(&mut self.grid_shader, &GRID_ATTRIBUTES);
     }
 
     /// Set the zoom level (the higher, the further out we zoom)
@@ -126,6 +153,53 @@ impl Renderer {
         self.update_grid();
     }
 
+    /// Centre the view on the cell at `origin`.
+    ///
+    /// Cells are drawn relative to the origin, so they stay in place however
+    /// far away from `(0, 0)` they are. The origin may even be outside of the
+    /// range of `Coord`.
+    pub fn set_origin(&mut self, origin: BigPoint) {
+        self.viewport.set_origin(origin);
+    }
+
+    /// Get the cell the view is centred on.
+    pub fn origin(&self) -> &BigPoint {
+        &self.viewport.origin
+    }
+
+    /// Set the number of cell states of the current rule.
+    ///
+    /// Live cells are always drawn in the same colour, dying cells (in rules
+    /// with more than two states) fade out the older they get.
+    pub fn set_states(&mut self, states: State) {
+        self.game_shader.use_program();
+        self.game_shader.set_i32(&CString::new("states").unwrap(),
+                                 states as i32);
+    }
+
+    /// Set the colours of the cell states of the current automaton.
+    ///
+    /// An empty palette switches back to the colours of Life-like rules.
+    /// Only the first 16 colours are used.
+    pub fn set_palette(&mut self, palette: &[Colour]) {
+        self.game_shader.use_program();
+        let palette = &palette[..palette.len().min(MAX_PALETTE)];
+        for (i, &colour) in palette.iter().enumerate() {
+            let name = CString::new(format!("palette[{}]", i)).unwrap();
+            self.game_shader.set_f32_v3(&name, colour);
+        }
+        self.game_shader.set_i32(&CString::new("palette_size").unwrap(),
+                                 palette.len() as i32);
+    }
+
+    /// Set the shape of the neighbourhood of the current rule.
+    ///
+    /// Cells are drawn as hexagons or triangles for hexagonal and triangular
+    /// neighbourhoods, respectively. The grid is only drawn for square cells.
+    pub fn set_shape(&mut self, shape: Shape) {
+        self.shape = shape;
+    }
+
     /// Update the OpenGL viewport and FOV
     pub fn set_viewport(&mut self, width: i32, height: i32) {
         unsafe {
@@ -147,22 +221,20 @@ impl Renderer {
         self.update_grid();
     }
 
-    fn make_game_vertices<T: IntoIterator<Item=Point>>(&mut self, data: T) {
+    fn make_game_vertices(&mut self, data: &QTree<State>) {
         self.game_shader.use_program();
         self.game_vertices.clear();
-        {
-            let mut push_point = |x: f32, y: f32| {
+        let area = self.viewport.area(self.shape);
+        for cell in area.map_or(vec![], |area| data.query(&area)) {
+            let state = match data.get(cell) {
+                Some(&state) => state,
+                None => continue,
+            };
+            let position = self.viewport.position(cell);
+            for (x, y) in cell_triangles(self.shape, cell, position) {
                 self.game_vertices.push(x);
                 self.game_vertices.push(y);
-            };
-            for cell in data {
-                let (x, y) = (cell.0 as f32, cell.1 as f32);
-                push_point(x - 1.0, y - 1.0);
-                push_point(x - 1.0, y);
-                push_point(x, y - 1.0);
-                push_point(x - 1.0, y);
-                push_point(x, y - 1.0);
-                push_point(x, y);
+                self.game_vertices.push(state as f32);
             }
         }
         self.game_shader.bind_vao(self.game_vao);
@@ -171,25 +243,72 @@ impl Renderer {
             gl::BufferData(
                 gl::ARRAY_BUFFER,
                 (self.game_vertices.len() * size_of::<f32>()) as isize,
-                &self.game_vertices[0] as *const f32 as *const c_void,
+                self.game_vertices.as_ptr() as *const c_void,
                 gl::STATIC_DRAW
             );
         }
-        Self::setup_vao(&mut self.game_shader, "pos");
+        Self::setup_vao(&mut self.game_shader, &GAME_ATTRIBUTES);
     }
 
     /// Actually draw to the buffer
-    pub fn draw<T: IntoIterator<Item=Point>>(&mut self, data: T) {
+    pub fn draw(&mut self, data: &QTree<State>) {
         self.make_game_vertices(data);
         unsafe {
             gl::ClearColor(0.2, 0.2, 0.2, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
             self.game_shader.use_program();
             self.game_shader.bind_vao(self.game_vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, (self.game_vertices.len() / 2) as i32);
+            let vertex_size = Self::vertex_size(&GAME_ATTRIBUTES);
+            let count = self.game_vertices.len() / vertex_size;
+            gl::DrawArrays(gl::TRIANGLES, 0, count as i32);
+            if self.shape == Shape::Hexagonal ||
+                self.shape == Shape::Triangular {
+                return
+            }
             self.grid_shader.use_program();
             self.grid_shader.bind_vao(self.grid_vao);
-            gl::DrawArrays(gl::LINES, 0, (self.grid_vertices.len() / 2) as i32);
+            let vertex_size = Self::vertex_size(&GRID_ATTRIBUTES);
+            let count = self.grid_vertices.len() / vertex_size;
+            gl::DrawArrays(gl::LINES, 0, count as i32);
+        }
+    }
+}
+
+/// Get the vertices of the triangles that make up a `cell`, which is drawn at
+/// `position` (relative to the origin of the view).
+///
+/// Square cells cover the unit square to the bottom left of their
+/// coordinates. Hexagons are shifted by half a cell per row, so that the
+/// skewed hexagonal neighbourhood lines up. Triangles are half a cell wide
+/// and point up or down depending on their position.
+fn cell_triangles(shape: Shape, cell: Point, position: (f32, f32))
+                  -> Vec<(f32, f32)> {
+    let (x, y) = position;
+    match shape {
+        Shape::Moore | Shape::VonNeumann => vec![
+            (x - 1.0, y - 1.0), (x - 1.0, y), (x, y - 1.0),
+            (x - 1.0, y), (x, y - 1.0), (x, y),
+        ],
+        Shape::Hexagonal => {
+            let centre = (x - 0.5 + 0.5 * y, y - 0.5);
+            let corners = [
+                (0.0, 2.0 / 3.0), (0.5, 1.0 / 3.0), (0.5, -1.0 / 3.0),
+                (0.0, -2.0 / 3.0), (-0.5, -1.0 / 3.0), (-0.5, 1.0 / 3.0),
+            ];
+            let mut vertices = Vec::with_capacity(18);
+            for i in 0..6 {
+                let (a, b) = (corners[i], corners[(i + 1) % 6]);
+                vertices.push(centre);
+                vertices.push((centre.0 + a.0, centre.1 + a.1));
+                vertices.push((centre.0 + b.0, centre.1 + b.1));
+            }
+            vertices
+        }
+        Shape::Triangular => {
+            let x = 0.5 * x;
+            let up = (cell.0 + cell.1) % 2 == 0;
+            let (base, tip) = if up { (y - 1.0, y) } else { (y, y - 1.0) };
+            vec![(x - 0.5, base), (x + 0.5, base), (x, tip)]
         }
     }
 }
@@ -197,9 +316,14 @@ impl Renderer {
 struct Viewport {
     window_size: (f32, f32),
     zoom: f32,
-    world_center: Point,
-    /// Viewport _in world coordinates_
-    pub viewport: (Point, Point),
+    /// The cell in the centre of the window
+    pub origin: BigPoint,
+    /// The origin, clamped to the range of `Coord`
+    anchor: Point,
+    /// The distance from the origin to the anchor
+    offset: (f32, f32),
+    /// The number of cells between the origin and the edges of the window
+    pub dim: (i32, i32),
 }
 
 impl Viewport {
@@ -207,11 +331,64 @@ impl Viewport {
         Viewport {
             window_size: (0.0, 0.0),
             zoom: 1.0,
-            world_center: (0, 0),
-            viewport: ((0, 0), (0, 0)),
+            origin: (BigInt::from(0), BigInt::from(0)),
+            anchor: (0, 0),
+            offset: (0.0, 0.0),
+            dim: (0, 0),
         }
     }
 
+    pub fn set_origin(&mut self, origin: BigPoint) {
+        let clamp = |value: &BigInt| coord(value).unwrap_or_else(|| {
+            if *value < BigInt::from(0) {
+                Coord::MIN
+            } else {
+                Coord::MAX
+            }
+        });
+        self.anchor = (clamp(&origin.0), clamp(&origin.1));
+        // Only used if cells are visible, in which case the anchor is close
+        let offset = |value: &BigInt, anchor: Coord| {
+            (BigInt::from(anchor) - value).to_f32().unwrap_or(0.0)
+        };
+        self.offset = (offset(&origin.0, self.anchor.0),
+                       offset(&origin.1, self.anchor.1));
+        self.origin = origin;
+    }
+
+    /// Get the part of the world in which cells of the given `shape` are
+    /// visible, or `None` if all of it is outside of the range of `Coord`.
+    pub fn area(&self, shape: Shape) -> Option<AABB> {
+        // Bounding boxes can't quite reach the limits of `Coord`
+        let (lowest, highest) = (Coord::MIN + 2, Coord::MAX - 2);
+        // Triangles are half a cell wide, and hexagons are shifted by half a
+        // cell per row (see `cell_triangles`)
+        let width = match shape {
+            Shape::Moore | Shape::VonNeumann => self.dim.0,
+            Shape::Hexagonal => self.dim.0 + (self.dim.1 + 1) / 2,
+            Shape::Triangular => 2 * self.dim.0,
+        };
+        // Cells cover the unit square to the bottom left of their coordinates
+        let range = |origin: &BigInt, dim: i32| {
+            let min = origin - BigInt::from(dim);
+            let max = origin + BigInt::from(dim + 1);
+            if max < BigInt::from(lowest) || min > BigInt::from(highest) {
+                return None
+            }
+            Some((coord(&min).map_or(lowest, |min| min.max(lowest)),
+                  coord(&max).map_or(highest, |max| max.min(highest))))
+        };
+        let x = range(&self.origin.0, width)?;
+        let y = range(&self.origin.1, self.dim.1)?;
+        Some(AABB::between((x.0, y.0), (x.1, y.1)))
+    }
+
+    /// Get the position of a visible `cell` relative to the origin.
+    pub fn position(&self, cell: Point) -> (f32, f32) {
+        ((cell.0 - self.anchor.0) as f32 + self.offset.0,
+         (cell.1 - self.anchor.1) as f32 + self.offset.1)
+    }
+
     pub fn set_zoom(&mut self, zoom: f32) {
         self.zoom = zoom;
         self.update();
@@ -225,10 +402,89 @@ impl Viewport {
     fn update(&mut self) {
         let dim = ((self.window_size.0 * DEFAULT_WIDTH_IN_CELLS / DEFAULT_WIDTH / self.zoom).ceil(),
                    (self.window_size.1 * DEFAULT_HEIGHT_IN_CELLS / DEFAULT_HEIGHT / self.zoom).ceil());
-        let dim = (dim.0 as i32, dim.1 as i32);
-        self.viewport = (
-            (self.world_center.0 - dim.0, self.world_center.1 - dim.1),
-            (self.world_center.0 + dim.0, self.world_center.1 + dim.1),
-        );
+        self.dim = (dim.0 as i32, dim.1 as i32);
+    }
+}
+
+/// Convert `value` into a `Coord`, if it fits.
+fn coord(value: &BigInt) -> Option<Coord> {
+    NumCast::from(value.clone())
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+
+    fn view(origin: (BigInt, BigInt), dim: i32) -> Viewport {
+        let mut viewport = Viewport::new();
+        viewport.set_origin(origin);
+        viewport.dim = (dim, dim);
+        viewport
+    }
+
+    #[test]
+    fn centred() {
+        let viewport = view((BigInt::from(0), BigInt::from(0)), 2);
+        let area = viewport.area(Shape::Moore).unwrap();
+        for &cell in &[(-2, -2), (3, 3), (0, 0), (-2, 3)] {
+            assert!(area.contains(cell));
+        }
+        assert_eq!(viewport.position((3, -2)), (3.0, -2.0));
+    }
+
+    #[test]
+    fn shapes() {
+        let viewport = view((BigInt::from(0), BigInt::from(0)), 4);
+        let square = viewport.area(Shape::Moore).unwrap();
+        assert!(square.contains((4, -4)));
+        assert!(!square.contains((6, -4)));
+
+        // Triangles are half as wide, so twice as many fit into a row
+        let triangles = viewport.area(Shape::Triangular).unwrap();
+        for &cell in &[(8, 0), (-8, 4), (0, 5)] {
+            assert!(triangles.contains(cell));
+        }
+        assert!(cell_triangles(Shape::Triangular, (8, 0),
+                               viewport.position((8, 0)))
+                .iter().all(|&(x, _)| x.abs() <= 4.5));
+
+        // Hexagons in the corners are shifted into view
+        let hexagons = viewport.area(Shape::Hexagonal).unwrap();
+        for &cell in &[(6, -4), (-5, 4)] {
+            assert!(!square.contains(cell));
+            assert!(hexagons.contains(cell));
+            assert!(cell_triangles(Shape::Hexagonal, cell,
+                                   viewport.position(cell))
+                    .iter().any(|&(x, _)| x.abs() < 4.0));
+        }
+    }
+
+    #[test]
+    fn far_away() {
+        // Absolute positions this far out can't be represented by an `f32`
+        let far = Coord::MAX - 100;
+        let viewport = view((BigInt::from(far), BigInt::from(-far)), 4);
+        let area = viewport.area(Shape::Moore).unwrap();
+        assert!(area.contains((far - 3, -far + 1)));
+        assert!(!area.contains((0, 0)));
+        assert_eq!(viewport.position((far - 3, -far + 1)), (-3.0, 1.0));
+        assert_eq!(viewport.position((far + 1, -far)), (1.0, 0.0));
+    }
+
+    #[test]
+    fn outside_of_coordinates() {
+        // Just beyond the largest coordinate, some cells are still visible
+        let beyond = BigInt::from(Coord::MAX) + BigInt::from(1);
+        let viewport = view((beyond.clone(), BigInt::from(0)), 4);
+        let area = viewport.area(Shape::Moore).unwrap();
+        let cell = (Coord::MAX - 3, 0);
+        assert!(area.contains(cell));
+        assert_eq!(viewport.position(cell), (-4.0, 0.0));
+
+        // Further out, nothing is
+        let beyond = beyond * BigInt::from(1000);
+        let viewport = view((BigInt::from(0), -beyond), 4);
+        assert!(viewport.area(Shape::Moore).is_none());
     }
 }
@@ -118,6 +118,14 @@ impl Shader {
             gl::Uniform1f(location, value);
         }
     }
+    /// Bind a uniform int
+    /// *Note:* The program has to be active before this is called
+    pub fn set_i32(&mut self, name: &CStr, value: i32) {
+        unsafe {
+            let location = gl::GetUniformLocation(self.id, name.as_ptr());
+            gl::Uniform1i(location, value);
+        }
+    }
     /// Bind a uniform ivec2
     /// *Note:* The program has to be active before this is called
     pub fn set_i32_v2(&mut self, name: &CStr, value: (i32, i32)) {
@@ -127,6 +135,15 @@ impl Shader {
         }
     }
 
+    /// Bind a uniform vec3
+    /// *Note:* The program has to be active before this is called
+    pub fn set_f32_v3(&mut self, name: &CStr, value: [f32; 3]) {
+        unsafe {
+            let location = gl::GetUniformLocation(self.id, name.as_ptr());
+            gl::Uniform3f(location, value[0], value[1], value[2]);
+        }
+    }
+
     /// Get a uniform float
     pub fn get_uniform_f32(&mut self, name: &CStr) -> f32 {
         unsafe {
@@ -0,0 +1,346 @@
+//! Rendering patterns into images without a window, e.g. for thumbnails.
+//!
+//! Unlike the `Renderer`, this doesn't need OpenGL or GLFW, so it also works
+//! on machines without a display.
+
+extern crate image;
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::fs::File;
+use std::path::Path;
+
+use self::image::{ColorType, Rgb, RgbImage};
+use self::image::png::PNGEncoder;
+use backend::{Coord, Point};
+use backend::automaton::{Automaton, Colour};
+use backend::data::{AABB, CellMap, QTree, State};
+
+// The same colours as in the shaders
+const ALIVE_COLOUR: Colour = [0.85, 0.85, 0.85];
+const DYING_COLOUR: Colour = [0.85, 0.45, 0.15];
+const BACKGROUND_COLOUR: Colour = [0.2, 0.2, 0.2];
+const GRID_COLOUR: Colour = [0.3, 0.3, 0.3];
+
+/// The colours a snapshot is drawn in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColourScheme {
+    /// The colour of dead cells.
+    pub background: Colour,
+    /// The colour of the grid lines.
+    pub grid: Colour,
+    /// The colours of the cell states. States without a colour of their own
+    /// use the last one.
+    pub palette: Vec<Colour>,
+}
+
+impl ColourScheme {
+    /// The colours the window uses for a rule with the given number of
+    /// `states`.
+    ///
+    /// Dying cells (in rules with more than two states) fade out the older
+    /// they get.
+    pub fn rule(states: State) -> ColourScheme {
+        let dying = states.max(3) as f32 - 2.0;
+        let palette = (0..states.max(2)).map(|state| match state {
+            0 => BACKGROUND_COLOUR,
+            1 => ALIVE_COLOUR,
+            _ => {
+                let age = (state as f32 - 2.0) / dying * 0.75;
+                let mut colour = DYING_COLOUR;
+                for (c, background) in colour.iter_mut()
+                    .zip(BACKGROUND_COLOUR.iter()) {
+                    *c += (background - *c) * age;
+                }
+                colour
+            }
+        }).collect();
+        ColourScheme {
+            background: BACKGROUND_COLOUR,
+            grid: GRID_COLOUR,
+            palette,
+        }
+    }
+
+    /// The colours the window uses for `automaton`.
+    pub fn automaton(automaton: &dyn Automaton) -> ColourScheme {
+        ColourScheme {
+            palette: automaton.palette(),
+            ..ColourScheme::default()
+        }
+    }
+
+    /// Get the colour of cells in `state`.
+    pub fn colour(&self, state: State) -> Colour {
+        match self.palette.get(state as usize).or_else(|| self.palette.last()) {
+            Some(&colour) if state != 0 => colour,
+            _ => self.background,
+        }
+    }
+}
+
+impl Default for ColourScheme {
+    fn default() -> ColourScheme {
+        ColourScheme::rule(2)
+    }
+}
+
+/// Renders a square region of the universe into an image.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    area: AABB,
+    cell_size: u32,
+    grid: bool,
+    colours: ColourScheme,
+}
+
+impl Snapshot {
+    /// Create a snapshot of `area`, with cells that are `cell_size` pixels
+    /// wide and high.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is 0.
+    pub fn new(area: AABB, cell_size: u32) -> Snapshot {
+        assert!(cell_size > 0, "Cells have to be at least one pixel wide");
+        Snapshot {
+            area,
+            cell_size,
+            grid: false,
+            colours: ColourScheme::default(),
+        }
+    }
+
+    /// Draw lines between the cells (or not).
+    ///
+    /// The lines take up the first row and column of pixels of each cell,
+    /// and one more row and column at the bottom and right edges. They are
+    /// left out if cells are only one pixel wide.
+    pub fn with_grid(self, grid: bool) -> Snapshot {
+        Snapshot {
+            grid,
+            ..self
+        }
+    }
+
+    /// Draw the cells in `colours`.
+    pub fn with_colours(self, colours: ColourScheme) -> Snapshot {
+        Snapshot {
+            colours,
+            ..self
+        }
+    }
+
+    /// Get the width and height of the image in pixels.
+    ///
+    /// # Errors
+    /// Fails if the image would be more than `u32::MAX` pixels wide.
+    pub fn size(&self) -> io::Result<u32> {
+        u32::try_from(self.area.half_dim().max(0)).ok()
+            .and_then(|half_dim| half_dim.checked_mul(2))
+            .and_then(|cells| cells.checked_mul(self.cell_size))
+            .and_then(|size| size.checked_add(self.has_grid() as u32))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                                          "the snapshot is too large"))
+    }
+
+    /// Draw the `cells` into an image.
+    ///
+    /// # Errors
+    /// Fails if the image would be too large (see `size`).
+    pub fn render(&self, cells: &QTree<State>) -> io::Result<RgbImage> {
+        let grid = self.has_grid();
+        let background = if grid {
+            self.colours.grid
+        } else {
+            self.colours.background
+        };
+        let size = self.size()?;
+        let mut image = RgbImage::from_pixel(size, size, pixel(background));
+
+        let (left, top) = self.corner();
+        let cell = |image: &mut RgbImage, x: Coord, y: Coord, colour| {
+            let border = grid as u32;
+            // Both fit into the image, but not necessarily into a `Coord`
+            let x0 = (i128::from(x) - i128::from(left)) as u32 *
+                self.cell_size + border;
+            let y0 = (i128::from(top) - i128::from(y)) as u32 *
+                self.cell_size + border;
+            for py in y0..y0 + self.cell_size - border {
+                for px in x0..x0 + self.cell_size - border {
+                    image.put_pixel(px, py, pixel(colour));
+                }
+            }
+        };
+        if grid {
+            let cells = 2 * self.area.half_dim();
+            for y in 0..cells {
+                for x in 0..cells {
+                    cell(&mut image, left + x, top - y,
+                         self.colours.background);
+                }
+            }
+        }
+        for (x, y) in cells.query(&self.area) {
+            if let Some(&state) = cells.get((x, y)) {
+                cell(&mut image, x, y, self.colours.colour(state));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Draw the `cells` and write them to `writer` as a PNG image.
+    ///
+    /// # Errors
+    /// Fails if the image would be too large, or if writing fails.
+    pub fn write_png<W: Write>(&self, cells: &QTree<State>, writer: W)
+                               -> io::Result<()> {
+        let image = self.render(cells)?;
+        PNGEncoder::new(writer).encode(&image, image.width(), image.height(),
+                                       ColorType::RGB(8))
+    }
+
+    /// Draw the `cells` and save them as a PNG file at `path`.
+    ///
+    /// # Errors
+    /// Fails if the image would be too large, or if the file can't be
+    /// created or written.
+    pub fn save_png<P: AsRef<Path>>(&self, cells: &QTree<State>, path: P)
+                                    -> io::Result<()> {
+        // Don't leave an empty file behind if the image is too large
+        let _ = self.size()?;
+        self.write_png(cells, File::create(path)?)
+    }
+
+    fn has_grid(&self) -> bool {
+        self.grid && self.cell_size > 1
+    }
+
+    /// Get the coordinates of the top left cell.
+    fn corner(&self) -> Point {
+        let (x, y) = self.area.center();
+        let half_dim = self.area.half_dim();
+        (x - half_dim, y + half_dim - 1)
+    }
+}
+
+/// Convert a `colour` into a pixel.
+fn pixel(colour: Colour) -> Rgb<u8> {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgb([channel(colour[0]), channel(colour[1]), channel(colour[2])])
+}
+
+#[cfg(test)]
+#[allow(unused_results)]
+mod tests {
+    use super::*;
+    use backend::automaton::Wireworld;
+    use backend::data::ALIVE;
+
+    fn glider() -> QTree<State> {
+        QTree::new(AABB::new((0, 0), 4),
+                   &vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)])
+            .map(|_, _| ALIVE)
+    }
+
+    #[test]
+    fn render() {
+        let snapshot = Snapshot::new(AABB::new((0, 0), 4), 2);
+        let image = snapshot.render(&glider()).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+        let alive = pixel(ALIVE_COLOUR);
+        let background = pixel(BACKGROUND_COLOUR);
+        // (0, 0) is the cell right of and above the centre
+        assert_eq!(*image.get_pixel(8, 6), alive);
+        assert_eq!(*image.get_pixel(9, 7), alive);
+        // (1, 2) is two cells further up
+        assert_eq!(*image.get_pixel(10, 2), alive);
+        assert_eq!(*image.get_pixel(8, 2), background);
+        let live = image.pixels().filter(|&&p| p == alive).count();
+        assert_eq!(live, 5 * 4);
+
+        // Cells outside of the area are left out
+        let snapshot = Snapshot::new(AABB::new((1, 1), 1), 1);
+        let image = snapshot.render(&glider()).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image.pixels().filter(|&&p| p == alive).count(), 3);
+        assert_eq!(*image.get_pixel(1, 0), background);
+    }
+
+    #[test]
+    fn grid() {
+        let snapshot = Snapshot::new(AABB::new((0, 0), 2), 4).with_grid(true);
+        let image = snapshot.render(&glider()).unwrap();
+        assert_eq!(image.dimensions(), (17, 17));
+        let grid = pixel(GRID_COLOUR);
+        let alive = pixel(ALIVE_COLOUR);
+        for i in 0..17 {
+            for line in (0..5).map(|line| line * 4) {
+                assert_eq!(*image.get_pixel(line, i), grid);
+                assert_eq!(*image.get_pixel(i, line), grid);
+            }
+        }
+        // (0, 0) is the third cell from the left and the second from the top
+        for i in 9..12 {
+            assert_eq!(*image.get_pixel(i, 5), alive);
+        }
+        assert_eq!(*image.get_pixel(5, 5), pixel(BACKGROUND_COLOUR));
+
+        // Without space for the lines, they are left out
+        let snapshot = Snapshot::new(AABB::new((0, 0), 2), 1).with_grid(true);
+        assert_eq!(snapshot.render(&glider()).unwrap().dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn colours() {
+        let colours = ColourScheme::rule(4);
+        assert_eq!(colours.colour(0), BACKGROUND_COLOUR);
+        assert_eq!(colours.colour(1), ALIVE_COLOUR);
+        assert_eq!(colours.colour(2), DYING_COLOUR);
+        // Like in the shader, only the states of the rule count towards the age
+        let oldest = colours.colour(3);
+        for i in 0..3 {
+            let expected = DYING_COLOUR[i] +
+                (BACKGROUND_COLOUR[i] - DYING_COLOUR[i]) * 0.375;
+            assert!((oldest[i] - expected).abs() < 1e-6);
+        }
+        assert_eq!(colours.colour(200), oldest);
+
+        let colours = ColourScheme::automaton(&Wireworld);
+        let cells = QTree::new(AABB::new((0, 0), 1), &vec![(0, 0)])
+            .map(|_, _| Wireworld::HEAD);
+        let image = Snapshot::new(AABB::new((0, 0), 1), 1)
+            .with_colours(colours.clone())
+            .render(&cells).unwrap();
+        assert_eq!(*image.get_pixel(1, 0),
+                   pixel(colours.colour(Wireworld::HEAD)));
+        assert_eq!(*image.get_pixel(0, 0), pixel(BACKGROUND_COLOUR));
+    }
+
+    #[test]
+    fn png() {
+        let snapshot = Snapshot::new(AABB::new((0, 0), 4), 3).with_grid(true);
+        let mut png = vec![];
+        snapshot.write_png(&glider(), &mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let decoded = image::load_from_memory(&png).unwrap().to_rgb();
+        let image = snapshot.render(&glider()).unwrap();
+        assert_eq!(decoded.into_raw(), image.into_raw());
+    }
+
+    #[test]
+    fn too_large() {
+        // 2^16 cells of 2^16 pixels
+        let snapshot = Snapshot::new(AABB::new((0, 0), 1 << 15), 1 << 16);
+        assert_eq!(snapshot.size().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert!(snapshot.render(&glider()).is_err());
+        assert!(snapshot.write_png(&glider(), vec![]).is_err());
+        let snapshot = Snapshot::new(AABB::new((0, 0), Coord::MAX), 2);
+        assert!(snapshot.size().is_err());
+
+        // Just small enough, including the line at the bottom and right
+        let snapshot = Snapshot::new(AABB::new((0, 0), 1), u32::MAX / 2)
+            .with_grid(true);
+        assert_eq!(snapshot.size().unwrap(), u32::MAX);
+    }
+}
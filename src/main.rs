@@ -1,7 +1,69 @@
 extern crate conway;
 use conway::*;
 
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::process;
+
+use backend::data::{AABB, QTree, State};
+use backend::pattern;
+use backend::pattern::bitmap::{self, BitmapImport};
+use backend::rule::Rule;
+use gui::snapshot::{ColourScheme, Snapshot};
+
+/// The width and height of cells in snapshots, in pixels.
+const SNAPSHOT_CELL_SIZE: u32 = 8;
+
 fn main() {
-    let controller = backend::Controller::new();
-    controller.run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let pattern = args.first().map(|path| {
+        load(path).unwrap_or_else(|error| {
+            eprintln!("Couldn't load {}: {}", path, error);
+            process::exit(1);
+        })
+    });
+    match (pattern, args.get(1).map(String::as_str), args.get(2)) {
+        (Some((data, rule)), Some("--png"), Some(output)) => {
+            if let Err(error) = snapshot(&data, &rule, output) {
+                eprintln!("Couldn't write {}: {}", output, error);
+                process::exit(1);
+            }
+        }
+        (Some((data, rule)), None, _) => {
+            backend::Controller::with_pattern(data, rule).run();
+        }
+        (None, _, _) => backend::Controller::new().run(),
+        _ => {
+            eprintln!("Usage: conway-bin [PATTERN [--png OUTPUT]]");
+            process::exit(2);
+        }
+    }
+}
+
+/// Read the pattern in a file in any supported format (or an image), and the
+/// rule it is meant to be run with.
+fn load(path: &str) -> Result<(QTree<State>, Rule), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    if bitmap::is_image(&bytes) {
+        return Ok((BitmapImport::new().load(&bytes)?, Rule::default()))
+    }
+    let pattern = pattern::parse(&String::from_utf8(bytes)?)?;
+    let rule = match pattern.metadata.rule {
+        Some(ref rule) => rule.parse()?,
+        None => Rule::default(),
+    };
+    Ok((pattern.cells, rule))
+}
+
+/// Render the pattern `data` (with a margin of one cell) into a PNG file at
+/// `output`, without opening a window.
+fn snapshot(data: &QTree<State>, rule: &Rule, output: &str)
+            -> io::Result<()> {
+    let (min, max) = data.bounding_box().unwrap_or(((0, 0), (0, 0)));
+    Snapshot::new(AABB::between(min, max).grow(1), SNAPSHOT_CELL_SIZE)
+        .with_grid(true)
+        .with_colours(ColourScheme::rule(rule.states()))
+        .save_png(data, output)
 }